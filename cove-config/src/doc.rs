@@ -232,6 +232,15 @@ impl Document for bool {
     }
 }
 
+impl Document for usize {
+    fn doc() -> Doc {
+        let mut doc = Doc::default();
+        doc.value_info.required = Some(true);
+        doc.value_info.r#type = Some("integer".to_string());
+        doc
+    }
+}
+
 impl Document for PathBuf {
     fn doc() -> Doc {
         let mut doc = Doc::default();
@@ -258,6 +267,15 @@ impl<I: Document> Document for HashMap<String, I> {
     }
 }
 
+impl<I: Document> Document for Vec<I> {
+    fn doc() -> Doc {
+        let mut doc = Doc::default();
+        doc.wrap_info.inner = Some(Box::new(I::doc()));
+        doc.wrap_info.metavar = Some("index".to_string());
+        doc
+    }
+}
+
 impl Document for KeyBinding {
     fn doc() -> Doc {
         let mut doc = Doc::default();
@@ -1,8 +1,13 @@
 use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::Arc;
 
-use serde::{Deserialize, Serialize};
+use cove_input::KeyBinding;
+use crossterm::style::Color;
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize};
 
-use crate::doc::Document;
+use crate::doc::{Doc, Document};
 
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Document)]
 #[serde(rename_all = "snake_case")]
@@ -12,6 +17,152 @@ pub enum RoomsSortOrder {
     Importance,
 }
 
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Document)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenRoomCursor {
+    Newest,
+    #[default]
+    OldestUnseen,
+    LastRead,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Document)]
+#[serde(rename_all = "snake_case")]
+pub enum NickListSummary {
+    #[default]
+    Compact,
+    Verbose,
+    Hidden,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Document)]
+#[serde(rename_all = "snake_case")]
+pub enum NickListSort {
+    #[default]
+    Type,
+    Alphabet,
+    Joined,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Document)]
+#[serde(rename_all = "snake_case")]
+pub enum EmojiMode {
+    Raw,
+    Shortcode,
+    #[default]
+    Unicode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Document)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyLevel {
+    #[default]
+    All,
+    Mentions,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Document)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeFormat {
+    #[default]
+    Full,
+    Smart,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Document)]
+pub struct HighlightStyle {
+    /// Text color, either a hex code like `"#ff8800"` or a color name like
+    /// `"yellow"`.
+    pub fg: Option<String>,
+
+    /// Background color, in the same format as `fg`.
+    pub bg: Option<String>,
+
+    #[serde(default)]
+    pub bold: bool,
+
+    #[serde(default)]
+    pub italic: bool,
+
+    #[serde(default)]
+    pub underlined: bool,
+}
+
+/// A set of styles used throughout the UI.
+///
+/// Colors are either a hex code like `"#ff8800"` or a color name like
+/// `"yellow"`. Unset colors are taken from the `base` theme.
+#[derive(Debug, Clone, Default, Deserialize, Document)]
+pub struct Theme {
+    /// Built-in theme this theme is based on, either `"dark"` or `"light"`.
+    #[document(default = "`\"dark\"`")]
+    pub base: Option<String>,
+
+    /// Color of message contents.
+    pub text: Option<String>,
+
+    /// Saturation of the colors nicks are hashed to, in percent.
+    pub nick_saturation: Option<usize>,
+
+    /// Lightness of the colors nicks are hashed to, in percent.
+    pub nick_lightness: Option<usize>,
+
+    /// Text color of the message under the cursor.
+    pub cursor_fg: Option<String>,
+
+    /// Background color of the message under the cursor.
+    pub cursor_bg: Option<String>,
+
+    /// Color of popup borders.
+    pub border: Option<String>,
+
+    /// Color of unseen message counts in the room list.
+    pub unseen: Option<String>,
+}
+
+/// A regular expression that is compiled once when the config is loaded and
+/// can then be cheaply cloned.
+#[derive(Debug, Clone)]
+pub struct Pattern(Arc<Regex>);
+
+impl Deref for Pattern {
+    type Target = Regex;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        match Regex::new(&pattern) {
+            Ok(regex) => Ok(Self(Arc::new(regex))),
+            Err(err) => Err(de::Error::custom(format!(
+                "invalid pattern {pattern:?}: {err}"
+            ))),
+        }
+    }
+}
+
+impl Document for Pattern {
+    fn doc() -> Doc {
+        String::doc()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Document)]
+pub struct HighlightRule {
+    /// Regular expression matching the text to highlight.
+    pub pattern: Pattern,
+
+    /// Style of the matched text.
+    #[serde(default)]
+    #[document(no_default)]
+    pub style: HighlightStyle,
+}
+
 // TODO Mark favourite rooms via printable ascii characters
 #[derive(Debug, Clone, Default, Deserialize, Document)]
 pub struct EuphRoom {
@@ -21,27 +172,159 @@ pub struct EuphRoom {
 
     /// If set, cove will set this username upon joining if there is no username
     /// associated with the current session.
+    ///
+    /// Overrides `euph.servers.<domain>.username`.
     pub username: Option<String>,
 
     /// If `euph.rooms.<room>.username` is set, this will force cove to set the
     /// username even if there is already a different username associated with
     /// the current session.
-    #[serde(default)]
-    pub force_username: bool,
+    ///
+    /// Overrides `euph.servers.<domain>.force_username`.
+    #[document(default = "`false`")]
+    pub force_username: Option<bool>,
 
-    /// If set, cove will try once to use this password to authenticate, should
-    /// the room be password-protected.
+    /// If set, cove will use this password to authenticate once the room
+    /// requires authentication.
+    ///
+    /// The password is only sent automatically the first time the room asks
+    /// for it, unless `auto_auth` is enabled. If it is rejected, cove asks for
+    /// the correct password instead.
+    ///
+    /// Overrides `euph.servers.<domain>.password`.
     pub password: Option<String>,
+
+    /// Whether to send the configured password every time the room requires
+    /// authentication, for example after reconnecting.
+    ///
+    /// This only has an effect if `autojoin` is enabled and a password is
+    /// configured. It allows password-protected rooms to stay joined
+    /// unattended. A rejected password is not sent again until the next
+    /// reconnect.
+    #[serde(default)]
+    pub auto_auth: bool,
+
+    /// Whether to always list this room before all unpinned rooms in the rooms
+    /// list, regardless of sort order.
+    #[serde(default)]
+    pub pinned: bool,
+
+    /// Which unseen messages in this room should draw your attention.
+    ///
+    /// With `all`, every unseen message is counted. With `mentions`, the
+    /// room's unseen count only includes messages mentioning you or matching
+    /// a `highlight` rule. With `none`, the room's unseen messages are not
+    /// included in the total shown in the rooms list heading and
+    /// `notify_command` is never run for this room.
+    #[serde(default)]
+    pub notify: NotifyLevel,
+
+    /// Key bindings that differ from the global ones while this room is open.
+    ///
+    /// Bindings are identified by their full name, for example
+    /// `"room.action.nick"` or `"tree.action.reply"`. Only the listed bindings
+    /// are replaced, all others are taken from the `keys` section. Unknown
+    /// binding names are rejected when loading the config.
+    #[serde(default)]
+    #[document(metavar = "binding")]
+    pub keys: HashMap<String, KeyBinding>,
+
+    /// Fixed colors for nicks in this room, overriding the color cove would
+    /// otherwise choose based on the nick.
+    ///
+    /// Nicks must match exactly, ignoring leading and trailing whitespace.
+    /// Colors can be specified as hex codes like `"#ff8800"` or by name.
+    /// Available names are `black`, `dark_grey`, `red`, `dark_red`, `green`,
+    /// `dark_green`, `yellow`, `dark_yellow`, `blue`, `dark_blue`, `magenta`,
+    /// `dark_magenta`, `cyan`, `dark_cyan`, `white` and `grey`.
+    #[serde(default)]
+    #[document(metavar = "nick")]
+    pub nick_colors: HashMap<String, String>,
 }
 
 #[derive(Debug, Default, Deserialize, Document)]
 pub struct EuphServer {
+    #[serde(default)]
     #[document(metavar = "room")]
     pub rooms: HashMap<String, EuphRoom>,
+
+    /// Whether to display messages starting with `/me` like normal messages
+    /// instead of as actions.
+    ///
+    /// Not all servers follow the convention of using `/me` for actions. On
+    /// those servers, it may be less confusing to see the raw message.
+    #[serde(default)]
+    pub plain_me: bool,
+
+    /// Default for `euph.servers.<domain>.rooms.<room>.username`, used for all
+    /// rooms on this server that don't set their own.
+    pub username: Option<String>,
+
+    /// Default for `euph.servers.<domain>.rooms.<room>.force_username`.
+    #[serde(default)]
+    pub force_username: bool,
+
+    /// Default for `euph.servers.<domain>.rooms.<room>.password`.
+    pub password: Option<String>,
+
+    /// Overrides `compose_prefix` for this server.
+    ///
+    /// Set to `""` to send messages on this server without a prefix.
+    pub compose_prefix: Option<String>,
+
+    /// Overrides `compose_suffix` for this server.
+    ///
+    /// Set to `""` to send messages on this server without a suffix.
+    pub compose_suffix: Option<String>,
+
+    /// Template for links to messages on this server, used when copying or
+    /// opening a message's link.
+    ///
+    /// The placeholders `{domain}`, `{room}` and `{id}` are replaced by the
+    /// server's domain, the room name and the message id respectively.
+    #[document(default = "`\"https://{domain}/room/{room}/#msg-{id}\"`")]
+    pub permalink: Option<String>,
 }
 
 #[derive(Debug, Default, Deserialize, Document)]
 pub struct Euph {
     #[document(metavar = "domain")]
     pub servers: HashMap<String, EuphServer>,
+
+    /// Domain that is filled in when connecting to a new room.
+    #[document(default = "`\"euphoria.leet.nu\"`")]
+    pub default_domain: Option<String>,
+}
+
+impl Euph {
+    pub fn default_domain(&self) -> &str {
+        self.default_domain.as_deref().unwrap_or("euphoria.leet.nu")
+    }
+
+    /// Link to a message in a room, according to the server's `permalink`
+    /// template.
+    pub fn permalink(&self, domain: &str, room: &str, id: &str) -> String {
+        self.servers
+            .get(domain)
+            .and_then(|server| server.permalink.as_deref())
+            .unwrap_or("https://{domain}/room/{room}/#msg-{id}")
+            .replace("{domain}", domain)
+            .replace("{room}", room)
+            .replace("{id}", id)
+    }
+}
+
+/// Parse a color like `red`, `dark_grey` or `#ff8800`.
+pub fn parse_color(color: &str) -> Option<Color> {
+    let color = color.trim();
+    if let Some(hex) = color.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+        let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+        let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+        return Some(Color::Rgb { r, g, b });
+    }
+    Color::try_from(color).ok()
 }
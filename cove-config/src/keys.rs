@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use cove_input::{KeyBinding, KeyGroup, KeyGroupInfo};
 use serde::Deserialize;
 
@@ -27,6 +29,8 @@ default_bindings! {
         pub fn focus => ["tab"];
         pub fn help => ["f1"];
         pub fn log => ["f12"];
+        pub fn palette => ["ctrl+k"];
+        pub fn cycle_theme => ["f2"];
     }
 
     pub mod scroll {
@@ -62,6 +66,7 @@ default_bindings! {
         pub fn delete => ["ctrl+d", "delete"];
         pub fn clear => ["ctrl+l"];
         pub fn external => ["ctrl+x", "alt+e"];
+        pub fn load_file => ["ctrl+o"];
     }
 
     pub mod rooms_action {
@@ -69,11 +74,17 @@ default_bindings! {
         pub fn connect_all => ["C"];
         pub fn disconnect => ["d"];
         pub fn disconnect_all => ["D"];
+        pub fn reconnect => ["r"];
         pub fn connect_autojoin => ["a"];
         pub fn disconnect_non_autojoin => ["A"];
         pub fn new => ["n"];
         pub fn delete => ["X"];
         pub fn change_sort_order => ["s"];
+        pub fn to_next_failing => ["e"];
+        pub fn to_next_unseen => ["u"];
+        pub fn metrics => ["M"];
+        pub fn complete => ["ctrl+n"];
+        pub fn filter => ["/"];
     }
 
     pub mod room_action {
@@ -81,6 +92,20 @@ default_bindings! {
         pub fn nick => ["n"];
         pub fn more_messages => ["m"];
         pub fn account => ["A"];
+        pub fn outbox => ["o"];
+        pub fn skip_affixes => ["x"];
+        pub fn set_read_mark => ["b"];
+        pub fn to_read_mark => ["B"];
+        pub fn search => ["?"];
+        pub fn mark_read => ["alt+s"];
+        pub fn filter_nick_list => ["/"];
+        pub fn reconnect => ["ctrl+r"];
+    }
+
+    pub mod room_outbox {
+        pub fn retry => ["r"];
+        pub fn edit => ["e"];
+        pub fn discard => ["d", "delete"];
     }
 
     pub mod tree_cursor {
@@ -99,6 +124,8 @@ default_bindings! {
         pub fn reply_alternate => ["R"];
         pub fn new_thread => ["t"];
         pub fn fold_tree => [" "];
+        pub fn fold_all => ["-"];
+        pub fn unfold_all => ["="];
         pub fn toggle_seen => ["s"];
         pub fn mark_visible_seen => ["S"];
         pub fn mark_older_seen => ["ctrl+s"];
@@ -106,11 +133,25 @@ default_bindings! {
         pub fn links => ["I"];
         pub fn increase_caesar => ["c"];
         pub fn decrease_caesar => ["C"];
+        pub fn toggle_emoji => ["e"];
+        pub fn toggle_ignored => ["alt+i"];
+        pub fn toggle_truncated => ["T"];
+        pub fn toggle_expanded => ["E"];
+        pub fn toggle_flat => ["v"];
+        pub fn search_thread => ["/"];
+        pub fn next_match => ["ctrl+n"];
+        pub fn prev_match => ["ctrl+p"];
+        pub fn copy => ["y"];
+        pub fn copy_link => ["Y"];
+        pub fn open_permalink => ["O"];
+        pub fn follow => ["F"];
+        pub fn reply_to_older => ["alt+up"];
+        pub fn reply_to_newer => ["alt+down"];
     }
 
 }
 
-#[derive(Debug, Deserialize, Document, KeyGroup)]
+#[derive(Debug, Clone, Deserialize, Document, KeyGroup)]
 /// General.
 pub struct General {
     /// Quit cove.
@@ -131,9 +172,15 @@ pub struct General {
     /// Show log.
     #[serde(default = "default::general::log")]
     pub log: KeyBinding,
+    /// Show command palette.
+    #[serde(default = "default::general::palette")]
+    pub palette: KeyBinding,
+    /// Switch to the next theme.
+    #[serde(default = "default::general::cycle_theme")]
+    pub cycle_theme: KeyBinding,
 }
 
-#[derive(Debug, Deserialize, Document, KeyGroup)]
+#[derive(Debug, Clone, Deserialize, Document, KeyGroup)]
 /// Scrolling.
 pub struct Scroll {
     /// Scroll up one line.
@@ -159,7 +206,7 @@ pub struct Scroll {
     pub center_cursor: KeyBinding,
 }
 
-#[derive(Debug, Deserialize, Document, KeyGroup)]
+#[derive(Debug, Clone, Deserialize, Document, KeyGroup)]
 /// Cursor movement.
 pub struct Cursor {
     /// Move up.
@@ -176,7 +223,7 @@ pub struct Cursor {
     pub to_bottom: KeyBinding,
 }
 
-#[derive(Debug, Deserialize, Document, KeyGroup)]
+#[derive(Debug, Clone, Deserialize, Document, KeyGroup)]
 /// Editor cursor movement.
 pub struct EditorCursor {
     /// Move left.
@@ -205,7 +252,7 @@ pub struct EditorCursor {
     pub down: KeyBinding,
 }
 
-#[derive(Debug, Deserialize, Document, KeyGroup)]
+#[derive(Debug, Clone, Deserialize, Document, KeyGroup)]
 /// Editor actions.
 pub struct EditorAction {
     /// Delete before cursor.
@@ -220,9 +267,12 @@ pub struct EditorAction {
     /// Edit in external editor.
     #[serde(default = "default::editor_action::external")]
     pub external: KeyBinding,
+    /// Load file contents into message editor.
+    #[serde(default = "default::editor_action::load_file")]
+    pub load_file: KeyBinding,
 }
 
-#[derive(Debug, Default, Deserialize, Document)]
+#[derive(Debug, Clone, Default, Deserialize, Document)]
 pub struct Editor {
     #[serde(default)]
     #[document(no_default)]
@@ -233,7 +283,7 @@ pub struct Editor {
     pub action: EditorAction,
 }
 
-#[derive(Debug, Deserialize, Document, KeyGroup)]
+#[derive(Debug, Clone, Deserialize, Document, KeyGroup)]
 /// Room list actions.
 pub struct RoomsAction {
     /// Connect to selected room.
@@ -248,6 +298,9 @@ pub struct RoomsAction {
     /// Disconnect from all rooms.
     #[serde(default = "default::rooms_action::disconnect_all")]
     pub disconnect_all: KeyBinding,
+    /// Reconnect to selected room.
+    #[serde(default = "default::rooms_action::reconnect")]
+    pub reconnect: KeyBinding,
     /// Connect to all autojoin rooms.
     #[serde(default = "default::rooms_action::connect_autojoin")]
     pub connect_autojoin: KeyBinding,
@@ -263,16 +316,32 @@ pub struct RoomsAction {
     /// Change sort order.
     #[serde(default = "default::rooms_action::change_sort_order")]
     pub change_sort_order: KeyBinding,
+    /// Move to next room that is waiting to reconnect or requires
+    /// authentication.
+    #[serde(default = "default::rooms_action::to_next_failing")]
+    pub to_next_failing: KeyBinding,
+    /// Open next room with unseen messages at its oldest unseen message.
+    #[serde(default = "default::rooms_action::to_next_unseen")]
+    pub to_next_unseen: KeyBinding,
+    /// Show connection metrics of all rooms.
+    #[serde(default = "default::rooms_action::metrics")]
+    pub metrics: KeyBinding,
+    /// Complete room and domain from recently joined rooms while connecting.
+    #[serde(default = "default::rooms_action::complete")]
+    pub complete: KeyBinding,
+    /// Filter the room list by domain and name.
+    #[serde(default = "default::rooms_action::filter")]
+    pub filter: KeyBinding,
 }
 
-#[derive(Debug, Default, Deserialize, Document)]
+#[derive(Debug, Clone, Default, Deserialize, Document)]
 pub struct Rooms {
     #[serde(default)]
     #[document(no_default)]
     pub action: RoomsAction,
 }
 
-#[derive(Debug, Deserialize, Document, KeyGroup)]
+#[derive(Debug, Clone, Deserialize, Document, KeyGroup)]
 /// Room actions.
 pub struct RoomAction {
     /// Authenticate.
@@ -287,16 +356,58 @@ pub struct RoomAction {
     /// Manage account.
     #[serde(default = "default::room_action::account")]
     pub account: KeyBinding,
+    /// Show outgoing messages that were not sent yet.
+    #[serde(default = "default::room_action::outbox")]
+    pub outbox: KeyBinding,
+    /// Send next message without `compose_prefix` and `compose_suffix`.
+    #[serde(default = "default::room_action::skip_affixes")]
+    pub skip_affixes: KeyBinding,
+    /// Set or remove read mark at selected message.
+    #[serde(default = "default::room_action::set_read_mark")]
+    pub set_read_mark: KeyBinding,
+    /// Move to read mark.
+    #[serde(default = "default::room_action::to_read_mark")]
+    pub to_read_mark: KeyBinding,
+    /// Search all messages of the room.
+    #[serde(default = "default::room_action::search")]
+    pub search: KeyBinding,
+    /// Mark all messages in the room as seen.
+    #[serde(default = "default::room_action::mark_read")]
+    pub mark_read: KeyBinding,
+    /// Filter the nick list by name while it is focused.
+    #[serde(default = "default::room_action::filter_nick_list")]
+    pub filter_nick_list: KeyBinding,
+    /// Reconnect to the current room.
+    #[serde(default = "default::room_action::reconnect")]
+    pub reconnect: KeyBinding,
 }
 
-#[derive(Debug, Default, Deserialize, Document)]
+#[derive(Debug, Clone, Deserialize, Document, KeyGroup)]
+/// Outbox actions.
+pub struct RoomOutbox {
+    /// Retry sending selected message.
+    #[serde(default = "default::room_outbox::retry")]
+    pub retry: KeyBinding,
+    /// Move selected message back into the editor.
+    #[serde(default = "default::room_outbox::edit")]
+    pub edit: KeyBinding,
+    /// Discard selected message.
+    #[serde(default = "default::room_outbox::discard")]
+    pub discard: KeyBinding,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Document)]
 pub struct Room {
     #[serde(default)]
     #[document(no_default)]
     pub action: RoomAction,
+
+    #[serde(default)]
+    #[document(no_default)]
+    pub outbox: RoomOutbox,
 }
 
-#[derive(Debug, Deserialize, Document, KeyGroup)]
+#[derive(Debug, Clone, Deserialize, Document, KeyGroup)]
 /// Tree cursor movement.
 pub struct TreeCursor {
     /// Move to above sibling.
@@ -326,7 +437,7 @@ pub struct TreeCursor {
     // TODO Bindings inspired by vim's ()/[]/{} bindings?
 }
 
-#[derive(Debug, Deserialize, Document, KeyGroup)]
+#[derive(Debug, Clone, Deserialize, Document, KeyGroup)]
 /// Tree actions.
 pub struct TreeAction {
     /// Reply to message, inline if possible.
@@ -341,6 +452,12 @@ pub struct TreeAction {
     /// Fold current message's subtree.
     #[serde(default = "default::tree_action::fold_tree")]
     pub fold_tree: KeyBinding,
+    /// Fold all threads.
+    #[serde(default = "default::tree_action::fold_all")]
+    pub fold_all: KeyBinding,
+    /// Unfold all messages.
+    #[serde(default = "default::tree_action::unfold_all")]
+    pub unfold_all: KeyBinding,
     /// Toggle current message's seen status.
     #[serde(default = "default::tree_action::toggle_seen")]
     pub toggle_seen: KeyBinding,
@@ -362,9 +479,56 @@ pub struct TreeAction {
     /// Decrease caesar cipher rotation.
     #[serde(default = "default::tree_action::decrease_caesar")]
     pub decrease_caesar: KeyBinding,
+    /// Cycle between rendered emoji, shortcodes and raw message contents.
+    #[serde(default = "default::tree_action::toggle_emoji")]
+    pub toggle_emoji: KeyBinding,
+    /// Toggle showing messages from ignored users.
+    #[serde(default = "default::tree_action::toggle_ignored")]
+    pub toggle_ignored: KeyBinding,
+    /// Toggle between showing the full and a shortened version of long
+    /// messages.
+    #[serde(default = "default::tree_action::toggle_truncated")]
+    pub toggle_truncated: KeyBinding,
+    /// Toggle between showing all lines and at most `max_message_lines` lines
+    /// of long messages.
+    #[serde(default = "default::tree_action::toggle_expanded")]
+    pub toggle_expanded: KeyBinding,
+    /// Toggle between showing messages as threads and as a flat list sorted
+    /// chronologically.
+    #[serde(default = "default::tree_action::toggle_flat")]
+    pub toggle_flat: KeyBinding,
+    /// Search for text within the current thread.
+    #[serde(default = "default::tree_action::search_thread")]
+    pub search_thread: KeyBinding,
+    /// Move to next search match.
+    #[serde(default = "default::tree_action::next_match")]
+    pub next_match: KeyBinding,
+    /// Move to previous search match.
+    #[serde(default = "default::tree_action::prev_match")]
+    pub prev_match: KeyBinding,
+    /// Copy message content to the clipboard.
+    #[serde(default = "default::tree_action::copy")]
+    pub copy: KeyBinding,
+    /// Copy link to message to the clipboard.
+    #[serde(default = "default::tree_action::copy_link")]
+    pub copy_link: KeyBinding,
+    /// Open link to message in the browser.
+    #[serde(default = "default::tree_action::open_permalink")]
+    pub open_permalink: KeyBinding,
+    /// Toggle following new messages.
+    #[serde(default = "default::tree_action::follow")]
+    pub follow: KeyBinding,
+    /// While composing, reply to the next older message instead, keeping the
+    /// draft.
+    #[serde(default = "default::tree_action::reply_to_older")]
+    pub reply_to_older: KeyBinding,
+    /// While composing, reply to the next newer message instead, keeping the
+    /// draft.
+    #[serde(default = "default::tree_action::reply_to_newer")]
+    pub reply_to_newer: KeyBinding,
 }
 
-#[derive(Debug, Default, Deserialize, Document)]
+#[derive(Debug, Clone, Default, Deserialize, Document)]
 pub struct Tree {
     #[serde(default)]
     #[document(no_default)]
@@ -375,7 +539,7 @@ pub struct Tree {
     pub action: TreeAction,
 }
 
-#[derive(Debug, Default, Deserialize, Document)]
+#[derive(Debug, Clone, Default, Deserialize, Document)]
 pub struct Keys {
     #[serde(default)]
     #[document(no_default)]
@@ -416,8 +580,41 @@ impl Keys {
             KeyGroupInfo::new("editor.action", &self.editor.action),
             KeyGroupInfo::new("rooms.action", &self.rooms.action),
             KeyGroupInfo::new("room.action", &self.room.action),
+            KeyGroupInfo::new("room.outbox", &self.room.outbox),
             KeyGroupInfo::new("tree.cursor", &self.tree.cursor),
             KeyGroupInfo::new("tree.action", &self.tree.action),
         ]
     }
+
+    /// Look up a binding by its full name, e.g. `room.action.nick`.
+    pub fn binding_mut(&mut self, name: &str) -> Option<&mut KeyBinding> {
+        let (group, name) = name.rsplit_once('.')?;
+        match group {
+            "general" => self.general.binding_mut(name),
+            "scroll" => self.scroll.binding_mut(name),
+            "cursor" => self.cursor.binding_mut(name),
+            "editor.cursor" => self.editor.cursor.binding_mut(name),
+            "editor.action" => self.editor.action.binding_mut(name),
+            "rooms.action" => self.rooms.action.binding_mut(name),
+            "room.action" => self.room.action.binding_mut(name),
+            "room.outbox" => self.room.outbox.binding_mut(name),
+            "tree.cursor" => self.tree.cursor.binding_mut(name),
+            "tree.action" => self.tree.action.binding_mut(name),
+            _ => None,
+        }
+    }
+
+    /// Replace individual bindings, leaving all others untouched.
+    ///
+    /// Returns the name of the first unknown binding as error.
+    pub fn with_overrides(&self, overrides: &HashMap<String, KeyBinding>) -> Result<Self, String> {
+        let mut keys = self.clone();
+        for (name, binding) in overrides {
+            match keys.binding_mut(name) {
+                Some(target) => *target = binding.clone(),
+                None => return Err(name.clone()),
+            }
+        }
+        Ok(keys)
+    }
 }
@@ -2,22 +2,46 @@ pub mod doc;
 mod euph;
 mod keys;
 
+use std::collections::HashMap;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{fs, io};
 
 use doc::Document;
+use jiff::fmt::strtime;
+use jiff::tz::TimeZone;
+use jiff::Timestamp;
 use serde::Deserialize;
 
 pub use crate::euph::*;
 pub use crate::keys::*;
 
+/// Names of the themes that are always available.
+pub const BUILTIN_THEMES: [&str; 2] = ["dark", "light"];
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("failed to read config file")]
     Io(#[from] io::Error),
     #[error("failed to parse config file")]
     Toml(#[from] toml::de::Error),
+    #[error("unknown key binding {binding:?} in room &{room} on {domain}")]
+    UnknownKeyBinding {
+        domain: String,
+        room: String,
+        binding: String,
+    },
+    #[error("invalid color {color:?} for {what}")]
+    InvalidColor { color: String, what: String },
+    #[error("unknown theme {0:?}")]
+    UnknownTheme(String),
+    #[error("invalid timestamp format {format:?}")]
+    InvalidTimestampFormat {
+        format: String,
+        #[source]
+        source: jiff::Error,
+    },
 }
 
 #[derive(Debug, Default, Deserialize, Document)]
@@ -61,6 +85,76 @@ pub struct Config {
     #[serde(default)]
     pub offline: bool,
 
+    /// Disconnect from rooms that haven't been viewed for this many minutes.
+    ///
+    /// Disconnected rooms stay in the rooms list and are reconnected when they
+    /// are viewed again. Rooms with `autojoin` enabled are only disconnected
+    /// if `idle_disconnect_autojoin` is enabled as well.
+    ///
+    /// If not set, rooms stay connected indefinitely.
+    pub idle_disconnect_after: Option<usize>,
+
+    /// Whether `idle_disconnect_after` also applies to rooms with `autojoin`
+    /// enabled.
+    #[serde(default)]
+    pub idle_disconnect_autojoin: bool,
+
+    /// Whether to trade durability for speed while downloading room history.
+    ///
+    /// When enabled, cove relaxes sqlite's `synchronous` setting while storing
+    /// messages from log requests and restores it afterwards. This makes
+    /// backfilling large rooms considerably faster.
+    ///
+    /// **Warning:** If your system crashes or loses power during a download,
+    /// the most recently downloaded messages may be lost. They will be
+    /// downloaded again the next time. The vault itself will not be corrupted.
+    #[serde(default)]
+    pub bulk_backfill: bool,
+
+    /// How long to wait for the vault to become available, in milliseconds.
+    ///
+    /// Only one instance of cove can use a vault at a time. If another instance
+    /// is still shutting down, cove waits for up to this long before giving up
+    /// with an error.
+    #[document(default = "`5000`")]
+    pub vault_busy_timeout: Option<usize>,
+
+    /// Whether to measure how long vault actions take.
+    ///
+    /// Actions taking longer than `vault_slow_action_threshold` are logged,
+    /// and a summary of all actions is printed when cove exits. This is always
+    /// enabled when cove is started with `--verbose`.
+    #[serde(default)]
+    pub vault_timing: bool,
+
+    /// Minimum duration of vault actions to be logged when `vault_timing` is
+    /// enabled, in milliseconds.
+    #[document(default = "`5`")]
+    pub vault_slow_action_threshold: Option<usize>,
+
+    /// Maximum number of messages of a single tree to load and display.
+    ///
+    /// Trees with more messages are truncated and marked as such. This bounds
+    /// memory usage and render time for extremely large threads. Exports are
+    /// never truncated.
+    #[document(default = "`10000`")]
+    pub max_tree_size: Option<usize>,
+
+    /// Maximum number of error popups stacked on top of a room.
+    ///
+    /// When more errors occur, the oldest popups are dropped. Identical
+    /// consecutive errors are combined into a single popup showing how often
+    /// the error occurred.
+    #[document(default = "`5`")]
+    pub max_popups: Option<usize>,
+
+    /// Whether to unfold folded threads when a new reply arrives in them.
+    ///
+    /// When disabled, folded threads stay folded and their count of hidden
+    /// messages is updated instead.
+    #[serde(default)]
+    pub unfold_on_new_reply: bool,
+
     /// Initial sort order of rooms list.
     ///
     /// `"alphabet"` sorts rooms in alphabetic order.
@@ -74,6 +168,161 @@ pub struct Config {
     #[serde(default)]
     pub rooms_sort_order: RoomsSortOrder,
 
+    /// Whether the `"importance"` sort order should sort rooms with unread
+    /// messages by their number of unread messages.
+    ///
+    /// When enabled, rooms with more unread messages are listed before rooms
+    /// with fewer unread messages. Otherwise, all rooms with unread messages
+    /// are sorted alphabetically.
+    #[serde(default)]
+    pub rooms_sort_by_unseen_count: bool,
+
+    /// Whether to show the newest message of each room in the rooms list.
+    ///
+    /// The preview consists of the sender's nick and the beginning of the
+    /// message's first line.
+    #[serde(default)]
+    pub rooms_message_preview: bool,
+
+    /// Whether to show how many messages are stored in the vault for each
+    /// room in the rooms list.
+    ///
+    /// The count is shown after the number of unread messages.
+    #[serde(default)]
+    pub show_message_counts: bool,
+
+    /// How to summarize the people in a room in the rooms list.
+    ///
+    /// `"compact"` shows abbreviated counts like `3p 1b 2l`, meaning 3 people,
+    /// 1 bot and 2 lurkers. Bots without a nick are counted as `n`.
+    ///
+    /// `"verbose"` spells the counts out, like `3 people, 1 bot, 2 lurkers`.
+    ///
+    /// `"hidden"` doesn't show any counts.
+    #[serde(default)]
+    pub nick_list_summary: NickListSummary,
+
+    /// How to sort the nick list.
+    ///
+    /// `"type"` groups sessions into people, bots, lurkers and nurkers (bots
+    /// without a nick) and sorts each group alphabetically.
+    ///
+    /// `"alphabet"` sorts all sessions alphabetically.
+    ///
+    /// `"joined"` sorts all sessions by the order they joined the room in.
+    /// Sessions that were already present when joining are sorted
+    /// alphabetically.
+    #[serde(default)]
+    pub nick_list_sort: NickListSort,
+
+    /// How to display emoji in messages.
+    ///
+    /// `"unicode"` replaces shortcodes like `:smile:` with the emoji they stand
+    /// for.
+    ///
+    /// `"raw"` displays messages as they were sent.
+    ///
+    /// `"shortcode"` replaces emoji with their shortcodes, so messages only
+    /// ever contain shortcodes. Unknown emoji are left as they are.
+    ///
+    /// Terminal emulators disagree on how wide some emoji are, which can mess
+    /// up the chat layout. `"raw"` and `"shortcode"` avoid such problems.
+    /// Rendering can also be switched per room at runtime.
+    #[serde(default)]
+    pub emoji: EmojiMode,
+
+    /// Whether to fetch the titles of linked web pages and show them in the
+    /// links popup.
+    ///
+    /// Titles are fetched in the background when the popup is opened and
+    /// cached until cove exits. Links whose title can't be fetched are shown
+    /// as-is.
+    #[serde(default)]
+    pub link_titles: bool,
+
+    /// Whether to show the connection latency in the room's status line.
+    ///
+    /// The latency is measured by pinging the server whenever it pings cove,
+    /// so it may take a little while to show up after connecting.
+    #[serde(default)]
+    pub show_latency: bool,
+
+    /// Ids of users whose messages are hidden.
+    ///
+    /// Messages from these users are shown as `<ignored>` placeholders so that
+    /// replies to them stay in place. The ids can be found in the message
+    /// inspection popup. Ignored messages can be shown again for the current
+    /// session via a key binding.
+    #[serde(default)]
+    pub ignore: Vec<String>,
+
+    /// Command used to edit messages in an external editor.
+    ///
+    /// The command is split into a program and its arguments like a shell would
+    /// split it, and the path of the file to edit is appended. For example,
+    /// `"nvim -c startinsert"`. If not set, `$VISUAL` or `$EDITOR` is used.
+    pub editor_command: Option<String>,
+
+    /// Shorten nicks in the nick list that are wider than this many columns.
+    ///
+    /// Shortened nicks end in `…`. The nick list grows to fit the widest nick,
+    /// so long nicks can take up a lot of space otherwise.
+    pub nick_list_max_nick_width: Option<usize>,
+
+    /// Shorten nicks next to messages that are wider than this many columns.
+    ///
+    /// See also `nick_list_max_nick_width`.
+    pub chat_max_nick_width: Option<usize>,
+
+    /// Maximum indentation depth of messages in the tree view.
+    ///
+    /// Messages nested deeper than this are drawn at this depth, with their
+    /// indentation ending in `»` to show that they are nested deeper than
+    /// shown. Useful for deeply nested threads on narrow terminals.
+    pub max_indent: Option<usize>,
+
+    /// Show at most this many lines of a message's content.
+    ///
+    /// Longer messages end in `… (show more)` and can be shown in full with
+    /// the `keys.tree.action.toggle_expanded` key binding. Only line breaks in
+    /// the content count, not lines added by wrapping.
+    pub max_message_lines: Option<usize>,
+
+    /// Marker to show at the start of lines in a message that were wrapped
+    /// because they were too long to fit, for example `"↪ "`.
+    ///
+    /// Makes it easier to tell where a wrapped line continues and where a new
+    /// line begins. By default, no marker is shown.
+    pub wrap_marker: Option<String>,
+
+    /// Minimum number of lines to keep between the cursor and the top and
+    /// bottom of the chat when scrolling.
+    ///
+    /// On small screens, this is reduced to at most half the chat height.
+    #[document(default = "`2`")]
+    pub scrolloff: Option<usize>,
+
+    /// Whether to center the cursor vertically when it jumps to a message that
+    /// was far off-screen, for example when moving to the next search match
+    /// or unseen message.
+    ///
+    /// When disabled, the chat is scrolled just far enough for the message to
+    /// become visible.
+    #[serde(default)]
+    pub center_cursor_on_jump: bool,
+
+    /// Where to place the cursor when opening a room for the first time.
+    ///
+    /// `"newest"` places the cursor below the newest message.
+    ///
+    /// `"oldest_unseen"` places the cursor on the oldest unseen message. If
+    /// there are no unseen messages, it behaves like `"newest"`.
+    ///
+    /// `"last_read"` places the cursor on the newest message that has already
+    /// been seen. If there is no such message, it behaves like `"newest"`.
+    #[serde(default)]
+    pub open_room_cursor: OpenRoomCursor,
+
     /// Time zone that chat timestamps should be displayed in.
     ///
     /// This option is interpreted as a POSIX TZ string. It is described here in
@@ -88,13 +337,130 @@ pub struct Config {
     /// If the `TZ` environment variable exists, it overrides this option. If
     /// neither exist, cove uses the system's local time zone.
     ///
-    /// **Warning:** On Windows, cove can't get the local time zone and uses UTC
-    /// instead. However, you can still specify a path to a tz data file or a
-    /// custom time zone string.
+    /// **Warning:** On Windows, cove can't get the local time zone and uses
+    /// `time_zone_fallback` instead. However, you can still specify a path to a
+    /// tz data file or a custom time zone string.
     #[serde(default)]
     #[document(default = "`$TZ` or local system time zone")]
     pub time_zone: Option<String>,
 
+    /// Time zone to use if the system's local time zone can't be determined.
+    ///
+    /// This option uses the same format as `time_zone`. It is checked on
+    /// startup even if it isn't needed, so an invalid value is noticed right
+    /// away.
+    #[serde(default)]
+    #[document(default = "`UTC`")]
+    pub time_zone_fallback: Option<String>,
+
+    /// How chat timestamps are displayed.
+    ///
+    /// `"full"` shows the date and time of every message, like
+    /// `2024-01-31 13:37`.
+    ///
+    /// `"smart"` only shows the time for messages sent today and only the date
+    /// for older messages.
+    #[serde(default)]
+    pub time_format: TimeFormat,
+
+    /// Custom format of message timestamps, overriding `time_format`.
+    ///
+    /// The format is a strftime-style pattern like `"%Y-%m-%d %H:%M:%S"`. See
+    /// the [jiff documentation](https://docs.rs/jiff/latest/jiff/fmt/strtime/index.html)
+    /// for the supported conversion specifiers.
+    pub timestamp_format: Option<String>,
+
+    /// Show only the time of day in message timestamps if the chat is narrower
+    /// than this many columns.
+    ///
+    /// This leaves more space for messages on narrow terminals. It takes
+    /// precedence over `time_format` and `timestamp_format`.
+    pub compact_timestamp_width: Option<usize>,
+
+    /// Rules for highlighting parts of messages, for example your own name.
+    ///
+    /// Each rule consists of a regular expression `pattern` and the `style`
+    /// that matching text is displayed in. If multiple rules match overlapping
+    /// text, the match starting first wins, and for matches starting at the
+    /// same position, the rule listed first. Mentions, room names and emoji
+    /// keep their own style.
+    ///
+    /// ```toml
+    /// [[highlight]]
+    /// pattern = "(?i)\\bcove\\b"
+    /// style = { fg = "yellow", bold = true }
+    /// ```
+    #[serde(default)]
+    #[document(no_default)]
+    pub highlight: Vec<HighlightRule>,
+
+    /// Theme to use on startup.
+    ///
+    /// Either one of the built-in themes `"dark"` and `"light"` or the name of
+    /// a theme defined in `themes`. The `keys.general.cycle_theme` key binding
+    /// switches between all themes while cove is running.
+    #[document(default = "`\"dark\"`")]
+    pub theme: Option<String>,
+
+    /// Custom themes, by name.
+    ///
+    /// For example:
+    ///
+    /// ```toml
+    /// [themes.solarized]
+    /// base = "light"
+    /// text = "#657b83"
+    /// cursor_bg = "#eee8d5"
+    /// ```
+    #[serde(default)]
+    #[document(metavar = "name")]
+    pub themes: HashMap<String, Theme>,
+
+    /// Command to run when a message mentioning you or matching a `highlight`
+    /// rule arrives in a room that is not currently open.
+    ///
+    /// The command is split at whitespace into the program and its arguments.
+    /// In each argument, `{room}`, `{nick}` and `{content}` are replaced by the
+    /// room name, the sender's nick and the message content. For example:
+    /// `"notify-send &{room} {content}"`
+    ///
+    /// To avoid floods of notifications, the command is run at most once every
+    /// ten seconds per room.
+    pub notify_command: Option<String>,
+
+    /// Ask for confirmation before sending messages with more than this many
+    /// lines.
+    ///
+    /// The confirmation popup shows a preview of the message. This helps
+    /// prevent accidentally sending huge pastes.
+    pub send_confirm_lines: Option<usize>,
+
+    /// Ask for confirmation before sending messages with more than this many
+    /// characters.
+    ///
+    /// See also `send_confirm_lines`.
+    pub send_confirm_chars: Option<usize>,
+
+    /// Ask for confirmation before marking more than this many messages as
+    /// seen at once via `tree.action.mark_older_seen`.
+    ///
+    /// This helps prevent accidentally marking large parts of a room's history
+    /// as seen.
+    pub confirm_bulk_seen_threshold: Option<usize>,
+
+    /// Text to prepend to every message sent, for example a tag.
+    ///
+    /// The prefix is not shown in the editor. It can be overridden for
+    /// individual servers via `euph.servers.<domain>.compose_prefix` and
+    /// skipped for a single message via the `room.action.skip_affixes` key
+    /// binding.
+    pub compose_prefix: Option<String>,
+
+    /// Text to append to every message sent, for example a signature.
+    ///
+    /// See also `compose_prefix`.
+    pub compose_suffix: Option<String>,
+
     #[serde(default)]
     #[document(no_default)]
     pub euph: Euph,
@@ -104,25 +470,197 @@ pub struct Config {
     pub keys: Keys,
 }
 
+fn check_color(color: &str, what: impl FnOnce() -> String) -> Result<(), Error> {
+    match parse_color(color) {
+        Some(_) => Ok(()),
+        None => Err(Error::InvalidColor {
+            color: color.to_string(),
+            what: what(),
+        }),
+    }
+}
+
 impl Config {
+    pub fn scrolloff(&self) -> usize {
+        self.scrolloff.unwrap_or(2)
+    }
+
     pub fn load(path: &Path) -> Result<Self, Error> {
-        Ok(match fs::read_to_string(path) {
+        let config: Self = match fs::read_to_string(path) {
             Ok(content) => toml::from_str(&content)?,
             Err(err) if err.kind() == ErrorKind::NotFound => Self::default(),
             Err(err) => Err(err)?,
-        })
+        };
+        config.check_room_keys()?;
+        config.check_timestamp_format()?;
+        config.check_highlight_colors()?;
+        config.check_themes()?;
+        config.check_nick_colors()?;
+        config.check_theme_colors()?;
+        Ok(config)
     }
 
-    pub fn euph_room(&self, domain: &str, name: &str) -> EuphRoom {
-        if let Some(server) = self.euph.servers.get(domain) {
-            if let Some(room) = server.rooms.get(name) {
-                return room.clone();
+    fn check_themes(&self) -> Result<(), Error> {
+        if let Some(name) = &self.theme {
+            if !BUILTIN_THEMES.contains(&name.as_str()) && !self.themes.contains_key(name) {
+                return Err(Error::UnknownTheme(name.clone()));
             }
         }
-        EuphRoom::default()
+        for theme in self.themes.values() {
+            if let Some(base) = &theme.base {
+                if !BUILTIN_THEMES.contains(&base.as_str()) {
+                    return Err(Error::UnknownTheme(base.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_theme_colors(&self) -> Result<(), Error> {
+        for (name, theme) in &self.themes {
+            let colors = [
+                ("text", &theme.text),
+                ("cursor_fg", &theme.cursor_fg),
+                ("cursor_bg", &theme.cursor_bg),
+                ("border", &theme.border),
+                ("unseen", &theme.unseen),
+            ];
+            for (field, color) in colors {
+                if let Some(color) = color {
+                    check_color(color, || format!("{field} in theme {name:?}"))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_nick_colors(&self) -> Result<(), Error> {
+        for (domain, server) in &self.euph.servers {
+            for (name, room) in &server.rooms {
+                for (nick, color) in &room.nick_colors {
+                    check_color(color, || {
+                        format!("nick {nick:?} in room &{name} on {domain}")
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_highlight_colors(&self) -> Result<(), Error> {
+        for rule in &self.highlight {
+            for (field, color) in [("fg", &rule.style.fg), ("bg", &rule.style.bg)] {
+                if let Some(color) = color {
+                    check_color(color, || {
+                        format!("{field} of highlight pattern {:?}", rule.pattern.as_str())
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_timestamp_format(&self) -> Result<(), Error> {
+        let Some(format) = &self.timestamp_format else {
+            return Ok(());
+        };
+        let sample = Timestamp::UNIX_EPOCH.to_zoned(TimeZone::UTC);
+        match strtime::format(format, &sample) {
+            Ok(_) => Ok(()),
+            Err(source) => Err(Error::InvalidTimestampFormat {
+                format: format.clone(),
+                source,
+            }),
+        }
+    }
+
+    fn check_room_keys(&self) -> Result<(), Error> {
+        for (domain, server) in &self.euph.servers {
+            for (name, room) in &server.rooms {
+                if let Err(binding) = self.keys.with_overrides(&room.keys) {
+                    return Err(Error::UnknownKeyBinding {
+                        domain: domain.clone(),
+                        room: name.clone(),
+                        binding,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The key bindings to use in a room, with the room's overrides applied.
+    ///
+    /// Room-specific bindings take precedence over the global ones.
+    pub fn room_keys(&self, domain: &str, name: &str) -> Option<Keys> {
+        let room = self.euph.servers.get(domain)?.rooms.get(name)?;
+        if room.keys.is_empty() {
+            return None;
+        }
+        self.keys.with_overrides(&room.keys).ok()
+    }
+
+    /// The config of a room, with the server-level defaults filled in where
+    /// the room doesn't specify its own values.
+    pub fn euph_room(&self, domain: &str, name: &str) -> EuphRoom {
+        let Some(server) = self.euph.servers.get(domain) else {
+            return EuphRoom::default();
+        };
+
+        let mut room = server.rooms.get(name).cloned().unwrap_or_default();
+        if room.username.is_none() {
+            room.username = server.username.clone();
+        }
+        if room.force_username.is_none() {
+            room.force_username = Some(server.force_username);
+        }
+        if room.password.is_none() {
+            room.password = server.password.clone();
+        }
+        room
+    }
+
+    /// The prefix and suffix to add to messages sent on a server.
+    pub fn compose_affixes(&self, domain: &str) -> (&str, &str) {
+        let server = self.euph.servers.get(domain);
+        let prefix = server
+            .and_then(|s| s.compose_prefix.as_ref())
+            .or(self.compose_prefix.as_ref());
+        let suffix = server
+            .and_then(|s| s.compose_suffix.as_ref())
+            .or(self.compose_suffix.as_ref());
+        (
+            prefix.map(|s| s as &str).unwrap_or_default(),
+            suffix.map(|s| s as &str).unwrap_or_default(),
+        )
     }
 
     pub fn time_zone_ref(&self) -> Option<&str> {
         self.time_zone.as_ref().map(|s| s as &str)
     }
+
+    pub fn time_zone_fallback_ref(&self) -> Option<&str> {
+        self.time_zone_fallback.as_ref().map(|s| s as &str)
+    }
+
+    pub fn max_tree_size(&self) -> usize {
+        self.max_tree_size.unwrap_or(10_000)
+    }
+
+    pub fn max_popups(&self) -> usize {
+        self.max_popups.unwrap_or(5).max(1)
+    }
+
+    pub fn vault_slow_action_threshold(&self) -> Duration {
+        Duration::from_millis(self.vault_slow_action_threshold.unwrap_or(5) as u64)
+    }
+
+    pub fn idle_disconnect_after(&self) -> Option<Duration> {
+        self.idle_disconnect_after
+            .map(|minutes| Duration::from_secs(minutes as u64 * 60))
+    }
+
+    pub fn vault_busy_timeout(&self) -> Duration {
+        Duration::from_millis(self.vault_busy_timeout.unwrap_or(5000) as u64)
+    }
 }
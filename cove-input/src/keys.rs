@@ -142,6 +142,21 @@ impl KeyPress {
             shift && ctrl && alt
         }
     }
+
+    /// A key event that this key press [matches](Self::matches).
+    pub fn to_key_event(&self) -> KeyEvent {
+        let mut modifiers = KeyModifiers::NONE;
+        if self.shift {
+            modifiers |= KeyModifiers::SHIFT;
+        }
+        if self.ctrl {
+            modifiers |= KeyModifiers::CONTROL;
+        }
+        if self.alt {
+            modifiers |= KeyModifiers::ALT;
+        }
+        KeyEvent::new(self.code, modifiers)
+    }
 }
 
 impl FromStr for KeyPress {
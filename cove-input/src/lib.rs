@@ -1,7 +1,8 @@
 mod keys;
 
-use std::io;
+use std::process::Command;
 use std::sync::Arc;
+use std::{fs, io};
 
 pub use cove_macro::KeyGroup;
 use crossterm::event::{Event, KeyEvent, KeyEventKind};
@@ -21,6 +22,8 @@ pub trait KeyGroup {
     const DESCRIPTION: &'static str;
 
     fn bindings(&self) -> Vec<KeyBindingInfo<'_>>;
+
+    fn binding_mut(&mut self, name: &str) -> Option<&mut KeyBinding>;
 }
 
 pub struct KeyGroupInfo<'a> {
@@ -43,6 +46,7 @@ pub struct InputEvent<'a> {
     event: Event,
     terminal: &'a mut Terminal,
     crossterm_lock: Arc<FairMutex<()>>,
+    editor_command: Option<&'a str>,
 }
 
 impl<'a> InputEvent<'a> {
@@ -55,9 +59,20 @@ impl<'a> InputEvent<'a> {
             event,
             terminal,
             crossterm_lock,
+            editor_command: None,
         }
     }
 
+    /// Use a custom command instead of `$EDITOR` in [`Self::prompt`].
+    ///
+    /// The command is split into a program and its arguments like a shell
+    /// would split it, so arguments may be quoted. The path of the file to edit
+    /// is appended as the last argument.
+    pub fn with_editor_command(mut self, command: Option<&'a str>) -> Self {
+        self.editor_command = command;
+        self
+    }
+
     /// If the current event represents a key press, returns the [`KeyEvent`]
     /// associated with that key press.
     pub fn key_event(&self) -> Option<KeyEvent> {
@@ -94,10 +109,38 @@ impl<'a> InputEvent<'a> {
     pub fn prompt(&mut self, initial_text: &str) -> io::Result<String> {
         let guard = self.crossterm_lock.lock();
         self.terminal.suspend().expect("failed to suspend");
-        let content = edit::edit(initial_text);
+        let content = match self.editor_command {
+            Some(command) => edit_with_command(command, initial_text),
+            None => edit::edit(initial_text),
+        };
         self.terminal.unsuspend().expect("fauled to unsuspend");
         drop(guard);
 
         content
     }
 }
+
+fn edit_with_command(command: &str, initial_text: &str) -> io::Result<String> {
+    let args = shell_words::split(command)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let Some((program, args)) = args.split_first() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "editor command is empty",
+        ));
+    };
+
+    // The file is created with a unique name and removed once it is dropped.
+    let file = tempfile::Builder::new()
+        .prefix("cove-")
+        .suffix(".txt")
+        .tempfile()?;
+    fs::write(file.path(), initial_text)?;
+
+    let status = Command::new(program).args(args).arg(file.path()).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("editor exited with {status}")));
+    }
+
+    fs::read_to_string(file.path())
+}
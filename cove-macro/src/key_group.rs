@@ -23,6 +23,7 @@ pub fn derive_impl(input: DeriveInput) -> syn::Result<TokenStream> {
     let description = docstring.strip_suffix('.').unwrap_or(&docstring);
 
     let mut bindings = vec![];
+    let mut bindings_mut = vec![];
     let mut defaults = vec![];
     for field in &data.fields {
         if let Some(field_ident) = &field.ident {
@@ -46,6 +47,10 @@ pub fn derive_impl(input: DeriveInput) -> syn::Result<TokenStream> {
                 }
             });
 
+            bindings_mut.push(quote! {
+                #field_name => Some(&mut self.#field_ident),
+            });
+
             defaults.push(quote! {
                 #field_ident: #default_value,
             });
@@ -62,6 +67,13 @@ pub fn derive_impl(input: DeriveInput) -> syn::Result<TokenStream> {
                     #( #bindings, )*
                 ]
             }
+
+            fn binding_mut(&mut self, name: &str) -> Option<&mut ::cove_input::KeyBinding> {
+                match name {
+                    #( #bindings_mut )*
+                    _ => None,
+                }
+            }
         }
 
         impl Default for #ident {
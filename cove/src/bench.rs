@@ -0,0 +1,173 @@
+//! Measure how long the core vault queries take.
+//!
+//! This is meant for comparing query performance across versions of cove,
+//! rusqlite and sqlite. The queries are run either against a room from the
+//! vault or against a generated room in an in-memory vault.
+
+use std::time::{Duration, Instant};
+
+use euphoxide::api::{Message, MessageId, SessionId, SessionView, Snowflake, Time, UserId};
+
+use crate::vault::{EuphRoomVault, EuphVault, RoomIdentifier};
+
+/// Messages per generated tree.
+const TREE_SIZE: u64 = 20;
+/// Number of message ids sampled for the path query.
+const SAMPLE_SIZE: usize = 100;
+
+#[derive(Debug, clap::Parser)]
+pub struct Args {
+    /// Room to run the queries against.
+    ///
+    /// If not specified, a room is generated in an in-memory vault instead.
+    pub room: Option<String>,
+
+    /// Domain to resolve the room name with.
+    #[arg(long, short, default_value = "euphoria.leet.nu")]
+    domain: String,
+
+    /// How often to run each query.
+    #[arg(long, short, default_value_t = 1000)]
+    iterations: usize,
+
+    /// Amount of messages to generate if no room is specified.
+    #[arg(long, short, default_value_t = 100_000)]
+    generate: u64,
+}
+
+fn generated_msg(index: u64) -> Message {
+    // Every tree is a binary tree, which gives it a decent mix of depth and
+    // breadth. Ids start at 1 because 0 is not a valid id.
+    let root = index - index % TREE_SIZE;
+    let offset = index % TREE_SIZE;
+    let parent = if offset == 0 {
+        None
+    } else {
+        Some(MessageId(Snowflake(root + (offset - 1) / 2 + 1)))
+    };
+
+    Message {
+        id: MessageId(Snowflake(index + 1)),
+        parent,
+        previous_edit_id: None,
+        time: Time(index as i64),
+        content: format!("Message number {index}"),
+        encryption_key_id: None,
+        edited: None,
+        deleted: None,
+        truncated: false,
+        sender: SessionView {
+            id: UserId(format!("agent:{}", index % 7)),
+            name: format!("nick{}", index % 7),
+            server_id: "bench".to_string(),
+            server_era: "bench".to_string(),
+            session_id: SessionId(format!("{}", index % 7)),
+            is_staff: false,
+            is_manager: false,
+            client_address: None,
+            real_client_address: None,
+        },
+    }
+}
+
+async fn generate(vault: &EuphRoomVault, amount: u64) -> anyhow::Result<()> {
+    eprintln!("Generating {amount} messages");
+    vault.join(Time(0)).await?;
+    let msgs = (0..amount).map(generated_msg).collect::<Vec<_>>();
+    vault.add_msgs(msgs, None, None, true).await?;
+    Ok(())
+}
+
+async fn sample_roots(vault: &EuphRoomVault) -> anyhow::Result<Vec<MessageId>> {
+    let mut roots = vec![];
+    let mut root = vault.last_root_id().await?;
+    while let Some(id) = root {
+        if roots.len() >= SAMPLE_SIZE {
+            break;
+        }
+        roots.push(id);
+        root = vault.prev_root_id(id).await?;
+    }
+    Ok(roots)
+}
+
+async fn sample_msgs(vault: &EuphRoomVault) -> anyhow::Result<Vec<MessageId>> {
+    let mut msgs = vec![];
+    let mut msg = vault.newest_msg_id().await?;
+    while let Some(id) = msg {
+        if msgs.len() >= SAMPLE_SIZE {
+            break;
+        }
+        msgs.push(id);
+        msg = vault.older_msg_id(id).await?;
+    }
+    Ok(msgs)
+}
+
+fn print_timings(name: &str, mut timings: Vec<Duration>) {
+    if timings.is_empty() {
+        println!("{name:<8} no samples");
+        return;
+    }
+
+    timings.sort_unstable();
+    let percentile = |p: usize| {
+        let idx = (timings.len() - 1) * p / 100;
+        timings[idx].as_secs_f64() * 1000.0
+    };
+    println!(
+        "{name:<8} min {:>9.3}ms  p50 {:>9.3}ms  p90 {:>9.3}ms  p99 {:>9.3}ms  max {:>9.3}ms",
+        percentile(0),
+        percentile(50),
+        percentile(90),
+        percentile(99),
+        percentile(100),
+    );
+}
+
+pub async fn bench(vault: &EuphVault, args: Args) -> anyhow::Result<()> {
+    let room = match &args.room {
+        Some(name) => RoomIdentifier::new(args.domain.clone(), name.clone()),
+        None => RoomIdentifier::new("bench".to_string(), "bench".to_string()),
+    };
+    let vault = vault.room(room);
+    let limit = Some(vault.vault().vault().max_tree_size());
+
+    if args.room.is_none() {
+        generate(&vault, args.generate).await?;
+    }
+
+    let roots = sample_roots(&vault).await?;
+    let msgs = sample_msgs(&vault).await?;
+    if roots.is_empty() || msgs.is_empty() {
+        anyhow::bail!("room contains no messages");
+    }
+
+    eprintln!("Running each query {} times", args.iterations);
+
+    let mut tree_timings = vec![];
+    let mut path_timings = vec![];
+    let mut root_timings = vec![];
+    for i in 0..args.iterations {
+        let root = roots[i % roots.len()];
+        let msg = msgs[i % msgs.len()];
+
+        let start = Instant::now();
+        vault.tree(root, limit).await?;
+        tree_timings.push(start.elapsed());
+
+        let start = Instant::now();
+        vault.path(msg).await?;
+        path_timings.push(start.elapsed());
+
+        let start = Instant::now();
+        vault.prev_root_id(root).await?;
+        root_timings.push(start.elapsed());
+    }
+
+    print_timings("tree", tree_timings);
+    print_timings("path", path_timings);
+    print_timings("root", root_timings);
+
+    Ok(())
+}
@@ -0,0 +1,19 @@
+//! Access to the system clipboard.
+
+use arboard::Clipboard;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+/// On some platforms (X11 in particular), the clipboard contents are only
+/// available for as long as the [`Clipboard`] that set them is alive, so it is
+/// kept around for the lifetime of the program.
+static CLIPBOARD: Lazy<Mutex<Option<Clipboard>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn copy(text: String) -> Result<(), arboard::Error> {
+    let mut guard = CLIPBOARD.lock();
+    let clipboard = match &mut *guard {
+        Some(clipboard) => clipboard,
+        None => guard.insert(Clipboard::new()?),
+    };
+    clipboard.set_text(text)
+}
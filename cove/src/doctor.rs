@@ -0,0 +1,216 @@
+//! Check the environment cove runs in for common problems.
+
+use std::env;
+use std::io::{self, IsTerminal};
+use std::time::Duration;
+
+use cove_config::Config;
+use directories::ProjectDirs;
+use tokio::net::TcpStream;
+
+use crate::util;
+use crate::vault::{self, MIGRATIONS};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct Report {
+    warnings: usize,
+    failures: usize,
+}
+
+impl Report {
+    fn pass(&mut self, what: impl AsRef<str>) {
+        println!("[ ok ] {}", what.as_ref());
+    }
+
+    fn warn(&mut self, what: impl AsRef<str>, hint: impl AsRef<str>) {
+        self.warnings += 1;
+        println!("[warn] {}", what.as_ref());
+        println!("       {}", hint.as_ref());
+    }
+
+    fn fail(&mut self, what: impl AsRef<str>, hint: impl AsRef<str>) {
+        self.failures += 1;
+        println!("[fail] {}", what.as_ref());
+        println!("       {}", hint.as_ref());
+    }
+}
+
+fn check_config(report: &mut Report, config: Result<Config, cove_config::Error>) -> Config {
+    match config {
+        Ok(config) => {
+            report.pass("Config file is valid");
+            config
+        }
+        Err(err) => {
+            report.fail(
+                format!("Config file is invalid: {:#}", anyhow::Error::from(err)),
+                "Fix the config file. Run `cove help-config` to see all options. \
+                 The remaining checks use the default config.",
+            );
+            Config::default()
+        }
+    }
+}
+
+fn check_time_zone(report: &mut Report, config: &Config) {
+    match util::load_time_zone(config.time_zone_ref(), config.time_zone_fallback_ref()) {
+        Ok(tz) => report.pass(format!(
+            "Time zone resolves to {}",
+            tz.iana_name().unwrap_or("a custom time zone")
+        )),
+        Err(err) => report.fail(
+            format!("Time zone could not be loaded: {err}"),
+            "Set the `time_zone` and `time_zone_fallback` config options or `TZ` \
+             environment variable to valid TZ strings.",
+        ),
+    }
+}
+
+fn check_vault(report: &mut Report, config: &Config, dirs: &ProjectDirs) {
+    if config.ephemeral {
+        report.pass("Vault is not used in ephemeral mode");
+        return;
+    }
+
+    // The vault is inspected through a plain read-only connection so that the
+    // check neither migrates nor otherwise modifies it.
+    let path = crate::data_dir(config, dirs).join("vault.db");
+    if !path.exists() {
+        report.pass("No vault exists yet, it will be created on first start");
+        return;
+    }
+
+    let conn = match vault::open_read_only(&path, config) {
+        Ok(conn) => conn,
+        Err(err) => {
+            report.fail(
+                format!("Vault could not be opened: {err}"),
+                "Make sure no other instance of cove is running and the vault is readable.",
+            );
+            return;
+        }
+    };
+
+    let expected = MIGRATIONS.len();
+    match vault::schema_version(&conn) {
+        Ok(version) if version == expected => {
+            report.pass(format!("Vault schema is up to date (version {version})"));
+        }
+        Ok(version) if version < expected => report.warn(
+            format!("Vault schema version is {version}, expected {expected}"),
+            "The vault will be migrated the next time cove starts.",
+        ),
+        Ok(version) => report.fail(
+            format!("Vault schema version is {version}, expected {expected}"),
+            "The vault was probably created by a newer version of cove. Update cove.",
+        ),
+        Err(err) => report.fail(
+            format!("Vault schema version could not be read: {err}"),
+            "The vault may be damaged.",
+        ),
+    }
+
+    match vault::integrity_check(&conn) {
+        Ok(problems) if problems == ["ok"] => report.pass("Vault passes integrity check"),
+        Ok(problems) => report.fail(
+            format!(
+                "Vault integrity check found problems: {}",
+                problems.join("; ")
+            ),
+            "Restore the vault from a backup or delete it to start fresh.",
+        ),
+        Err(err) => report.fail(
+            format!("Vault integrity check could not be run: {err}"),
+            "The vault may be damaged.",
+        ),
+    }
+}
+
+fn check_terminal(report: &mut Report, config: &Config) {
+    if io::stdout().is_terminal() {
+        report.pass("Standard output is a terminal");
+    } else {
+        report.warn(
+            "Standard output is not a terminal",
+            "The interactive client needs a terminal to run in.",
+        );
+    }
+
+    match env::var("TERM") {
+        Ok(term) if term == "dumb" => report.warn(
+            "Terminal does not support colors (TERM=dumb)",
+            "Use a terminal emulator with color support.",
+        ),
+        Ok(term) => report.pass(format!("Terminal type is {term}")),
+        Err(_) => report.warn(
+            "TERM environment variable is not set",
+            "Colors and other terminal features may not work as expected.",
+        ),
+    }
+
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .find_map(|var| env::var(var).ok().filter(|value| !value.is_empty()));
+    match locale {
+        Some(locale) if locale.to_lowercase().replace('-', "").contains("utf8") => {
+            report.pass(format!("Locale uses UTF-8 ({locale})"));
+        }
+        _ => report.warn(
+            "Locale does not seem to use UTF-8",
+            "Non-ASCII characters may be displayed incorrectly.",
+        ),
+    }
+
+    if config.measure_widths {
+        report.pass("Character widths are measured");
+    } else {
+        report.warn(
+            "Character widths are guessed",
+            "If emoji or other wide characters mess up the layout, \
+             enable the `measure_widths` config option.",
+        );
+    }
+}
+
+async fn check_servers(report: &mut Report, config: &Config) {
+    let mut domains = config.euph.servers.keys().collect::<Vec<_>>();
+    domains.sort_unstable();
+
+    for domain in domains {
+        let connect = TcpStream::connect((domain.as_str(), 443));
+        match tokio::time::timeout(CONNECT_TIMEOUT, connect).await {
+            Ok(Ok(_)) => report.pass(format!("Server {domain} is reachable")),
+            Ok(Err(err)) => report.fail(
+                format!("Server {domain} is not reachable: {err}"),
+                "Check your network connection and the domain name.",
+            ),
+            Err(_) => report.fail(
+                format!("Server {domain} did not respond in time"),
+                "Check your network connection and the domain name.",
+            ),
+        }
+    }
+}
+
+pub async fn doctor(
+    config: Result<Config, cove_config::Error>,
+    dirs: &ProjectDirs,
+) -> anyhow::Result<()> {
+    let mut report = Report::default();
+
+    let config = check_config(&mut report, config);
+    check_time_zone(&mut report, &config);
+    check_vault(&mut report, &config, dirs);
+    check_terminal(&mut report, &config);
+    check_servers(&mut report, &config).await;
+
+    println!();
+    println!("{} failures, {} warnings", report.failures, report.warnings);
+
+    if report.failures > 0 {
+        anyhow::bail!("some checks failed");
+    }
+    Ok(())
+}
@@ -1,7 +1,13 @@
+mod emoji;
+mod metrics;
+mod notify;
 mod room;
 mod small_message;
 mod util;
 
+pub use emoji::*;
+pub use metrics::*;
+pub use notify::*;
 pub use room::*;
 pub use small_message::*;
 pub use util::*;
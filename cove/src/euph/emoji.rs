@@ -0,0 +1,65 @@
+//! Conversion from unicode emoji back to euphoria's emoji shortcodes.
+
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::util::EMOJI;
+
+/// Unicode emoji and the shortcode they are replaced with, built from the same
+/// table that is used for replacing shortcodes with emoji.
+struct Shortcodes {
+    names: HashMap<&'static str, &'static str>,
+    regex: Regex,
+}
+
+impl Shortcodes {
+    fn load() -> Self {
+        let mut names = HashMap::new();
+        for (name, emoji) in &EMOJI.0 {
+            let Some(emoji) = emoji else { continue };
+            // Multiple shortcodes may stand for the same emoji. Prefer the
+            // shortest one so that the result doesn't depend on hash map order.
+            names
+                .entry(emoji.as_str())
+                .and_modify(|prev: &mut &str| {
+                    if (name.len(), name.as_str()) < (prev.len(), *prev) {
+                        *prev = name;
+                    }
+                })
+                .or_insert(name.as_str());
+        }
+
+        // Longer emoji must be tried first since many emoji are made up of
+        // other emoji combined with modifiers.
+        let mut emoji = names.keys().copied().collect::<Vec<_>>();
+        emoji.sort_unstable_by_key(|e| Reverse(e.len()));
+        let pattern = emoji
+            .into_iter()
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join("|");
+        let regex = Regex::new(&pattern).expect("escaped emoji form a valid regex");
+
+        Self { names, regex }
+    }
+}
+
+static SHORTCODES: Lazy<Shortcodes> = Lazy::new(Shortcodes::load);
+
+/// Replace unicode emoji with their shortcodes, for example `😄` with
+/// `:smile:`. Unknown emoji are left as they are.
+pub fn to_shortcodes(text: &str) -> Cow<'_, str> {
+    SHORTCODES
+        .regex
+        .replace_all(text, |caps: &regex::Captures<'_>| {
+            let emoji = &caps[0];
+            match SHORTCODES.names.get(emoji) {
+                Some(name) => format!(":{name}:"),
+                None => emoji.to_string(),
+            }
+        })
+}
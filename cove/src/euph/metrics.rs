@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+
+use euphoxide::api::{Data, SendEvent, SendReply};
+use euphoxide::bot::instance::Event;
+
+/// Cumulative connection statistics for a single room.
+///
+/// The counters live for as long as the process does and survive reconnects.
+/// They are not persisted in the vault.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Number of packets received from the server.
+    pub packets: usize,
+    /// Number of messages received, including those in snapshots and logs.
+    pub msgs: usize,
+    /// Total size of the content of all received messages.
+    pub content_bytes: usize,
+    /// Number of times a connection was successfully established.
+    pub connects: usize,
+    connected_since: Option<Instant>,
+    connected_for: Duration,
+}
+
+impl Metrics {
+    /// Number of times the connection was established after the first time.
+    pub fn reconnects(&self) -> usize {
+        self.connects.saturating_sub(1)
+    }
+
+    /// Total time spent connected, including the current connection.
+    pub fn uptime(&self) -> Duration {
+        let current = self.connected_since.map(|s| s.elapsed());
+        self.connected_for + current.unwrap_or_default()
+    }
+
+    pub fn disconnected(&mut self) {
+        if let Some(since) = self.connected_since.take() {
+            self.connected_for += since.elapsed();
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::Connecting(_) => {}
+            Event::Connected(_, _) => {
+                self.disconnected();
+                self.connects += 1;
+                self.connected_since = Some(Instant::now());
+            }
+            Event::Packet(_, packet, _) => {
+                self.packets += 1;
+                if let Ok(data) = &packet.content {
+                    self.on_data(data);
+                }
+            }
+            Event::Disconnected(_) | Event::Stopped(_) => self.disconnected(),
+        }
+    }
+
+    fn on_data(&mut self, data: &Data) {
+        let msgs = match data {
+            Data::SendEvent(SendEvent(msg)) | Data::SendReply(SendReply(msg)) => {
+                std::slice::from_ref(msg)
+            }
+            Data::SnapshotEvent(d) => &d.log[..],
+            Data::LogReply(d) => &d.log[..],
+            _ => return,
+        };
+
+        self.msgs += msgs.len();
+        self.content_bytes += msgs.iter().map(|m| m.content.len()).sum::<usize>();
+    }
+}
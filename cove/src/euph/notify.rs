@@ -0,0 +1,68 @@
+//! Run the `notify_command` for messages that need the user's attention.
+
+use std::process::Stdio;
+
+use log::warn;
+use tokio::process::Command;
+
+use super::small_message::nick_char;
+use super::util::EMOJI;
+
+/// Whether a message mentions the nick.
+///
+/// Nicks are compared the way euphoria does, so differences in case and
+/// whitespace don't matter. The mention must end where the nick ends, meaning
+/// `@foobar` doesn't mention `foo`.
+pub fn mentions(content: &str, nick: &str) -> bool {
+    let nick = euphoxide::nick::normalize(&EMOJI, nick);
+    if nick.is_empty() {
+        return false;
+    }
+
+    content.match_indices('@').any(|(i, _)| {
+        let rest = &content[i + 1..];
+        let end = rest.find(|c| !nick_char(c)).unwrap_or(rest.len());
+        euphoxide::nick::normalize(&EMOJI, &rest[..end]) == nick
+    })
+}
+
+/// Run the command in the background.
+///
+/// The command is split at whitespace, then `{room}`, `{nick}` and `{content}`
+/// are replaced in each argument. This way, the placeholders are always passed
+/// as a single argument each, no matter their contents.
+pub fn notify(command: &str, room: &str, nick: &str, content: &str) {
+    let mut args = command.split_whitespace().map(|arg| {
+        arg.replace("{room}", room)
+            .replace("{nick}", nick)
+            .replace("{content}", content)
+    });
+    let Some(program) = args.next() else {
+        return;
+    };
+
+    // The command must not interfere with the terminal cove is drawing to.
+    let child = Command::new(&program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => {
+            warn!("Failed to run notify command {program:?}: {err}");
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        match child.wait().await {
+            Ok(status) if !status.success() => {
+                warn!("Notify command {program:?} exited with {status}");
+            }
+            Ok(_) => {}
+            Err(err) => warn!("Failed to wait for notify command {program:?}: {err}"),
+        }
+    });
+}
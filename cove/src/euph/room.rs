@@ -1,36 +1,49 @@
 // TODO Remove rl2dev-specific code
 
 use std::convert::Infallible;
-use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use euphoxide::api::packet::ParsedPacket;
 use euphoxide::api::{
-    Auth, AuthOption, Data, Log, Login, Logout, MessageId, Nick, Send, SendEvent, SendReply, Time,
-    UserId,
+    Auth, AuthOption, Data, Log, Login, Logout, MessageId, Nick, Ping, PingReply, Send, SendEvent,
+    SendReply, Time, UserId,
 };
 use euphoxide::bot::instance::{ConnSnapshot, Event, Instance, InstanceConfig};
 use euphoxide::conn::{self, ConnTx, Joined};
 use log::{debug, error, info, warn};
+use parking_lot::Mutex;
 use tokio::select;
 use tokio::sync::oneshot;
 
 use crate::macros::logging_unwrap;
-use crate::vault::EuphRoomVault;
+use crate::vault::{EuphRoomVault, RoomIdentifier};
 
 const LOG_INTERVAL: Duration = Duration::from_secs(10);
 
+/// Delay before reconnecting after the first failure. It doubles with every
+/// consecutive failure.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+type OnEvent = Arc<dyn Fn(Event) + std::marker::Send + Sync>;
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum State {
-    Disconnected,
+    /// Waiting to reconnect. Contains the time of the next connection attempt,
+    /// if known.
+    Disconnected(Option<Instant>),
     Connecting,
-    Connected(ConnTx, conn::State),
+    /// Also contains the round-trip time of the most recent ping, if known.
+    Connected(ConnTx, conn::State, Option<Duration>),
     Stopped,
 }
 
 impl State {
     pub fn conn_tx(&self) -> Option<&ConnTx> {
-        if let Self::Connected(conn_tx, _) = self {
+        if let Self::Connected(conn_tx, _, _) = self {
             Some(conn_tx)
         } else {
             None
@@ -39,10 +52,40 @@ impl State {
 
     pub fn joined(&self) -> Option<&Joined> {
         match self {
-            Self::Connected(_, conn::State::Joined(joined)) => Some(joined),
+            Self::Connected(_, conn::State::Joined(joined), _) => Some(joined),
+            _ => None,
+        }
+    }
+
+    /// Round-trip time of the most recent ping while connected.
+    pub fn latency(&self) -> Option<Duration> {
+        match self {
+            Self::Connected(_, _, latency) => *latency,
             _ => None,
         }
     }
+
+    /// Time until the next connection attempt while disconnected.
+    pub fn retry_in(&self) -> Option<Duration> {
+        match self {
+            Self::Disconnected(Some(retry_at)) => {
+                Some(retry_at.saturating_duration_since(Instant::now()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Progress of downloading older messages into the vault.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backfill {
+    /// Filling the gap between the most recent span and older messages that
+    /// are already known. Contains the fraction of the gap that has been
+    /// downloaded so far.
+    Gap(f32),
+    /// Downloading messages towards the start of the room history, whose
+    /// position is unknown.
+    History,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -51,14 +94,28 @@ pub enum Error {
     NotConnected,
 }
 
-#[derive(Debug)]
 pub struct Room {
     vault: EuphRoomVault,
     ephemeral: bool,
 
-    instance: Instance,
+    /// `None` while waiting to reconnect. The instance is shared with the task
+    /// that recreates it after the reconnect delay.
+    instance: Arc<Mutex<Option<Instance>>>,
+    instance_config: InstanceConfig,
+    /// Source of instance names, shared between all rooms of a server. Every
+    /// new instance gets a fresh name so events of old instances that haven't
+    /// shut down yet can be told apart from those of the current one.
+    instance_ids: Arc<AtomicUsize>,
+    on_event: OnEvent,
     state: State,
 
+    /// Connection attempts that failed since the room was last joined.
+    failures: u32,
+
+    /// `Some` while waiting to reconnect. Set to `None` to drop the sender and
+    /// cancel the reconnect.
+    reconnect_canary: Option<oneshot::Sender<Infallible>>,
+
     /// `None` before any `snapshot-event`, then either `Some(None)` or
     /// `Some(Some(id))`. Reset whenever connection is lost.
     last_msg_id: Option<Option<MessageId>>,
@@ -66,10 +123,25 @@ pub struct Room {
     /// `Some` while `Self::regularly_request_logs` is running. Set to `None` to
     /// drop the sender and stop the task.
     log_request_canary: Option<oneshot::Sender<Infallible>>,
+
+    /// Payload and send time of the ping we're waiting on a reply for.
+    ping_sent: Option<(Time, Instant)>,
+    /// Round-trip time of the most recent ping. Reset whenever connection is
+    /// lost.
+    latency: Option<Duration>,
+
+    /// `None` if no backfilling is necessary or the room is ephemeral.
+    /// Updated whenever new messages from the room log arrive.
+    backfill: Option<Backfill>,
 }
 
 impl Room {
-    pub fn new<F>(vault: EuphRoomVault, instance_config: InstanceConfig, on_event: F) -> Self
+    pub fn new<F>(
+        vault: EuphRoomVault,
+        instance_config: InstanceConfig,
+        instance_ids: Arc<AtomicUsize>,
+        on_event: F,
+    ) -> Self
     where
         F: Fn(Event) + std::marker::Send + Sync + 'static,
     {
@@ -80,28 +152,58 @@ impl Room {
         let is_rl2dev = vault.room().domain == "euphoria.io" && vault.room().name == "rl2dev";
         let ephemeral = vault.vault().vault().ephemeral() || is_rl2dev;
 
+        let instance_config =
+            instance_config.name(Self::instance_name(vault.room(), &instance_ids));
+        let on_event: OnEvent = Arc::new(on_event);
+        let instance = Self::build_instance(instance_config.clone(), on_event.clone());
+
         Self {
             vault,
             ephemeral,
-            instance: instance_config.build(on_event),
-            state: State::Disconnected,
+            instance: Arc::new(Mutex::new(Some(instance))),
+            instance_config,
+            instance_ids,
+            on_event,
+            state: State::Disconnected(None),
+            failures: 0,
+            reconnect_canary: None,
             last_msg_id: None,
             log_request_canary: None,
+            ping_sent: None,
+            latency: None,
+            backfill: None,
         }
     }
 
+    fn instance_name(room: &RoomIdentifier, instance_ids: &AtomicUsize) -> String {
+        let id = instance_ids.fetch_add(1, Ordering::Relaxed);
+        format!("{room:?}-{id}")
+    }
+
+    fn build_instance(instance_config: InstanceConfig, on_event: OnEvent) -> Instance {
+        instance_config.build(move |event| on_event(event))
+    }
+
     pub fn stopped(&self) -> bool {
-        self.instance.stopped()
+        match &*self.instance.lock() {
+            Some(instance) => instance.stopped(),
+            // Waiting to reconnect
+            None => false,
+        }
     }
 
-    pub fn instance(&self) -> &Instance {
-        &self.instance
+    pub fn instance_config(&self) -> &InstanceConfig {
+        &self.instance_config
     }
 
     pub fn state(&self) -> &State {
         &self.state
     }
 
+    pub fn backfill(&self) -> Option<Backfill> {
+        self.backfill
+    }
+
     fn conn_tx(&self) -> Result<&ConnTx, Error> {
         self.state.conn_tx().ok_or(Error::NotConnected)
     }
@@ -110,10 +212,13 @@ impl Room {
         match event {
             Event::Connecting(_) => {
                 self.state = State::Connecting;
+                self.reconnect_canary = None;
 
                 // Juuust to make sure
                 self.last_msg_id = None;
                 self.log_request_canary = None;
+                self.ping_sent = None;
+                self.latency = None;
             }
             Event::Connected(_, ConnSnapshot { conn_tx, state }) => {
                 if !self.ephemeral {
@@ -121,7 +226,7 @@ impl Room {
                     self.log_request_canary = Some(tx);
                     let vault_clone = self.vault.clone();
                     let conn_tx_clone = conn_tx.clone();
-                    debug!("{}: spawning log request task", self.instance.config().room);
+                    debug!("{}: spawning log request task", self.instance_config.room);
                     tokio::task::spawn(async move {
                         select! {
                             _ = rx => {},
@@ -130,21 +235,26 @@ impl Room {
                     });
                 }
 
-                self.state = State::Connected(conn_tx, state);
+                self.state = State::Connected(conn_tx, state, None);
 
-                let cookies = &*self.instance.config().server.cookies;
+                let cookies = &*self.instance_config.server.cookies;
                 let cookies = cookies.lock().unwrap().clone();
                 let domain = self.vault.room().domain.clone();
                 logging_unwrap!(self.vault.vault().set_cookies(domain, cookies).await);
             }
             Event::Packet(_, packet, ConnSnapshot { conn_tx, state }) => {
-                self.state = State::Connected(conn_tx, state);
+                self.state = State::Connected(conn_tx, state, self.latency);
                 self.on_packet(packet).await;
             }
             Event::Disconnected(_) => {
-                self.state = State::Disconnected;
                 self.last_msg_id = None;
                 self.log_request_canary = None;
+                self.ping_sent = None;
+                self.latency = None;
+                self.reconnect_with_backoff();
+            }
+            Event::Stopped(_) if self.reconnect_canary.is_some() => {
+                // The instance was stopped by us in order to reconnect later.
             }
             Event::Stopped(_) => {
                 self.state = State::Stopped;
@@ -152,6 +262,47 @@ impl Room {
         }
     }
 
+    /// Stop the current instance and start a new one after a delay that grows
+    /// with every consecutive failure.
+    fn reconnect_with_backoff(&mut self) {
+        let factor = 2_u32.saturating_pow(self.failures);
+        let delay = RECONNECT_DELAY
+            .saturating_mul(factor)
+            .min(MAX_RECONNECT_DELAY);
+        self.failures = self.failures.saturating_add(1);
+
+        info!(
+            "{}: reconnecting in {}s",
+            self.instance_config.room,
+            delay.as_secs()
+        );
+        self.state = State::Disconnected(Some(Instant::now() + delay));
+
+        let (tx, rx) = oneshot::channel();
+        self.reconnect_canary = Some(tx);
+
+        // Stop the instance so it doesn't reconnect by itself
+        self.instance.lock().take();
+
+        // The new instance gets a new name so that events the old instance may
+        // still emit are ignored.
+        let name = Self::instance_name(self.vault.room(), &self.instance_ids);
+        self.instance_config = self.instance_config.clone().name(name);
+
+        let instance = self.instance.clone();
+        let instance_config = self.instance_config.clone();
+        let on_event = self.on_event.clone();
+        tokio::task::spawn(async move {
+            select! {
+                _ = rx => {},
+                _ = tokio::time::sleep(delay) => {
+                    let new = Self::build_instance(instance_config, on_event);
+                    *instance.lock() = Some(new);
+                }
+            }
+        });
+    }
+
     async fn regularly_request_logs(vault: EuphRoomVault, conn_tx: ConnTx) {
         // TODO Make log downloading smarter
 
@@ -206,8 +357,34 @@ impl Room {
         // `LogReply`s, so we don't need to do anything special here.
     }
 
+    async fn backfill_status(vault: &EuphRoomVault) -> Option<Backfill> {
+        let (start, end) = match logging_unwrap!(vault.last_span().await)? {
+            (Some(start), Some(end)) => (start, end),
+            _ => return None, // Already at top of room history
+        };
+
+        let Some(older) = logging_unwrap!(vault.older_msg_id(start).await) else {
+            return Some(Backfill::History);
+        };
+
+        // Message ids are snowflakes and thus roughly ordered by time, so the
+        // distance between them is a decent measure of progress.
+        let done = end.0 .0.saturating_sub(start.0 .0) as f64;
+        let total = end.0 .0.saturating_sub(older.0 .0) as f64;
+        if total <= 0.0 {
+            return None;
+        }
+        Some(Backfill::Gap((done / total) as f32))
+    }
+
+    async fn update_backfill(&mut self) {
+        if !self.ephemeral {
+            self.backfill = Self::backfill_status(&self.vault).await;
+        }
+    }
+
     fn own_user_id(&self) -> Option<UserId> {
-        if let State::Connected(_, state) = &self.state {
+        if let State::Connected(_, state, _) = &self.state {
             Some(match state {
                 conn::State::Joining(joining) => joining.hello.as_ref()?.session.id.clone(),
                 conn::State::Joined(joined) => joined.session.id.clone(),
@@ -217,8 +394,36 @@ impl Room {
         }
     }
 
+    /// Measure latency by sending a ping of our own whenever the server pings
+    /// us.
+    fn send_ping(&mut self) {
+        let Ok(conn_tx) = self.conn_tx() else {
+            return;
+        };
+        let time = Time::now();
+        conn_tx.send_only(Ping { time });
+        self.ping_sent = Some((time, Instant::now()));
+    }
+
+    fn on_ping_reply(&mut self, time: Time) {
+        let Some((sent_time, sent_at)) = self.ping_sent else {
+            return;
+        };
+        if time != sent_time {
+            // Reply to a ping sent by euphoxide itself
+            return;
+        }
+
+        let latency = sent_at.elapsed();
+        self.ping_sent = None;
+        self.latency = Some(latency);
+        if let State::Connected(_, _, state_latency) = &mut self.state {
+            *state_latency = Some(latency);
+        }
+    }
+
     async fn on_packet(&mut self, packet: ParsedPacket) {
-        let room_name = &self.instance.config().room;
+        let room_name = &self.instance_config.room;
         let Ok(data) = &packet.content else {
             return;
         };
@@ -243,7 +448,8 @@ impl Room {
             Data::PartEvent(d) => {
                 debug!("{room_name}: {:?} left", d.0.name);
             }
-            Data::PingEvent(_) => {}
+            Data::PingEvent(_) => self.send_ping(),
+            Data::PingReply(PingReply { time: Some(time) }) => self.on_ping_reply(*time),
             Data::PmInitiateEvent(d) => {
                 // TODO Show info popup and automatically join PM room
                 info!(
@@ -264,20 +470,24 @@ impl Room {
             }
             Data::SnapshotEvent(d) => {
                 info!("{room_name}: successfully joined");
+                self.failures = 0;
                 logging_unwrap!(self.vault.join(Time::now()).await);
                 self.last_msg_id = Some(d.log.last().map(|m| m.id));
                 logging_unwrap!(
                     self.vault
-                        .add_msgs(d.log.clone(), None, self.own_user_id())
+                        .add_msgs(d.log.clone(), None, self.own_user_id(), false)
                         .await
                 );
+                self.update_backfill().await;
             }
             Data::LogReply(d) => {
+                let bulk = self.vault.vault().vault().bulk_backfill();
                 logging_unwrap!(
                     self.vault
-                        .add_msgs(d.log.clone(), d.before, self.own_user_id())
+                        .add_msgs(d.log.clone(), d.before, self.own_user_id(), bulk)
                         .await
                 );
+                self.update_backfill().await;
             }
             _ => {}
         }
@@ -1,16 +1,19 @@
+use std::borrow::Cow;
 use std::mem;
+use std::ops::Range;
 
+use cove_config::EmojiMode;
 use crossterm::style::Stylize;
-use euphoxide::api::{MessageId, Snowflake, Time};
+use euphoxide::api::{MessageId, Snowflake, Time, UserId};
 use jiff::Timestamp;
-use toss::{Style, Styled};
+use toss::{Style, Styled, WidthDb};
 
 use crate::store::Msg;
-use crate::ui::ChatMsg;
+use crate::ui::{ChatMsg, RenderOptions};
 
-use super::util;
+use super::util::{self, Highlight, NickColors};
 
-fn nick_char(ch: char) -> bool {
+pub(super) fn nick_char(ch: char) -> bool {
     // Closely following the heim mention regex:
     // https://github.com/euphoria-io/heim/blob/978c921063e6b06012fc8d16d9fbf1b3a0be1191/client/lib/stores/chat.js#L14-L15
     // `>` has been experimentally confirmed to delimit mentions as well.
@@ -37,6 +40,9 @@ struct Highlighter<'a> {
     content: &'a str,
     base_style: Style,
     exact: bool,
+    emoji: bool,
+    nick_colors: &'a NickColors,
+    highlights: Vec<(Range<usize>, &'a Highlight)>,
 
     span: Span,
     span_start: usize,
@@ -57,9 +63,9 @@ impl<'a> Highlighter<'a> {
 
         let text = &self.content[self.span_start..idx]; // Includes @
         self.result = mem::take(&mut self.result).and_then(if self.exact {
-            util::style_nick_exact(text, self.base_style)
+            util::style_nick_exact(text, self.base_style, self.nick_colors)
         } else {
-            util::style_nick(text, self.base_style)
+            util::style_nick(text, self.base_style, self.nick_colors)
         });
 
         self.span = Span::Nothing;
@@ -89,7 +95,7 @@ impl<'a> Highlighter<'a> {
         let name = &self.content[self.span_start + 1..idx];
         if let Some(replace) = util::EMOJI.get(name) {
             match replace {
-                Some(replace) if !self.exact => {
+                Some(replace) if !self.exact && self.emoji => {
                     self.result = mem::take(&mut self.result).then(replace, self.base_style);
                 }
                 _ => {
@@ -114,8 +120,27 @@ impl<'a> Highlighter<'a> {
             return;
         }
 
-        self.result =
-            mem::take(&mut self.result).then(&self.content[self.span_start..idx], self.base_style);
+        // Split the span into highlighted and non-highlighted parts
+        let mut start = self.span_start;
+        for (range, highlight) in &self.highlights {
+            if range.end <= start || range.start >= idx {
+                continue;
+            }
+            if range.start > start {
+                self.result = mem::take(&mut self.result)
+                    .then(&self.content[start..range.start], self.base_style);
+                start = range.start;
+            }
+            let end = range.end.min(idx);
+            self.result = mem::take(&mut self.result)
+                .then(&self.content[start..end], highlight.apply(self.base_style));
+            start = end;
+        }
+
+        if start < idx {
+            self.result =
+                mem::take(&mut self.result).then(&self.content[start..idx], self.base_style);
+        }
 
         self.span = Span::Nothing;
         self.span_start = idx;
@@ -177,18 +202,29 @@ impl<'a> Highlighter<'a> {
         self.room_or_mention_possible = !char.is_alphanumeric();
     }
 
-    fn highlight(content: &'a str, base_style: Style, exact: bool) -> Styled {
+    fn highlight(
+        content: &'a str,
+        base_style: Style,
+        exact: bool,
+        emoji: bool,
+        nick_colors: &'a NickColors,
+        highlights: &'a [Highlight],
+    ) -> Styled {
+        let content = if exact { content } else { content.trim() };
         let mut this = Self {
-            content: if exact { content } else { content.trim() },
+            content,
             base_style,
             exact,
+            emoji,
+            nick_colors,
+            highlights: util::highlight_ranges(content, highlights),
             span: Span::Nothing,
             span_start: 0,
             room_or_mention_possible: true,
             result: Styled::default(),
         };
 
-        for (idx, char) in (if exact { content } else { content.trim() }).char_indices() {
+        for (idx, char) in content.char_indices() {
             this.step(idx, char);
         }
 
@@ -198,8 +234,15 @@ impl<'a> Highlighter<'a> {
     }
 }
 
-fn highlight_content(content: &str, base_style: Style, exact: bool) -> Styled {
-    Highlighter::highlight(content, base_style, exact)
+fn highlight_content(
+    content: &str,
+    base_style: Style,
+    exact: bool,
+    emoji: bool,
+    nick_colors: &NickColors,
+    highlights: &[Highlight],
+) -> Styled {
+    Highlighter::highlight(content, base_style, exact, emoji, nick_colors, highlights)
 }
 
 #[derive(Debug, Clone)]
@@ -208,6 +251,7 @@ pub struct SmallMessage {
     pub parent: Option<MessageId>,
     pub time: Time,
     pub nick: String,
+    pub user_id: UserId,
     pub content: String,
     pub seen: bool,
 }
@@ -220,24 +264,58 @@ fn style_me() -> Style {
     Style::new().grey().italic()
 }
 
-fn styled_nick(nick: &str) -> Styled {
-    Styled::new_plain("[")
-        .and_then(util::style_nick(nick, Style::new()))
-        .then_plain("]")
+/// Surround an already styled nick with brackets.
+fn styled_nick(nick: Styled) -> Styled {
+    Styled::new_plain("[").and_then(nick).then_plain("]")
 }
 
-fn styled_nick_me(nick: &str) -> Styled {
-    let style = style_me();
-    Styled::new("*", style).and_then(util::style_nick(nick, style))
+/// Prefix an already styled nick with the `/me` marker. The nick should be
+/// styled based on [`style_me`].
+fn styled_nick_me(nick: Styled) -> Styled {
+    Styled::new("*", style_me()).and_then(nick)
+}
+
+/// Apply the parts of the emoji mode that change the text itself.
+fn emoji_text(content: &str, emoji: EmojiMode) -> Cow<'_, str> {
+    match emoji {
+        EmojiMode::Shortcode => super::to_shortcodes(content),
+        EmojiMode::Raw | EmojiMode::Unicode => Cow::Borrowed(content),
+    }
 }
 
-fn styled_content(content: &str) -> Styled {
-    highlight_content(content.trim(), Style::new(), false)
+fn styled_content(
+    content: &str,
+    style: Style,
+    emoji: EmojiMode,
+    nick_colors: &NickColors,
+    highlights: &[Highlight],
+) -> Styled {
+    highlight_content(
+        emoji_text(content, emoji).trim(),
+        style,
+        false,
+        emoji == EmojiMode::Unicode,
+        nick_colors,
+        highlights,
+    )
 }
 
-fn styled_content_me(content: &str) -> Styled {
+fn styled_content_me(
+    content: &str,
+    emoji: EmojiMode,
+    nick_colors: &NickColors,
+    highlights: &[Highlight],
+) -> Styled {
     let style = style_me();
-    highlight_content(content.trim(), style, false).then("*", style)
+    highlight_content(
+        emoji_text(content, emoji).trim(),
+        style,
+        false,
+        emoji == EmojiMode::Unicode,
+        nick_colors,
+        highlights,
+    )
+    .then("*", style)
 }
 
 fn styled_editor_content(content: &str) -> Styled {
@@ -246,7 +324,7 @@ fn styled_editor_content(content: &str) -> Styled {
     } else {
         Style::new()
     };
-    highlight_content(content, style, true)
+    highlight_content(content, style, true, false, &NickColors::new(), &[])
 }
 
 impl Msg for SmallMessage {
@@ -274,19 +352,66 @@ impl ChatMsg for SmallMessage {
         Some(self.time.as_timestamp())
     }
 
-    fn styled(&self) -> (Styled, Styled) {
-        Self::pseudo(&self.nick, &self.content)
+    fn styled(&self, options: &RenderOptions, widthdb: &mut WidthDb) -> (Styled, Styled) {
+        match as_me(&self.content) {
+            Some(content) if !options.plain_me => (
+                styled_nick_me(util::style_nick_truncated(
+                    widthdb,
+                    &self.nick,
+                    style_me(),
+                    options.max_nick_width,
+                    &options.nick_colors,
+                )),
+                styled_content_me(
+                    content,
+                    options.emoji,
+                    &options.nick_colors,
+                    &options.highlights,
+                ),
+            ),
+            _ => (
+                styled_nick(util::style_nick_truncated(
+                    widthdb,
+                    &self.nick,
+                    Style::new(),
+                    options.max_nick_width,
+                    &options.nick_colors,
+                )),
+                styled_content(
+                    &self.content,
+                    options.theme.text,
+                    options.emoji,
+                    &options.nick_colors,
+                    &options.highlights,
+                ),
+            ),
+        }
+    }
+
+    fn ignored(&self, options: &RenderOptions) -> bool {
+        !options.show_ignored && options.ignored_users.contains(&self.user_id.0)
     }
 
     fn edit(nick: &str, content: &str) -> (Styled, Styled) {
-        (styled_nick(nick), styled_editor_content(content))
+        let nick_colors = NickColors::new();
+        (
+            styled_nick(util::style_nick(nick, Style::new(), &nick_colors)),
+            styled_editor_content(content),
+        )
     }
 
     fn pseudo(nick: &str, content: &str) -> (Styled, Styled) {
+        let nick_colors = NickColors::new();
         if let Some(content) = as_me(content) {
-            (styled_nick_me(nick), styled_content_me(content))
+            (
+                styled_nick_me(util::style_nick(nick, style_me(), &nick_colors)),
+                styled_content_me(content, EmojiMode::Unicode, &nick_colors, &[]),
+            )
         } else {
-            (styled_nick(nick), styled_content(content))
+            (
+                styled_nick(util::style_nick(nick, Style::new(), &nick_colors)),
+                styled_content(content, Style::new(), EmojiMode::Unicode, &nick_colors, &[]),
+            )
         }
     }
 }
@@ -1,7 +1,12 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use cove_config::{parse_color, HighlightRule, Pattern};
 use crossterm::style::{Color, Stylize};
 use euphoxide::Emoji;
 use once_cell::sync::Lazy;
-use toss::{Style, Styled};
+use toss::{Style, Styled, WidthDb};
+use unicode_segmentation::UnicodeSegmentation;
 
 pub static EMOJI: Lazy<Emoji> = Lazy::new(Emoji::load);
 
@@ -37,20 +42,202 @@ fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
     ((r * 256.0) as u8, (g * 256.0) as u8, (b * 256.0) as u8)
 }
 
-pub fn nick_color(nick: &str) -> (u8, u8, u8) {
+/// How nicks are colored.
+///
+/// Most nicks get a color whose hue is derived from the nick. Some nicks may
+/// have fixed colors overriding the automatically chosen color.
+#[derive(Debug, Clone)]
+pub struct NickColors {
+    overrides: HashMap<String, Color>,
+    saturation: f32,
+    lightness: f32,
+}
+
+impl NickColors {
+    pub fn new() -> Self {
+        Self {
+            overrides: HashMap::new(),
+            saturation: 1.0,
+            lightness: 0.72,
+        }
+    }
+
+    /// Change saturation and lightness of the automatically chosen colors.
+    /// Both are clamped to the range `[0, 1]`.
+    pub fn set_palette(&mut self, saturation: f32, lightness: f32) {
+        self.saturation = saturation.clamp(0.0, 1.0);
+        self.lightness = lightness.clamp(0.0, 1.0);
+    }
+}
+
+impl Default for NickColors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A compiled `highlight` rule.
+#[derive(Debug, Clone)]
+pub struct Highlight {
+    regex: Pattern,
+    fg: Option<Color>,
+    bg: Option<Color>,
+    bold: bool,
+    italic: bool,
+    underlined: bool,
+}
+
+impl Highlight {
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+
+    pub fn apply(&self, mut style: Style) -> Style {
+        if let Some(fg) = self.fg {
+            style = style.with(fg);
+        }
+        if let Some(bg) = self.bg {
+            style = style.on(bg);
+        }
+        if self.bold {
+            style = style.bold();
+        }
+        if self.italic {
+            style = style.italic();
+        }
+        if self.underlined {
+            style = style.underlined();
+        }
+        style
+    }
+}
+
+/// Parse the `highlight` config option.
+pub fn parse_highlights(rules: &[HighlightRule]) -> Vec<Highlight> {
+    let parse = |color: &Option<String>| parse_color(color.as_ref()?);
+
+    let mut result = vec![];
+    for rule in rules {
+        // Patterns were compiled and colors checked when loading the config
+        result.push(Highlight {
+            regex: rule.pattern.clone(),
+            fg: parse(&rule.style.fg),
+            bg: parse(&rule.style.bg),
+            bold: rule.style.bold,
+            italic: rule.style.italic,
+            underlined: rule.style.underlined,
+        });
+    }
+    result
+}
+
+/// Find the non-overlapping parts of a text matched by the highlights.
+///
+/// Earlier matches take precedence over later ones, and for matches starting
+/// at the same position, earlier highlights take precedence.
+pub fn highlight_ranges<'a>(
+    text: &str,
+    highlights: &'a [Highlight],
+) -> Vec<(Range<usize>, &'a Highlight)> {
+    let mut matches = highlights
+        .iter()
+        .flat_map(|h| h.regex.find_iter(text).map(move |m| (m.range(), h)))
+        .filter(|(range, _)| !range.is_empty())
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|(range, _)| range.start);
+
+    let mut result: Vec<(Range<usize>, &Highlight)> = vec![];
+    for (range, highlight) in matches {
+        let overlaps = result
+            .last()
+            .is_some_and(|(last, _)| range.start < last.end);
+        if !overlaps {
+            result.push((range, highlight));
+        }
+    }
+    result
+}
+
+/// Parse the `nick_colors` config option.
+pub fn parse_nick_colors(colors: &HashMap<String, String>) -> NickColors {
+    let mut result = NickColors::new();
+    for (nick, color) in colors {
+        // Colors were already checked when loading the config
+        if let Some(parsed) = parse_color(color) {
+            result.overrides.insert(nick.trim().to_string(), parsed);
+        }
+    }
+    result
+}
+
+pub fn nick_color(nick: &str, colors: &NickColors) -> Color {
+    // Mentions are colored like the nick they mention
+    let name = nick.strip_prefix('@').unwrap_or(nick).trim();
+    if let Some(color) = colors.overrides.get(name) {
+        return *color;
+    }
+
     let hue = euphoxide::nick::hue(&EMOJI, nick) as f32;
-    hsl_to_rgb(hue, 1.0, 0.72)
+    let (r, g, b) = hsl_to_rgb(hue, colors.saturation, colors.lightness);
+    Color::Rgb { r, g, b }
 }
 
-pub fn nick_style(nick: &str, base: Style) -> Style {
-    let (r, g, b) = nick_color(nick);
-    base.bold().with(Color::Rgb { r, g, b })
+pub fn nick_style(nick: &str, base: Style, overrides: &NickColors) -> Style {
+    base.bold().with(nick_color(nick, overrides))
+}
+
+pub fn style_nick(nick: &str, base: Style, overrides: &NickColors) -> Styled {
+    Styled::new(EMOJI.replace(nick), nick_style(nick, base, overrides))
+}
+
+/// Shorten a nick to at most `max_width` columns, marking the cut with `…`.
+pub fn truncate_nick(widthdb: &mut WidthDb, nick: &str, max_width: Option<usize>) -> String {
+    match max_width {
+        Some(max_width) => truncate(widthdb, nick, max_width),
+        None => nick.to_string(),
+    }
+}
+
+/// Shorten a text to at most `max_width` columns, marking the cut with `…`.
+///
+/// The text is cut between graphemes so emoji sequences and combining
+/// characters stay intact, and widths are taken from the [`WidthDb`] so they
+/// match what the terminal actually displays.
+pub fn truncate(widthdb: &mut WidthDb, text: &str, max_width: usize) -> String {
+    if widthdb.width(text) <= max_width {
+        return text.to_string();
+    }
+
+    let mut result = String::new();
+    let mut width = 0;
+    for grapheme in text.graphemes(true) {
+        let grapheme_width = usize::from(widthdb.grapheme_width(grapheme, width));
+        // Leave one column for the ellipsis
+        if width + grapheme_width + 1 > max_width {
+            break;
+        }
+        width += grapheme_width;
+        result.push_str(grapheme);
+    }
+    if max_width > 0 {
+        result.push('…');
+    }
+    result
 }
 
-pub fn style_nick(nick: &str, base: Style) -> Styled {
-    Styled::new(EMOJI.replace(nick), nick_style(nick, base))
+/// Like [`style_nick`], but shortened to at most `max_width` columns. The color
+/// is still based on the full nick.
+pub fn style_nick_truncated(
+    widthdb: &mut WidthDb,
+    nick: &str,
+    base: Style,
+    max_width: Option<usize>,
+    overrides: &NickColors,
+) -> Styled {
+    let truncated = truncate_nick(widthdb, &EMOJI.replace(nick), max_width);
+    Styled::new(truncated, nick_style(nick, base, overrides))
 }
 
-pub fn style_nick_exact(nick: &str, base: Style) -> Styled {
-    Styled::new(nick, nick_style(nick, base))
+pub fn style_nick_exact(nick: &str, base: Style, overrides: &NickColors) -> Styled {
+    Styled::new(nick, nick_style(nick, base, overrides))
 }
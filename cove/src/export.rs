@@ -1,11 +1,17 @@
 //! Export logs from the vault to plain text files.
 
+mod html;
 mod json;
 mod text;
 
 use std::fs::File;
 use std::io::{self, BufWriter, Write};
 
+use anyhow::{anyhow, bail};
+use euphoxide::api::Time;
+use jiff::civil::{Date, DateTime};
+use jiff::tz::TimeZone;
+
 use crate::vault::{EuphRoomVault, EuphVault, RoomIdentifier};
 
 #[derive(Debug, Clone, Copy, clap::ValueEnum)]
@@ -16,7 +22,10 @@ pub enum Format {
     Json,
     /// Message objects in the same format as the euphoria API uses, one per
     /// line (https://jsonlines.org/).
+    #[value(alias = "jsonl")]
     JsonLines,
+    /// Tree-structured messages as a standalone web page.
+    Html,
 }
 
 impl Format {
@@ -25,6 +34,7 @@ impl Format {
             Self::Text => "text",
             Self::Json => "json",
             Self::JsonLines => "json lines",
+            Self::Html => "html",
         }
     }
 
@@ -33,6 +43,7 @@ impl Format {
             Self::Text => "txt",
             Self::Json => "json",
             Self::JsonLines => "jsonl",
+            Self::Html => "html",
         }
     }
 }
@@ -45,6 +56,15 @@ pub struct Args {
     #[arg(long, short)]
     all: bool,
 
+    /// Export all rooms in the vault matching this pattern.
+    ///
+    /// Patterns have the form `<domain>/<name>` and may contain `*` to match
+    /// any sequence of characters and `?` to match any single character, for
+    /// example `euphoria.io/*`. Patterns without a `/` are resolved with
+    /// `--domain`. Can be specified multiple times.
+    #[arg(long = "room", value_name = "PATTERN")]
+    room_patterns: Vec<String>,
+
     /// Domain to resolve the room names with.
     #[arg(long, short, default_value = "euphoria.leet.nu")]
     domain: String,
@@ -56,72 +76,159 @@ pub struct Args {
     /// Location of the output file
     ///
     /// May include the following placeholders:
+    /// `%d` - room domain
     /// `%r` - room name
     /// `%e` - format extension
     /// A literal `%` can be written as `%%`.
     ///
-    /// If the value ends with a `/`, it is assumed to point to a directory and
-    /// `%r.%e` will be appended.
+    /// Defaults to `%r.%e`, or `%d-%r.%e` if rooms of multiple domains are
+    /// exported. If the value ends with a `/`, it is assumed to point to a
+    /// directory and the default will be appended.
     ///
     /// If the value is a literal `-`, the export will be written to stdout. To
     /// write to a file named `-`, you can use `./-`.
     ///
     /// Must be a valid utf-8 encoded string.
-    #[arg(long, short, default_value_t = Into::into("%r.%e"))]
+    #[arg(long, short)]
     #[arg(verbatim_doc_comment)]
-    out: String,
+    out: Option<String>,
+
+    /// Only export messages sent at or after this date or time.
+    ///
+    /// Accepts dates like `2024-05-01` and times like `2024-05-01T18:30` in the
+    /// configured time zone. The text and html formats also include the
+    /// messages that exported messages reply to.
+    #[arg(long)]
+    since: Option<String>,
+
+    /// Only export messages sent before this date or time.
+    ///
+    /// Accepts the same values as `--since`. For example, `--since 2024-05-01
+    /// --until 2024-05-02` exports the messages of a single day.
+    #[arg(long)]
+    until: Option<String>,
+}
+
+/// Range of message times to export. The start is inclusive, the end is
+/// exclusive.
+#[derive(Debug, Clone, Copy, Default)]
+struct TimeRange {
+    since: Option<Time>,
+    until: Option<Time>,
+}
+
+impl TimeRange {
+    fn parse(since: Option<&str>, until: Option<&str>, tz: &TimeZone) -> anyhow::Result<Self> {
+        let since = since.map(|s| parse_time(s, tz)).transpose()?;
+        let until = until.map(|s| parse_time(s, tz)).transpose()?;
+        if let (Some(since), Some(until)) = (since, until) {
+            if since.0 >= until.0 {
+                bail!("--since must be earlier than --until");
+            }
+        }
+        Ok(Self { since, until })
+    }
+
+    fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    fn contains(&self, time: Time) -> bool {
+        self.since.map_or(true, |since| time.0 >= since.0)
+            && self.until.map_or(true, |until| time.0 < until.0)
+    }
+}
+
+fn parse_time(s: &str, tz: &TimeZone) -> anyhow::Result<Time> {
+    let datetime = match s.parse::<DateTime>() {
+        Ok(datetime) => datetime,
+        Err(_) => s
+            .parse::<Date>()
+            .map_err(|_| anyhow!("invalid date or time {s:?}"))?
+            .to_datetime(jiff::civil::Time::midnight()),
+    };
+    let timestamp = datetime.to_zoned(tz.clone())?.timestamp();
+    Ok(Time(timestamp.as_second()))
 }
 
 async fn export_room<W: Write>(
     vault: &EuphRoomVault,
     out: &mut W,
     format: Format,
+    range: TimeRange,
 ) -> anyhow::Result<()> {
     match format {
-        Format::Text => text::export(vault, out).await?,
-        Format::Json => json::export(vault, out).await?,
-        Format::JsonLines => json::export_lines(vault, out).await?,
+        Format::Text => text::export(vault, out, range).await?,
+        Format::Json => json::export(vault, out, range).await?,
+        Format::JsonLines => json::export_lines(vault, out, range).await?,
+        Format::Html => html::export(vault, out, range).await?,
     }
     Ok(())
 }
 
-pub async fn export(vault: &EuphVault, mut args: Args) -> anyhow::Result<()> {
-    if args.out.ends_with('/') {
-        args.out.push_str("%r.%e");
-    }
+pub async fn export(vault: &EuphVault, args: Args, tz: &TimeZone) -> anyhow::Result<()> {
+    let range = TimeRange::parse(args.since.as_deref(), args.until.as_deref(), tz)?;
+
+    let mut rooms = args
+        .rooms
+        .iter()
+        .map(|name| RoomIdentifier::new(args.domain.clone(), name.clone()))
+        .collect::<Vec<_>>();
 
-    let rooms = if args.all {
-        let mut rooms = vault
-            .rooms()
-            .await?
-            .into_iter()
-            .map(|id| id.name)
-            .collect::<Vec<_>>();
+    if args.all || !args.room_patterns.is_empty() {
+        let vault_rooms = vault.rooms().await?;
+        if args.all {
+            rooms.extend(vault_rooms.iter().cloned());
+        }
+        for pattern in &args.room_patterns {
+            let matched = matching_rooms(&vault_rooms, pattern, &args.domain);
+            if matched.is_empty() {
+                eprintln!("No rooms match {pattern:?}");
+            } else {
+                let names = matched
+                    .iter()
+                    .map(|id| format!("{id:?}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                eprintln!("Rooms matching {pattern:?}: {names}");
+            }
+            rooms.extend(matched);
+        }
         rooms.sort_unstable();
-        rooms
-    } else {
-        let mut rooms = args.rooms.clone();
-        rooms.dedup();
-        rooms
-    };
+    }
+    rooms.dedup();
 
     if rooms.is_empty() {
         eprintln!("No rooms to export");
     }
 
-    for room in rooms {
-        if args.out == "-" {
-            eprintln!("Exporting &{room} as {} to stdout", args.format.name());
-            let vault = vault.room(RoomIdentifier::new(args.domain.clone(), room));
+    // Rooms with the same name on different domains would otherwise be
+    // exported to the same file.
+    let multiple_domains = rooms.iter().any(|id| id.domain != rooms[0].domain);
+    let default_out = if multiple_domains {
+        "%d-%r.%e"
+    } else {
+        "%r.%e"
+    };
+    let out = match args.out {
+        Some(out) if out.ends_with('/') => format!("{out}{default_out}"),
+        Some(out) => out,
+        None => default_out.to_string(),
+    };
+
+    for id in rooms {
+        if out == "-" {
+            eprintln!("Exporting {id:?} as {} to stdout", args.format.name());
+            let vault = vault.room(id);
             let mut stdout = BufWriter::new(io::stdout());
-            export_room(&vault, &mut stdout, args.format).await?;
+            export_room(&vault, &mut stdout, args.format, range).await?;
             stdout.flush()?;
         } else {
-            let out = format_out(&args.out, &room, args.format);
-            eprintln!("Exporting &{room} as {} to {out}", args.format.name());
-            let vault = vault.room(RoomIdentifier::new(args.domain.clone(), room));
+            let out = format_out(&out, &id, args.format);
+            eprintln!("Exporting {id:?} as {} to {out}", args.format.name());
+            let vault = vault.room(id);
             let mut file = BufWriter::new(File::create(out)?);
-            export_room(&vault, &mut file, args.format).await?;
+            export_room(&vault, &mut file, args.format, range).await?;
             file.flush()?;
         }
     }
@@ -129,14 +236,65 @@ pub async fn export(vault: &EuphVault, mut args: Args) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn format_out(out: &str, room: &str, format: Format) -> String {
+fn matching_rooms(rooms: &[RoomIdentifier], pattern: &str, domain: &str) -> Vec<RoomIdentifier> {
+    let pattern = if pattern.contains('/') {
+        pattern.to_string()
+    } else {
+        format!("{domain}/{pattern}")
+    };
+
+    rooms
+        .iter()
+        .filter(|id| glob_matches(&pattern, &format!("{}/{}", id.domain, id.name)))
+        .cloned()
+        .collect()
+}
+
+/// Whether the text matches the pattern, where `*` matches any sequence of
+/// characters and `?` matches any single character.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    let mut p = 0;
+    let mut t = 0;
+    // Position after the most recent `*` and the text position it was matched
+    // against, for retrying with the `*` matching one more character.
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p).copied() {
+            Some('*') => {
+                p += 1;
+                star = Some((p, t));
+            }
+            Some(c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    star = Some((star_p, t));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn format_out(out: &str, id: &RoomIdentifier, format: Format) -> String {
     let mut result = String::new();
 
     let mut special = false;
     for char in out.chars() {
         if special {
             match char {
-                'r' => result.push_str(room),
+                'd' => result.push_str(&id.domain),
+                'r' => result.push_str(&id.name),
                 'e' => result.push_str(format.extension()),
                 '%' => result.push('%'),
                 _ => {
@@ -154,3 +312,48 @@ fn format_out(out: &str, room: &str, format: Format) -> String {
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_without_wildcards_matches_exactly() {
+        assert!(glob_matches("test", "test"));
+        assert!(!glob_matches("test", "tests"));
+        assert!(!glob_matches("test", "tes"));
+        assert!(!glob_matches("test", "best"));
+    }
+
+    #[test]
+    fn glob_star_matches_any_sequence() {
+        assert!(glob_matches("te*st", "test"));
+        assert!(glob_matches("te*st", "teapot-st"));
+        assert!(glob_matches("*st", "test"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*", "anything"));
+        assert!(!glob_matches("te*st", "teapot"));
+    }
+
+    #[test]
+    fn glob_trailing_star_matches_any_suffix() {
+        assert!(glob_matches("test*", "test"));
+        assert!(glob_matches("test*", "testing"));
+        assert!(glob_matches("te**", "test"));
+        assert!(!glob_matches("test*", "tes"));
+    }
+
+    #[test]
+    fn glob_question_mark_matches_single_character() {
+        assert!(glob_matches("t?st", "test"));
+        assert!(glob_matches("????", "test"));
+        assert!(!glob_matches("t?st", "tst"));
+        assert!(!glob_matches("t?st", "teest"));
+    }
+
+    #[test]
+    fn empty_glob_only_matches_empty_text() {
+        assert!(glob_matches("", ""));
+        assert!(!glob_matches("", "test"));
+    }
+}
@@ -0,0 +1,118 @@
+use std::io::Write;
+
+use euphoxide::api::MessageId;
+
+use crate::euph::SmallMessage;
+use crate::store::Tree;
+use crate::vault::EuphRoomVault;
+
+use super::TimeRange;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+const STYLE: &str = "
+body { font-family: monospace; }
+ul { list-style: none; padding-left: 1.5em; border-left: 1px solid #ccc; }
+body > ul { padding-left: 0; border-left: none; }
+.time { color: #888; }
+.nick { font-weight: bold; }
+.content { white-space: pre-wrap; }
+";
+
+pub async fn export<W: Write>(
+    vault: &EuphRoomVault,
+    out: &mut W,
+    range: TimeRange,
+) -> anyhow::Result<()> {
+    let room = escape(&vault.room().name);
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>&amp;{room}</title>")?;
+    writeln!(out, "<style>{STYLE}</style>")?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    writeln!(out, "<h1>&amp;{room}</h1>")?;
+    writeln!(out, "<ul>")?;
+
+    let mut exported_trees = 0;
+    let mut exported_msgs = 0;
+    let mut root_id = vault.first_root_id().await?;
+    while let Some(some_root_id) = root_id {
+        let mut tree = vault.tree(some_root_id, None).await?;
+        root_id = vault.next_root_id(some_root_id).await?;
+        if !range.is_unbounded() {
+            tree = tree.filtered(|msg| range.contains(msg.time));
+            if tree.len() == 0 {
+                continue;
+            }
+        }
+
+        write_tree(out, &tree, some_root_id)?;
+
+        exported_trees += 1;
+        exported_msgs += tree.len();
+
+        if exported_trees % 10000 == 0 {
+            eprintln!("  {exported_trees} trees, {exported_msgs} messages")
+        }
+    }
+    eprintln!("  {exported_trees} trees, {exported_msgs} messages in total");
+
+    writeln!(out, "</ul>")?;
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")?;
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for char in text.chars() {
+        match char {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(char),
+        }
+    }
+    result
+}
+
+fn write_tree<W: Write>(
+    out: &mut W,
+    tree: &Tree<SmallMessage>,
+    id: MessageId,
+) -> anyhow::Result<()> {
+    write!(out, "<li>")?;
+
+    if let Some(msg) = tree.msg(&id) {
+        write_msg(out, msg)?;
+    } else {
+        write!(out, "[...]")?;
+    }
+
+    if let Some(children) = tree.children(&id) {
+        writeln!(out, "<ul>")?;
+        for child in children {
+            write_tree(out, tree, *child)?;
+        }
+        write!(out, "</ul>")?;
+    }
+
+    writeln!(out, "</li>")?;
+    Ok(())
+}
+
+fn write_msg<W: Write>(out: &mut W, msg: &SmallMessage) -> anyhow::Result<()> {
+    let time = msg.time.as_timestamp().strftime(TIME_FORMAT);
+    write!(
+        out,
+        "<span class=\"time\">{time}</span> <span class=\"nick\">[{}]</span> <span class=\"content\">{}</span>",
+        escape(&msg.nick),
+        escape(&msg.content),
+    )?;
+    Ok(())
+}
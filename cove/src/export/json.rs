@@ -1,16 +1,39 @@
 use std::io::Write;
 
+use euphoxide::api::{Message, MessageId};
+
 use crate::vault::EuphRoomVault;
 
+use super::TimeRange;
+
 const CHUNK_SIZE: usize = 10000;
 
-pub async fn export<W: Write>(vault: &EuphRoomVault, file: &mut W) -> anyhow::Result<()> {
+async fn chunk_after(
+    vault: &EuphRoomVault,
+    id: Option<MessageId>,
+    range: TimeRange,
+) -> anyhow::Result<Vec<Message>> {
+    let messages = if range.is_unbounded() {
+        vault.chunk_after(id, CHUNK_SIZE).await?
+    } else {
+        vault
+            .chunk_after_in_range(id, CHUNK_SIZE, range.since, range.until)
+            .await?
+    };
+    Ok(messages)
+}
+
+pub async fn export<W: Write>(
+    vault: &EuphRoomVault,
+    file: &mut W,
+    range: TimeRange,
+) -> anyhow::Result<()> {
     write!(file, "[")?;
 
     let mut total = 0;
     let mut last_msg_id = None;
     loop {
-        let messages = vault.chunk_after(last_msg_id, CHUNK_SIZE).await?;
+        let messages = chunk_after(vault, last_msg_id, range).await?;
         last_msg_id = Some(match messages.last() {
             Some(last_msg) => last_msg.id,
             None => break, // No more messages, export finished
@@ -37,11 +60,15 @@ pub async fn export<W: Write>(vault: &EuphRoomVault, file: &mut W) -> anyhow::Re
     Ok(())
 }
 
-pub async fn export_lines<W: Write>(vault: &EuphRoomVault, file: &mut W) -> anyhow::Result<()> {
+pub async fn export_lines<W: Write>(
+    vault: &EuphRoomVault,
+    file: &mut W,
+    range: TimeRange,
+) -> anyhow::Result<()> {
     let mut total = 0;
     let mut last_msg_id = None;
     loop {
-        let messages = vault.chunk_after(last_msg_id, CHUNK_SIZE).await?;
+        let messages = chunk_after(vault, last_msg_id, range).await?;
         last_msg_id = Some(match messages.last() {
             Some(last_msg) => last_msg.id,
             None => break, // No more messages, export finished
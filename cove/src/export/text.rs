@@ -7,17 +7,30 @@ use crate::euph::SmallMessage;
 use crate::store::Tree;
 use crate::vault::EuphRoomVault;
 
+use super::TimeRange;
+
 const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 const TIME_EMPTY: &str = "                   ";
 
-pub async fn export<W: Write>(vault: &EuphRoomVault, out: &mut W) -> anyhow::Result<()> {
+pub async fn export<W: Write>(
+    vault: &EuphRoomVault,
+    out: &mut W,
+    range: TimeRange,
+) -> anyhow::Result<()> {
     let mut exported_trees = 0;
     let mut exported_msgs = 0;
     let mut root_id = vault.first_root_id().await?;
     while let Some(some_root_id) = root_id {
-        let tree = vault.tree(some_root_id).await?;
-        write_tree(out, &tree, some_root_id, 0)?;
+        let mut tree = vault.tree(some_root_id, None).await?;
         root_id = vault.next_root_id(some_root_id).await?;
+        if !range.is_unbounded() {
+            tree = tree.filtered(|msg| range.contains(msg.time));
+            if tree.len() == 0 {
+                continue;
+            }
+        }
+
+        write_tree(out, &tree, some_root_id, 0)?;
 
         exported_trees += 1;
         exported_msgs += tree.len();
@@ -0,0 +1,102 @@
+//! Import messages from a JSON lines file into the vault.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use euphoxide::api::{Message, Time};
+
+use crate::vault::{EuphRoomVault, EuphVault, RoomIdentifier};
+
+const CHUNK_SIZE: usize = 10000;
+
+#[derive(Debug, clap::Parser)]
+pub struct Args {
+    /// Room to import the messages into.
+    room: String,
+
+    /// File containing one message object per line, as produced by
+    /// `cove export --format json-lines`.
+    file: PathBuf,
+
+    /// Domain to resolve the room name with.
+    #[arg(long, short, default_value = "euphoria.leet.nu")]
+    domain: String,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    inserted: usize,
+    updated: usize,
+}
+
+async fn import_chunk(
+    vault: &EuphRoomVault,
+    msgs: Vec<Message>,
+    counts: &mut Counts,
+) -> anyhow::Result<()> {
+    // The span of the chunk is recorded from its first to its last message, so
+    // the messages must be in order.
+    for pair in msgs.windows(2) {
+        if pair[0].id >= pair[1].id {
+            bail!(
+                "message ids are not in ascending order ({} is followed by {})",
+                pair[0].id.0,
+                pair[1].id.0
+            );
+        }
+    }
+
+    let ids = msgs.iter().map(|msg| msg.id).collect::<Vec<_>>();
+    let existing = vault.count_existing_msgs(ids).await?;
+    counts.updated += existing;
+    counts.inserted += msgs.len() - existing;
+
+    vault.add_msgs(msgs, None, None, true).await?;
+    Ok(())
+}
+
+pub async fn import(vault: &EuphVault, args: Args) -> anyhow::Result<()> {
+    let file = File::open(&args.file)
+        .with_context(|| format!("failed to open {}", args.file.display()))?;
+
+    eprintln!("Importing {} into &{}", args.file.display(), args.room);
+    let vault = vault.room(RoomIdentifier::new(args.domain, args.room));
+
+    // Messages of rooms that are not in the rooms table don't show up anywhere,
+    // so the room must exist before importing into it.
+    vault.join(Time::now()).await?;
+
+    let mut counts = Counts::default();
+    let mut chunk = vec![];
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let msg = serde_json::from_str::<Message>(&line)
+            .with_context(|| format!("invalid message on line {}", i + 1))?;
+        chunk.push(msg);
+
+        if chunk.len() >= CHUNK_SIZE {
+            import_chunk(&vault, chunk, &mut counts).await?;
+            chunk = vec![];
+            eprintln!(
+                "  {} messages inserted, {} updated",
+                counts.inserted, counts.updated
+            );
+        }
+    }
+    if !chunk.is_empty() {
+        import_chunk(&vault, chunk, &mut counts).await?;
+    }
+
+    eprintln!(
+        "  {} messages inserted, {} updated in total",
+        counts.inserted, counts.updated
+    );
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+//! Fetching and caching the titles of web pages linked in messages.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use log::debug;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use regex::Regex;
+use tokio::sync::mpsc;
+
+use crate::ui::UiEvent;
+
+/// How long to wait for a page before giving up on its title.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Only this many bytes of a page are searched for its title.
+const MAX_BYTES: usize = 64 * 1024;
+
+enum Title {
+    Pending,
+    Found(String),
+    Missing,
+}
+
+/// Titles are cached for the lifetime of the program so that opening the
+/// links popup again doesn't refetch them.
+static TITLES: Lazy<Mutex<HashMap<String, Title>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .expect("http client should be buildable")
+});
+
+static TITLE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap());
+
+/// The title of the page behind a link, if it has already been fetched.
+pub fn get(link: &str) -> Option<String> {
+    match TITLES.lock().get(link) {
+        Some(Title::Found(title)) => Some(title.clone()),
+        Some(Title::Pending | Title::Missing) | None => None,
+    }
+}
+
+/// Start fetching the title of the page behind a link in the background,
+/// unless it has already been fetched or is currently being fetched.
+///
+/// Only `http://` and `https://` links are fetched. Once the title is known,
+/// the UI is told to redraw.
+pub fn fetch(link: String, ui_event_tx: mpsc::UnboundedSender<UiEvent>) {
+    if !link.starts_with("http://") && !link.starts_with("https://") {
+        return;
+    }
+
+    {
+        let mut titles = TITLES.lock();
+        if titles.contains_key(&link) {
+            return;
+        }
+        titles.insert(link.clone(), Title::Pending);
+    }
+
+    tokio::task::spawn(async move {
+        let title = match fetch_title(&link).await {
+            Ok(Some(title)) => Title::Found(title),
+            Ok(None) => Title::Missing,
+            Err(err) => {
+                debug!("Failed to fetch title of {link}: {err}");
+                Title::Missing
+            }
+        };
+        TITLES.lock().insert(link, title);
+        let _ = ui_event_tx.send(UiEvent::Redraw);
+    });
+}
+
+async fn fetch_title(link: &str) -> reqwest::Result<Option<String>> {
+    let mut response = CLIENT.get(link).send().await?.error_for_status()?;
+
+    let mut body = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_BYTES {
+            body.truncate(MAX_BYTES);
+            break;
+        }
+    }
+
+    let body = String::from_utf8_lossy(&body);
+    let title = TITLE_RE
+        .captures(&body)
+        .map(|captures| clean_title(&captures[1]))
+        .filter(|title| !title.is_empty());
+    Ok(title)
+}
+
+fn clean_title(title: &str) -> String {
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    title
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
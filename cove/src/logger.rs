@@ -1,4 +1,6 @@
+use std::collections::HashSet;
 use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::vec;
 
@@ -8,10 +10,10 @@ use jiff::Timestamp;
 use log::{Level, LevelFilter, Log};
 use parking_lot::Mutex;
 use tokio::sync::mpsc;
-use toss::{Style, Styled};
+use toss::{Style, Styled, WidthDb};
 
 use crate::store::{Msg, MsgStore, Path, Tree};
-use crate::ui::ChatMsg;
+use crate::ui::{ChatMsg, RenderOptions};
 
 #[derive(Debug, Clone)]
 pub struct LogMsg {
@@ -46,7 +48,7 @@ impl ChatMsg for LogMsg {
         Some(self.time)
     }
 
-    fn styled(&self) -> (Styled, Styled) {
+    fn styled(&self, _options: &RenderOptions, _widthdb: &mut WidthDb) -> (Styled, Styled) {
         let nick_style = match self.level {
             Level::Error => Style::new().bold().red(),
             Level::Warn => Style::new().bold().yellow(),
@@ -59,6 +61,10 @@ impl ChatMsg for LogMsg {
         (nick, content)
     }
 
+    fn ignored(&self, _options: &RenderOptions) -> bool {
+        false
+    }
+
     fn edit(_nick: &str, _content: &str) -> (Styled, Styled) {
         panic!("log is not editable")
     }
@@ -71,11 +77,20 @@ impl ChatMsg for LogMsg {
 /// Prints all error messages when dropped.
 pub struct LoggerGuard {
     messages: Arc<Mutex<Vec<LogMsg>>>,
+    errors_printed: Arc<AtomicBool>,
 }
 
-impl Drop for LoggerGuard {
-    fn drop(&mut self) {
-        let guard = self.messages.lock();
+impl LoggerGuard {
+    fn print_errors(messages: &Mutex<Vec<LogMsg>>, errors_printed: &AtomicBool) {
+        // The messages may be locked by the thread that is currently panicking,
+        // in which case waiting for the lock would never finish.
+        let Some(guard) = messages.try_lock() else {
+            return;
+        };
+        if errors_printed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
         let mut error_encountered = false;
         for msg in &*guard {
             if msg.level == Level::Error {
@@ -91,6 +106,20 @@ impl Drop for LoggerGuard {
             eprintln!();
         }
     }
+
+    /// A function that prints all error messages unless they have already been
+    /// printed, for use in a panic hook.
+    pub fn error_printer(&self) -> impl Fn() + Send + Sync + 'static {
+        let messages = self.messages.clone();
+        let errors_printed = self.errors_printed.clone();
+        move || Self::print_errors(&messages, &errors_printed)
+    }
+}
+
+impl Drop for LoggerGuard {
+    fn drop(&mut self) {
+        Self::print_errors(&self.messages, &self.errors_printed);
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -182,6 +211,22 @@ impl MsgStore<LogMsg> for Logger {
     async fn set_older_seen(&self, _id: &usize, _seen: bool) -> Result<(), Self::Error> {
         Ok(())
     }
+
+    async fn folded_ids(&self) -> Result<HashSet<usize>, Self::Error> {
+        Ok(HashSet::new())
+    }
+
+    async fn set_folded(&self, _id: &usize, _folded: bool) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn fold_all(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn unfold_all(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 impl Log for Logger {
@@ -230,6 +275,7 @@ impl Logger {
         };
         let guard = LoggerGuard {
             messages: logger.messages.clone(),
+            errors_printed: Arc::new(AtomicBool::new(false)),
         };
 
         log::set_max_level(if verbose {
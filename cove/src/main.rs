@@ -1,28 +1,38 @@
 // TODO Remove unnecessary Debug impls and compare compile times
-// TODO Invoke external notification command?
 
+mod bench;
+mod clipboard;
+mod doctor;
 mod euph;
 mod export;
+mod import;
+mod link_titles;
 mod logger;
 mod macros;
+mod serve;
+mod stats;
 mod store;
 mod ui;
 mod util;
 mod vault;
 mod version;
 
+use std::io;
+use std::panic;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use anyhow::Context;
 use clap::Parser;
 use cove_config::doc::Document;
 use cove_config::Config;
 use directories::{BaseDirs, ProjectDirs};
+use euphoxide::api::Time;
 use log::info;
 use tokio::sync::mpsc;
 use toss::Terminal;
 
-use crate::logger::Logger;
+use crate::logger::{Logger, LoggerGuard};
 use crate::ui::Ui;
 use crate::vault::Vault;
 use crate::version::{NAME, VERSION};
@@ -33,8 +43,20 @@ enum Command {
     Run,
     /// Export room logs as plain text files.
     Export(export::Args),
+    /// Import messages from a JSON lines file, for example one created via
+    /// `cove export --format json-lines`.
+    Import(import::Args),
     /// Compact and clean up vault.
-    Gc,
+    Gc {
+        /// Delete messages older than this before compacting, for example
+        /// `90d`. Supported units are s, m, h, d and w.
+        ///
+        /// Deleted messages are not downloaded again when backfilling.
+        #[arg(long, value_parser = util::parse_duration)]
+        older_than: Option<Duration>,
+    },
+    /// Print message counts and time ranges of all rooms in the vault.
+    Stats,
     /// Clear euphoria session cookies.
     ClearCookies {
         /// Clear cookies for a specific domain only.
@@ -43,6 +65,13 @@ enum Command {
     },
     /// Print config documentation as markdown.
     HelpConfig,
+    /// Check config, vault, terminal and connectivity for problems.
+    Doctor,
+    /// Serve rooms and messages from the vault as JSON over HTTP (read-only).
+    Serve(serve::Args),
+    /// Measure how long the core vault queries take.
+    #[command(hide = true)]
+    BenchQueries(bench::Args),
 }
 
 impl Default for Command {
@@ -115,25 +144,69 @@ fn update_config_with_args(config: &mut Config, args: &Args) {
     config.ephemeral |= args.ephemeral;
     config.measure_widths |= args.measure_widths;
     config.offline |= args.offline;
+    config.vault_timing |= args.verbose;
 }
 
 fn open_vault(config: &Config, dirs: &ProjectDirs) -> anyhow::Result<Vault> {
     let vault = if config.ephemeral {
-        vault::launch_in_memory()?
+        vault::launch_in_memory(config)?
     } else {
         let data_dir = data_dir(config, dirs);
         eprintln!("Data dir:    {}", data_dir.to_string_lossy());
-        vault::launch(&data_dir.join("vault.db"))?
+        vault::launch(&data_dir.join("vault.db"), config).map_err(|err| {
+            if vault::is_busy(&err) {
+                anyhow::anyhow!(err)
+                    .context("vault is in use, is another instance of cove running?")
+            } else {
+                err.into()
+            }
+        })?
     };
 
     Ok(vault)
 }
 
+fn open_vault_read_only(
+    config: &Config,
+    dirs: &ProjectDirs,
+    prepare: bool,
+) -> anyhow::Result<Vault> {
+    let data_dir = data_dir(config, dirs);
+    eprintln!("Data dir:    {}", data_dir.to_string_lossy());
+    let vault = vault::launch_read_only(&data_dir.join("vault.db"), config, prepare)
+        .context("failed to open vault")?;
+    Ok(vault)
+}
+
+/// Put the terminal back into a usable state. Errors are ignored since there is
+/// nothing left to do about them.
+fn restore_terminal() {
+    let _ = crossterm::terminal::disable_raw_mode();
+    let _ = crossterm::execute!(
+        io::stdout(),
+        crossterm::terminal::LeaveAlternateScreen,
+        crossterm::cursor::Show,
+    );
+}
+
+/// Make sure the panic message and logged errors are readable even if cove
+/// panics while the terminal is in raw mode and showing the alternate screen.
+fn install_panic_hook(logger_guard: &LoggerGuard) {
+    let print_errors = logger_guard.error_printer();
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+        print_errors();
+    }));
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
     let (logger, logger_guard, logger_rx) = Logger::init(args.verbose);
+    install_panic_hook(&logger_guard);
     let dirs = ProjectDirs::from("de", "plugh", "cove").expect("failed to find config directory");
 
     // https://github.com/snapview/tokio-tungstenite/issues/353#issuecomment-2455247837
@@ -146,16 +219,30 @@ async fn main() -> anyhow::Result<()> {
     eprintln!("Config file: {}", config_path.to_string_lossy());
 
     // Load config
-    let mut config = Config::load(&config_path)?;
+    let config = Config::load(&config_path);
+    if let Some(Command::Doctor) = args.command {
+        // The doctor should also run and report if the config is invalid.
+        let config = config.map(|mut config| {
+            update_config_with_args(&mut config, &args);
+            config
+        });
+        return doctor::doctor(config, &dirs).await;
+    }
+    let mut config = config?;
     update_config_with_args(&mut config, &args);
     let config = Box::leak(Box::new(config));
 
     match args.command.unwrap_or_default() {
         Command::Run => run(logger, logger_rx, config, &dirs).await?,
         Command::Export(args) => export(config, &dirs, args).await?,
-        Command::Gc => gc(config, &dirs).await?,
+        Command::Import(args) => import(config, &dirs, args).await?,
+        Command::Serve(args) => serve(config, &dirs, args).await?,
+        Command::BenchQueries(args) => bench_queries(config, &dirs, args).await?,
+        Command::Gc { older_than } => gc(config, &dirs, older_than).await?,
+        Command::Stats => stats(config, &dirs).await?,
         Command::ClearCookies { domain } => clear_cookies(config, &dirs, domain).await?,
         Command::HelpConfig => help_config(),
+        Command::Doctor => unreachable!("handled before loading config"),
     }
 
     // Print all logged errors. This should always happen, even if cove panics,
@@ -175,7 +262,12 @@ async fn run(
 ) -> anyhow::Result<()> {
     info!("Welcome to {NAME} {VERSION}",);
 
-    let tz = util::load_time_zone(config.time_zone_ref()).context("failed to load time zone")?;
+    let tz = util::load_time_zone(config.time_zone_ref(), config.time_zone_fallback_ref())
+        .context("failed to load time zone")?;
+    info!(
+        "Using time zone {}",
+        tz.iana_name().unwrap_or("specified by TZ string")
+    );
 
     let vault = open_vault(config, dirs)?;
 
@@ -195,15 +287,86 @@ async fn export(
 ) -> anyhow::Result<()> {
     let vault = open_vault(config, dirs)?;
 
-    export::export(&vault.euph(), args).await?;
+    let tz = util::load_time_zone(config.time_zone_ref(), config.time_zone_fallback_ref())
+        .context("failed to load time zone")?;
+    export::export(&vault.euph(), args, &tz).await?;
 
     vault.close().await;
     Ok(())
 }
 
-async fn gc(config: &'static Config, dirs: &ProjectDirs) -> anyhow::Result<()> {
+async fn import(
+    config: &'static Config,
+    dirs: &ProjectDirs,
+    args: import::Args,
+) -> anyhow::Result<()> {
     let vault = open_vault(config, dirs)?;
 
+    import::import(&vault.euph(), args).await?;
+
+    vault.close().await;
+    Ok(())
+}
+
+async fn serve(
+    config: &'static Config,
+    dirs: &ProjectDirs,
+    args: serve::Args,
+) -> anyhow::Result<()> {
+    // Serving never modifies the vault, so it shouldn't lock out the TUI.
+    let vault = open_vault_read_only(config, dirs, true)?;
+
+    serve::serve(&vault.euph(), args).await?;
+
+    vault.close().await;
+    Ok(())
+}
+
+async fn bench_queries(
+    config: &'static Config,
+    dirs: &ProjectDirs,
+    args: bench::Args,
+) -> anyhow::Result<()> {
+    let vault = if args.room.is_some() {
+        // The queries only read, so existing rooms are benchmarked without
+        // migrating or locking the vault.
+        open_vault_read_only(config, dirs, true)?
+    } else {
+        vault::launch_in_memory(config)?
+    };
+
+    bench::bench(&vault.euph(), args).await?;
+
+    vault.close().await;
+    Ok(())
+}
+
+async fn gc(
+    config: &'static Config,
+    dirs: &ProjectDirs,
+    older_than: Option<Duration>,
+) -> anyhow::Result<()> {
+    let vault = open_vault(config, dirs)?;
+
+    if let Some(older_than) = older_than {
+        let age = i64::try_from(older_than.as_secs()).unwrap_or(i64::MAX);
+        let time = Time(Time::now().0.saturating_sub(age));
+
+        for room in vault.euph().rooms().await? {
+            let deleted = vault
+                .euph()
+                .room(room.clone())
+                .delete_older_msgs(time)
+                .await?;
+            if deleted > 0 {
+                eprintln!(
+                    "Deleted {deleted} messages from &{}@{}",
+                    room.name, room.domain
+                );
+            }
+        }
+    }
+
     eprintln!("Cleaning up and compacting vault");
     eprintln!("This may take a while...");
     vault.gc().await?;
@@ -212,6 +375,15 @@ async fn gc(config: &'static Config, dirs: &ProjectDirs) -> anyhow::Result<()> {
     Ok(())
 }
 
+async fn stats(config: &'static Config, dirs: &ProjectDirs) -> anyhow::Result<()> {
+    let vault = open_vault_read_only(config, dirs, false)?;
+
+    stats::stats(&vault).await?;
+
+    vault.close().await;
+    Ok(())
+}
+
 async fn clear_cookies(
     config: &'static Config,
     dirs: &ProjectDirs,
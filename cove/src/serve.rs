@@ -0,0 +1,279 @@
+//! Serve the vault's contents as JSON over a minimal read-only HTTP server.
+//!
+//! Endpoints:
+//!
+//! - `GET /rooms` lists all rooms in the vault.
+//! - `GET /rooms/<domain>/<room>/msgs?after=<id>&amount=<n>` returns up to
+//!   `amount` messages following `after` in the same format as the euphoria API
+//!   uses, along with the id to pass as `after` to get the next page.
+//! - `GET /rooms/<domain>/<room>/trees/<id>` returns the message tree rooted at
+//!   `id` with nested children. Large trees are truncated according to the
+//!   `max_tree_size` config option.
+//! - `GET /rooms/<domain>/<room>/threads/<id>` returns all messages of the
+//!   thread rooted at `id` as a flat list in the same format as the euphoria
+//!   API uses, ordered by id.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::extract::{Path, Query, Request, State};
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use euphoxide::api::MessageId;
+use hyper::server::conn::http1;
+use hyper_util::rt::{TokioIo, TokioTimer};
+use hyper_util::service::TowerToHyperService;
+use log::{debug, warn};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+use crate::euph::SmallMessage;
+use crate::store::Tree;
+use crate::vault::{EuphVault, RoomIdentifier};
+
+/// How long clients may take to send the head of a request before the
+/// connection is closed.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_AMOUNT: usize = 100;
+const MAX_AMOUNT: usize = 1000;
+
+#[derive(Debug, clap::Parser)]
+pub struct Args {
+    /// Address to listen on.
+    ///
+    /// Binding to anything other than localhost exposes the entire vault to
+    /// the network.
+    #[arg(long, short, default_value = "127.0.0.1:8080")]
+    bind: SocketAddr,
+}
+
+enum Response {
+    Ok(Value),
+    BadRequest(&'static str),
+    NotFound,
+    MethodNotAllowed,
+    InternalError,
+}
+
+impl Response {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::Ok(_) => StatusCode::OK,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            Self::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn body(self) -> Value {
+        match self {
+            Self::Ok(value) => value,
+            Self::BadRequest(reason) => json!({ "error": reason }),
+            Self::NotFound => json!({ "error": "not found" }),
+            Self::MethodNotAllowed => json!({ "error": "only GET is supported" }),
+            Self::InternalError => json!({ "error": "internal error" }),
+        }
+    }
+
+    fn from_result(result: anyhow::Result<Self>) -> Self {
+        result.unwrap_or_else(|err| {
+            warn!("Failed to handle request: {err}");
+            Self::InternalError
+        })
+    }
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> axum::response::Response {
+        (self.status(), Json(self.body())).into_response()
+    }
+}
+
+fn parse_msg_id(s: &str) -> Option<MessageId> {
+    serde_json::from_value(Value::String(s.to_string())).ok()
+}
+
+fn tree_json(tree: &Tree<SmallMessage>, id: &MessageId) -> Value {
+    let children = tree
+        .children(id)
+        .unwrap_or_default()
+        .iter()
+        .map(|child| tree_json(tree, child))
+        .collect::<Vec<_>>();
+
+    match tree.msg(id) {
+        Some(msg) => json!({
+            "id": msg.id,
+            "parent": msg.parent,
+            "time": msg.time,
+            "nick": msg.nick,
+            "content": msg.content,
+            "children": children,
+        }),
+        // Placeholder for messages that are referenced but not in the vault
+        None => json!({ "id": id, "children": children }),
+    }
+}
+
+async fn rooms(vault: &EuphVault) -> anyhow::Result<Response> {
+    let mut rooms = vault.rooms().await?;
+    rooms.sort_unstable_by(|a, b| (&a.domain, &a.name).cmp(&(&b.domain, &b.name)));
+    let rooms = rooms
+        .into_iter()
+        .map(|id| json!({ "domain": id.domain, "name": id.name }))
+        .collect::<Vec<_>>();
+    Ok(Response::Ok(Value::Array(rooms)))
+}
+
+async fn msgs(
+    vault: &EuphVault,
+    room: RoomIdentifier,
+    query: &HashMap<String, String>,
+) -> anyhow::Result<Response> {
+    let after = match query.get("after") {
+        None => None,
+        Some(after) => match parse_msg_id(after) {
+            Some(id) => Some(id),
+            None => return Ok(Response::BadRequest("invalid message id")),
+        },
+    };
+    let amount = match query.get("amount").map(|a| a.parse::<usize>()) {
+        None => DEFAULT_AMOUNT,
+        Some(Ok(amount)) => amount.min(MAX_AMOUNT),
+        Some(Err(_)) => return Ok(Response::BadRequest("invalid amount")),
+    };
+
+    let msgs = vault.room(room).chunk_after(after, amount).await?;
+    let next = msgs.last().map(|msg| msg.id);
+    Ok(Response::Ok(json!({ "msgs": msgs, "next": next })))
+}
+
+async fn tree(vault: &EuphVault, room: RoomIdentifier, id: &str) -> anyhow::Result<Response> {
+    let Some(id) = parse_msg_id(id) else {
+        return Ok(Response::BadRequest("invalid message id"));
+    };
+    let vault = vault.room(room);
+    if vault.msg(id).await?.is_none() {
+        return Ok(Response::NotFound);
+    }
+    let limit = vault.vault().vault().max_tree_size();
+    let tree = vault.tree(id, Some(limit)).await?;
+    let mut json = tree_json(&tree, &id);
+    json["truncated"] = tree.truncated().into();
+    Ok(Response::Ok(json))
+}
+
+async fn thread(vault: &EuphVault, room: RoomIdentifier, id: &str) -> anyhow::Result<Response> {
+    let Some(id) = parse_msg_id(id) else {
+        return Ok(Response::BadRequest("invalid message id"));
+    };
+    let msgs = vault.room(room).subtree_full_msgs(id).await?;
+    if msgs.is_empty() {
+        return Ok(Response::NotFound);
+    }
+    Ok(Response::Ok(json!({ "msgs": msgs })))
+}
+
+async fn get_rooms(State(vault): State<EuphVault>) -> Response {
+    Response::from_result(rooms(&vault).await)
+}
+
+async fn get_msgs(
+    State(vault): State<EuphVault>,
+    Path((domain, name)): Path<(String, String)>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let room = RoomIdentifier::new(domain, name);
+    Response::from_result(msgs(&vault, room, &query).await)
+}
+
+async fn get_tree(
+    State(vault): State<EuphVault>,
+    Path((domain, name, id)): Path<(String, String, String)>,
+) -> Response {
+    let room = RoomIdentifier::new(domain, name);
+    Response::from_result(tree(&vault, room, &id).await)
+}
+
+async fn get_thread(
+    State(vault): State<EuphVault>,
+    Path((domain, name, id)): Path<(String, String, String)>,
+) -> Response {
+    let room = RoomIdentifier::new(domain, name);
+    Response::from_result(thread(&vault, room, &id).await)
+}
+
+async fn not_found() -> Response {
+    Response::NotFound
+}
+
+async fn method_not_allowed() -> Response {
+    Response::MethodNotAllowed
+}
+
+async fn log_request(request: Request, next: Next) -> axum::response::Response {
+    debug!("{} {}", request.method(), request.uri());
+    next.run(request).await
+}
+
+fn router(vault: EuphVault) -> Router {
+    Router::new()
+        .route("/rooms", get(get_rooms).fallback(method_not_allowed))
+        .route(
+            "/rooms/:domain/:name/msgs",
+            get(get_msgs).fallback(method_not_allowed),
+        )
+        .route(
+            "/rooms/:domain/:name/trees/:id",
+            get(get_tree).fallback(method_not_allowed),
+        )
+        .route(
+            "/rooms/:domain/:name/threads/:id",
+            get(get_thread).fallback(method_not_allowed),
+        )
+        .fallback(not_found)
+        .layer(middleware::from_fn(log_request))
+        .with_state(vault)
+}
+
+pub async fn serve(vault: &EuphVault, args: Args) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(args.bind).await?;
+    let router = router(vault.clone());
+    eprintln!("Serving vault on http://{}/rooms", args.bind);
+    eprintln!("Press Ctrl+C to stop");
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        // Errors like running out of file descriptors or
+                        // aborted connections are transient.
+                        warn!("Failed to accept connection: {err}");
+                        continue;
+                    }
+                };
+                let service = TowerToHyperService::new(router.clone());
+                tokio::spawn(async move {
+                    let result = http1::Builder::new()
+                        .timer(TokioTimer::new())
+                        .header_read_timeout(READ_TIMEOUT)
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                    if let Err(err) = result {
+                        warn!("Failed to handle connection: {err}");
+                    }
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+//! Summarize the contents of the vault.
+
+use crate::vault::Vault;
+
+pub async fn stats(vault: &Vault) -> anyhow::Result<()> {
+    let rooms = vault.euph().room_stats().await?;
+
+    // One tab-separated line per room so the output is easy to process further
+    for stats in &rooms {
+        let (oldest, newest) = match stats.time_range {
+            Some((oldest, newest)) => (
+                oldest.as_timestamp().to_string(),
+                newest.as_timestamp().to_string(),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        println!(
+            "{}\t&{}\t{}\t{}\t{oldest}\t{newest}",
+            stats.room.domain, stats.room.name, stats.msgs, stats.unseen,
+        );
+    }
+
+    let msgs = rooms.iter().map(|s| s.msgs).sum::<usize>();
+    let unseen = rooms.iter().map(|s| s.unseen).sum::<usize>();
+    let size = vault.size().await?;
+    eprintln!(
+        "{} rooms, {msgs} messages, {unseen} unseen, {size} bytes",
+        rooms.len(),
+    );
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::vec;
@@ -49,6 +49,7 @@ pub struct Tree<M: Msg> {
     root: M::Id,
     msgs: HashMap<M::Id, M>,
     children: HashMap<M::Id, Vec<M::Id>>,
+    truncated: bool,
 }
 
 impl<M: Msg> Tree<M> {
@@ -71,9 +72,46 @@ impl<M: Msg> Tree<M> {
             root,
             msgs,
             children,
+            truncated: false,
         }
     }
 
+    /// Mark the tree as containing only some of its messages.
+    pub fn with_truncated(mut self, truncated: bool) -> Self {
+        self.truncated = truncated;
+        self
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Keep only the messages matching a predicate, along with their
+    /// ancestors so that the tree stays connected.
+    pub fn filtered(self, keep: impl Fn(&M) -> bool) -> Self {
+        let mut kept = HashSet::new();
+        for (id, msg) in &self.msgs {
+            if kept.contains(id) || !keep(msg) {
+                continue;
+            }
+            let mut id = Some(id.clone());
+            while let Some(some_id) = id {
+                if !kept.insert(some_id.clone()) {
+                    break; // Ancestors were already kept
+                }
+                id = self.parent(&some_id);
+            }
+        }
+
+        let msgs = self
+            .msgs
+            .into_iter()
+            .filter(|(id, _)| kept.contains(id))
+            .map(|(_, msg)| msg)
+            .collect();
+        Self::new(self.root, msgs).with_truncated(self.truncated)
+    }
+
     pub fn len(&self) -> usize {
         self.msgs.len()
     }
@@ -152,4 +190,9 @@ pub trait MsgStore<M: Msg> {
     async fn unseen_msgs_count(&self) -> Result<usize, Self::Error>;
     async fn set_seen(&self, id: &M::Id, seen: bool) -> Result<(), Self::Error>;
     async fn set_older_seen(&self, id: &M::Id, seen: bool) -> Result<(), Self::Error>;
+    async fn folded_ids(&self) -> Result<HashSet<M::Id>, Self::Error>;
+    async fn set_folded(&self, id: &M::Id, folded: bool) -> Result<(), Self::Error>;
+    /// Fold every tree by folding its root.
+    async fn fold_all(&self) -> Result<(), Self::Error>;
+    async fn unfold_all(&self) -> Result<(), Self::Error>;
 }
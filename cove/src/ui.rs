@@ -1,7 +1,9 @@
 mod chat;
 mod euph;
 mod key_bindings;
+mod palette;
 mod rooms;
+mod theme;
 mod util;
 mod widgets;
 
@@ -18,21 +20,27 @@ use tokio::sync::mpsc::error::TryRecvError;
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use tokio::task;
 use toss::widgets::BoxedAsync;
-use toss::{Terminal, WidgetExt};
+use toss::{Terminal, WidgetExt, WidthDb};
 
 use crate::logger::{LogMsg, Logger};
 use crate::macros::logging_unwrap;
 use crate::util::InfallibleExt;
-use crate::vault::Vault;
+use crate::vault::{RoomIdentifier, Vault};
 
-pub use self::chat::ChatMsg;
 use self::chat::ChatState;
+pub use self::chat::{ChatMsg, RenderOptions};
+use self::palette::{PaletteResult, PaletteState};
 use self::rooms::Rooms;
+use self::theme::Theme;
 use self::widgets::ListState;
 
 /// Time to spend batch processing events before redrawing the screen.
 const EVENT_PROCESSING_TIME: Duration = Duration::from_millis(1000 / 15); // 15 fps
 
+/// Time between redraws when nothing else happens, so time-dependent parts of
+/// the UI stay up to date.
+const TICK_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Error for anything that can go wrong while rendering.
 #[derive(Debug, thiserror::Error)]
 pub enum UiError {
@@ -51,8 +59,15 @@ impl From<Infallible> for UiError {
 pub enum UiEvent {
     GraphemeWidthsChanged,
     LogChanged,
+    Tick,
+    /// Redraw without anything else happening, e.g. to keep a countdown up to
+    /// date.
+    Redraw,
     Term(crossterm::event::Event),
     Euph(euphoxide::bot::instance::Event),
+    /// Reconnect to a room. Sent by rooms since only [`Rooms`] can start new
+    /// connections.
+    Reconnect(RoomIdentifier),
 }
 
 enum EventHandleResult {
@@ -78,6 +93,11 @@ pub struct Ui {
 
     key_bindings_visible: bool,
     key_bindings_list: ListState<Infallible>,
+
+    palette: Option<PaletteState>,
+
+    /// All available themes. The first one is the active theme.
+    themes: Vec<Theme>,
 }
 
 impl Ui {
@@ -110,18 +130,26 @@ impl Ui {
         //
         // On the other hand, if the crossterm_event_task stops for any reason,
         // the rest of the UI is also shut down and the client stops.
+        let themes = Theme::load_all(config);
+        let theme = themes[0].clone();
+        let mut log_options = RenderOptions::new(tz.clone());
+        log_options.theme = theme.clone();
+
         let mut ui = Self {
             config,
             event_tx: event_tx.clone(),
             mode: Mode::Main,
-            rooms: Rooms::new(config, tz.clone(), vault, event_tx.clone()).await,
-            log_chat: ChatState::new(logger, tz),
+            rooms: Rooms::new(config, tz, theme, vault, event_tx.clone()).await,
+            log_chat: ChatState::new(logger, log_options),
             key_bindings_visible: false,
             key_bindings_list: ListState::new(),
+            palette: None,
+            themes,
         };
         tokio::select! {
             e = ui.run_main(terminal, event_rx, crossterm_lock) => e?,
             _ = Self::update_on_log_event(logger_rx, &event_tx) => (),
+            _ = Self::tick(&event_tx) => (),
             e = crossterm_event_task => e??,
         }
         Ok(())
@@ -159,6 +187,16 @@ impl Ui {
         }
     }
 
+    async fn tick(event_tx: &UnboundedSender<UiEvent>) {
+        let mut interval = tokio::time::interval(TICK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if event_tx.send(UiEvent::Tick).is_err() {
+                return;
+            }
+        }
+    }
+
     async fn run_main(
         &mut self,
         terminal: &mut Terminal,
@@ -171,7 +209,8 @@ impl Ui {
             // Redraw if necessary
             if redraw {
                 redraw = false;
-                terminal.present_async_widget(self.widget().await).await?;
+                let widget = self.widget(terminal.widthdb()).await;
+                terminal.present_async_widget(widget).await?;
 
                 if terminal.measuring_required() {
                     let _guard = crossterm_lock.lock();
@@ -205,14 +244,35 @@ impl Ui {
         }
     }
 
-    async fn widget(&mut self) -> BoxedAsync<'_, UiError> {
+    async fn widget(&mut self, widthdb: &mut WidthDb) -> BoxedAsync<'_, UiError> {
+        let border = self.themes[0].border;
+
+        // Like the palette, the key bindings list shows the keys in effect for
+        // the shown room. It must be built before the rooms widget borrows the
+        // rooms mutably.
+        let key_bindings = if self.key_bindings_visible {
+            let keys = match self.mode {
+                Mode::Main => self.rooms.keys(),
+                Mode::Log => &self.config.keys,
+            };
+            Some(key_bindings::widget(
+                &mut self.key_bindings_list,
+                keys,
+                border,
+            ))
+        } else {
+            None
+        };
+
         let widget = match self.mode {
-            Mode::Main => self.rooms.widget().await,
+            Mode::Main => self.rooms.widget(widthdb).await,
             Mode::Log => self.log_chat.widget(String::new(), true),
         };
 
-        if self.key_bindings_visible {
-            let popup = key_bindings::widget(&mut self.key_bindings_list, self.config);
+        if let Some(popup) = key_bindings {
+            popup.desync().above(widget).boxed_async()
+        } else if let Some(palette) = &mut self.palette {
+            let popup = palette.widget(border);
             popup.desync().above(widget).boxed_async()
         } else {
             widget
@@ -229,6 +289,11 @@ impl Ui {
             UiEvent::GraphemeWidthsChanged => EventHandleResult::Redraw,
             UiEvent::LogChanged if self.mode == Mode::Log => EventHandleResult::Redraw,
             UiEvent::LogChanged => EventHandleResult::Continue,
+            UiEvent::Tick => {
+                self.rooms.disconnect_idle_rooms();
+                EventHandleResult::Redraw
+            }
+            UiEvent::Redraw => EventHandleResult::Redraw,
             UiEvent::Term(crossterm::event::Event::Resize(_, _)) => EventHandleResult::Redraw,
             UiEvent::Term(event) => {
                 self.handle_term_event(terminal, crossterm_lock.clone(), event)
@@ -241,6 +306,10 @@ impl Ui {
                     EventHandleResult::Continue
                 }
             }
+            UiEvent::Reconnect(room) => {
+                self.rooms.reconnect_to_room(room).await;
+                EventHandleResult::Redraw
+            }
         }
     }
 
@@ -250,7 +319,8 @@ impl Ui {
         crossterm_lock: Arc<FairMutex<()>>,
         event: crossterm::event::Event,
     ) -> EventHandleResult {
-        let mut event = InputEvent::new(event, terminal, crossterm_lock);
+        let mut event = InputEvent::new(event, terminal, crossterm_lock)
+            .with_editor_command(self.config.editor_command.as_deref());
         let keys = &self.config.keys;
 
         if event.matches(&keys.general.exit) {
@@ -270,11 +340,54 @@ impl Ui {
             return EventHandleResult::Continue;
         }
 
+        // Like the key bindings list, the palette captures all events
+        if let Some(palette) = &mut self.palette {
+            match palette.handle_input_event(&mut event, keys) {
+                PaletteResult::NotHandled => return EventHandleResult::Continue,
+                PaletteResult::Handled => return EventHandleResult::Redraw,
+                PaletteResult::Close => {
+                    self.palette = None;
+                    return EventHandleResult::Redraw;
+                }
+                PaletteResult::Execute(key) => {
+                    self.palette = None;
+                    // Handled like a real key press once the palette is gone
+                    let event = crossterm::event::Event::Key(key);
+                    if self.event_tx.send(UiEvent::Term(event)).is_err() {
+                        return EventHandleResult::Stop;
+                    }
+                    return EventHandleResult::Redraw;
+                }
+            }
+        }
+
         if event.matches(&keys.general.help) {
             self.key_bindings_visible = true;
             return EventHandleResult::Redraw;
         }
 
+        if event.matches(&keys.general.cycle_theme) {
+            self.themes.rotate_left(1);
+            let theme = &self.themes[0];
+            self.rooms.set_theme(theme.clone());
+            self.log_chat.options_mut().theme = theme.clone();
+            return EventHandleResult::Redraw;
+        }
+
+        if event.matches(&keys.general.palette) {
+            let context = match self.mode {
+                Mode::Main if self.rooms.showing_room() => palette::Context::Room,
+                Mode::Main => palette::Context::RoomList,
+                Mode::Log => palette::Context::Log,
+            };
+            let keys = match self.mode {
+                Mode::Main => self.rooms.keys(),
+                Mode::Log => keys,
+            };
+            self.palette = Some(PaletteState::new(keys, context));
+            return EventHandleResult::Redraw;
+        }
+
         match self.mode {
             Mode::Main => {
                 if event.matches(&keys.general.log) {
@@ -1,55 +1,145 @@
 mod blocks;
 mod cursor;
 mod renderer;
+mod search;
 mod tree;
 mod widgets;
 
-use cove_config::Keys;
+use std::collections::HashSet;
+
+use cove_config::{EmojiMode, Keys, TimeFormat};
 use cove_input::InputEvent;
 use jiff::tz::TimeZone;
 use jiff::Timestamp;
 use toss::widgets::{BoxedAsync, EditorState};
-use toss::{Styled, WidgetExt};
+use toss::{Styled, WidgetExt, WidthDb};
 
+use crate::euph::{Highlight, NickColors};
 use crate::store::{Msg, MsgStore};
 use crate::util;
 
 use self::cursor::Cursor;
+use self::search::Search;
 use self::tree::TreeViewState;
 
+use super::theme::Theme;
 use super::UiError;
 
 pub trait ChatMsg {
     fn time(&self) -> Option<Timestamp>;
-    fn styled(&self) -> (Styled, Styled);
+    fn styled(&self, options: &RenderOptions, widthdb: &mut WidthDb) -> (Styled, Styled);
+    /// Whether the message should be rendered as an ignored placeholder.
+    fn ignored(&self, options: &RenderOptions) -> bool;
     fn edit(nick: &str, content: &str) -> (Styled, Styled);
     fn pseudo(nick: &str, content: &str) -> (Styled, Styled);
 }
 
+/// Options influencing how the messages of a chat are rendered.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Time zone that timestamps are displayed in.
+    pub tz: TimeZone,
+    pub time_format: TimeFormat,
+    /// Custom strftime-style pattern for timestamps, overriding
+    /// [`Self::time_format`].
+    pub timestamp_format: Option<String>,
+    /// Show only the time of day if the chat is narrower than this many
+    /// columns.
+    pub compact_timestamp_width: Option<usize>,
+    /// Whether timestamps are currently shown compactly. This is updated
+    /// according to [`Self::compact_timestamp_width`] whenever the chat is
+    /// drawn.
+    pub compact_timestamps: bool,
+    /// Display messages starting with `/me` like normal messages instead of as
+    /// actions.
+    pub plain_me: bool,
+    /// How to display emoji and emoji shortcodes like `:smile:`.
+    pub emoji: EmojiMode,
+    /// Shorten nicks wider than this many columns.
+    pub max_nick_width: Option<usize>,
+    /// Fixed colors for some nicks.
+    pub nick_colors: NickColors,
+    /// Rules for highlighting parts of message contents.
+    pub highlights: Vec<Highlight>,
+    /// Draw messages nested deeper than this at this depth.
+    pub max_indent: Option<usize>,
+    /// Show at most this many lines of message contents unless expanded.
+    pub max_message_lines: Option<usize>,
+    /// Lines to keep between the cursor and the edges of the chat.
+    pub scrolloff: usize,
+    /// Center the cursor when it jumps to a message far off-screen.
+    pub center_cursor_on_jump: bool,
+    /// Ids of users whose messages are hidden.
+    pub ignored_users: HashSet<String>,
+    pub show_ignored: bool,
+    /// Marker to prefix lines of message contents with that had to be wrapped.
+    pub wrap_marker: Option<String>,
+    /// Styles of message contents and the cursor.
+    pub theme: Theme,
+}
+
+impl RenderOptions {
+    pub fn new(tz: TimeZone) -> Self {
+        Self {
+            tz,
+            time_format: TimeFormat::Full,
+            timestamp_format: None,
+            compact_timestamp_width: None,
+            compact_timestamps: false,
+            plain_me: false,
+            emoji: EmojiMode::Unicode,
+            max_nick_width: None,
+            nick_colors: NickColors::new(),
+            highlights: vec![],
+            max_indent: None,
+            max_message_lines: None,
+            scrolloff: 2,
+            center_cursor_on_jump: false,
+            ignored_users: HashSet::new(),
+            show_ignored: false,
+            wrap_marker: None,
+            theme: Theme::dark(),
+        }
+    }
+}
+
 pub enum Mode {
     Tree,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Follow {
+    Off,
+    /// Keep the cursor at the bottom so new messages scroll into view.
+    On,
+    /// Follow mode was switched off because the user scrolled up manually.
+    Interrupted,
+}
+
 pub struct ChatState<M: Msg, S: MsgStore<M>> {
     store: S,
 
     cursor: Cursor<M::Id>,
     editor: EditorState,
     caesar: i8,
+    follow: Follow,
 
     mode: Mode,
     tree: TreeViewState<M, S>,
+    search: Option<Search<M::Id>>,
 }
 
 impl<M: Msg, S: MsgStore<M> + Clone> ChatState<M, S> {
-    pub fn new(store: S, tz: TimeZone) -> Self {
+    pub fn new(store: S, options: RenderOptions) -> Self {
         Self {
             cursor: Cursor::Bottom,
             editor: EditorState::new(),
             caesar: 0,
+            follow: Follow::Off,
 
             mode: Mode::Tree,
-            tree: TreeViewState::new(store.clone(), tz),
+            tree: TreeViewState::new(store.clone(), options),
+            search: None,
 
             store,
         }
@@ -69,6 +159,12 @@ impl<M: Msg, S: MsgStore<M>> ChatState<M, S> {
         S::Error: Send,
         UiError: From<S::Error>,
     {
+        if self.follow == Follow::On {
+            if let Cursor::Msg(_) = self.cursor {
+                self.cursor = Cursor::Bottom;
+            }
+        }
+
         match self.mode {
             Mode::Tree => self
                 .tree
@@ -95,6 +191,14 @@ impl<M: Msg, S: MsgStore<M>> ChatState<M, S> {
         S: Send + Sync,
         S::Error: Send,
     {
+        if self.follow == Follow::On && matches!(self.cursor, Cursor::Bottom | Cursor::Msg(_)) {
+            if Self::scrolls_up(event, keys) {
+                self.follow = Follow::Interrupted;
+            } else if Self::moves_cursor(event, keys) {
+                return Ok(Reaction::Handled);
+            }
+        }
+
         let reaction = match self.mode {
             Mode::Tree => {
                 self.tree
@@ -125,10 +229,86 @@ impl<M: Msg, S: MsgStore<M>> ChatState<M, S> {
                 Reaction::Handled
             }
 
+            Reaction::NotHandled if event.matches(&keys.tree.action.follow) => {
+                self.follow = match self.follow {
+                    Follow::On => Follow::Off,
+                    Follow::Off | Follow::Interrupted => {
+                        self.cursor = Cursor::Bottom;
+                        Follow::On
+                    }
+                };
+                Reaction::Handled
+            }
+
+            Reaction::NotHandled if event.matches(&keys.tree.action.toggle_ignored) => {
+                let options = self.tree.options_mut();
+                options.show_ignored = !options.show_ignored;
+                Reaction::Handled
+            }
+
+            Reaction::NotHandled if event.matches(&keys.tree.action.toggle_flat) => {
+                self.tree.toggle_flat();
+                Reaction::Handled
+            }
+
+            Reaction::NotHandled if event.matches(&keys.tree.action.toggle_emoji) => {
+                let options = self.tree.options_mut();
+                options.emoji = match options.emoji {
+                    EmojiMode::Unicode => EmojiMode::Shortcode,
+                    EmojiMode::Shortcode => EmojiMode::Raw,
+                    EmojiMode::Raw => EmojiMode::Unicode,
+                };
+                Reaction::Handled
+            }
+
+            Reaction::NotHandled if self.search.is_some() && self.cursor().is_some() => {
+                if event.matches(&keys.tree.action.next_match) {
+                    self.move_to_search_match(true).await?;
+                    Reaction::Handled
+                } else if event.matches(&keys.tree.action.prev_match) {
+                    self.move_to_search_match(false).await?;
+                    Reaction::Handled
+                } else if event.matches(&keys.general.abort) {
+                    self.search = None;
+                    Reaction::Handled
+                } else {
+                    Reaction::NotHandled
+                }
+            }
+
             reaction => reaction,
         })
     }
 
+    fn scrolls_up(event: &mut InputEvent<'_>, keys: &Keys) -> bool {
+        event.matches(&keys.scroll.up_line)
+            || event.matches(&keys.scroll.up_half)
+            || event.matches(&keys.scroll.up_full)
+    }
+
+    fn moves_cursor(event: &mut InputEvent<'_>, keys: &Keys) -> bool {
+        event.matches(&keys.cursor.up)
+            || event.matches(&keys.cursor.down)
+            || event.matches(&keys.cursor.to_top)
+            || event.matches(&keys.cursor.to_bottom)
+            || event.matches(&keys.tree.cursor.to_above_sibling)
+            || event.matches(&keys.tree.cursor.to_below_sibling)
+            || event.matches(&keys.tree.cursor.to_parent)
+            || event.matches(&keys.tree.cursor.to_root)
+            || event.matches(&keys.tree.cursor.to_older_message)
+            || event.matches(&keys.tree.cursor.to_newer_message)
+            || event.matches(&keys.tree.cursor.to_older_unseen_message)
+            || event.matches(&keys.tree.cursor.to_newer_unseen_message)
+            || event.matches(&keys.scroll.down_line)
+            || event.matches(&keys.scroll.down_half)
+            || event.matches(&keys.scroll.down_full)
+            || event.matches(&keys.scroll.center_cursor)
+    }
+
+    pub fn follow(&self) -> Follow {
+        self.follow
+    }
+
     pub fn cursor(&self) -> Option<&M::Id> {
         match &self.cursor {
             Cursor::Msg(id) => Some(id),
@@ -136,6 +316,140 @@ impl<M: Msg, S: MsgStore<M>> ChatState<M, S> {
         }
     }
 
+    pub fn at_bottom(&self) -> bool {
+        matches!(self.cursor, Cursor::Bottom)
+    }
+
+    /// Search the thread the cursor is in and move the cursor to the first
+    /// match at or after the cursor.
+    pub async fn search_thread(
+        &mut self,
+        widthdb: &mut WidthDb,
+        query: String,
+    ) -> Result<(), S::Error>
+    where
+        M: ChatMsg,
+    {
+        let Some(id) = self.cursor().cloned() else {
+            return Ok(());
+        };
+        let root = self.store.path(&id).await?.into_first();
+        let tree = self.store.tree(&root).await?;
+        self.search = Some(Search::in_tree(
+            &tree,
+            self.tree.options(),
+            widthdb,
+            query,
+            &id,
+        ));
+        self.move_to_current_search_match().await
+    }
+
+    /// Show search results found outside of the chat, for example by searching
+    /// the whole room, and move the cursor to the first match at or after the
+    /// cursor.
+    pub async fn show_search_results(
+        &mut self,
+        query: String,
+        matches: Vec<M::Id>,
+    ) -> Result<(), S::Error> {
+        self.search = Some(Search::from_matches(query, matches, self.cursor()));
+        self.move_to_current_search_match().await
+    }
+
+    async fn move_to_current_search_match(&mut self) -> Result<(), S::Error> {
+        if let Some(id) = self.search.as_ref().and_then(|s| s.current()).cloned() {
+            self.tree.unfold_path(&id).await?;
+            self.move_cursor_to(Some(id));
+        }
+        Ok(())
+    }
+
+    async fn move_to_search_match(&mut self, forward: bool) -> Result<(), S::Error> {
+        let Some(search) = &mut self.search else {
+            return Ok(());
+        };
+        let id = if forward {
+            search.next_match()
+        } else {
+            search.prev_match()
+        };
+        if let Some(id) = id.cloned() {
+            self.tree.unfold_path(&id).await?;
+            self.move_cursor_to(Some(id));
+        }
+        Ok(())
+    }
+
+    /// The query of the current search, along with the one-based
+    /// position of the current match and the total amount of matches.
+    pub fn search_status(&self) -> Option<(&str, usize, usize)> {
+        let search = self.search.as_ref()?;
+        let (current, total) = search.position();
+        Some((search.query(), current, total))
+    }
+
+    /// Unfold a message and all of its ancestors, for example when a new reply
+    /// to the message arrives.
+    pub async fn unfold_path(&mut self, id: &M::Id) -> Result<(), S::Error> {
+        self.tree.unfold_path(id).await
+    }
+
+    /// Restore the folded messages from the store.
+    pub async fn load_folded(&mut self) -> Result<(), S::Error> {
+        self.tree.load_folded().await
+    }
+
+    /// Draw a divider below a message, or remove it if `id` is `None`.
+    pub fn set_read_mark(&mut self, id: Option<M::Id>) {
+        self.tree.set_read_mark(id);
+    }
+
+    /// Draw a divider above the oldest unseen message to separate it from
+    /// older seen messages, or remove it if `id` is `None`.
+    pub fn set_unseen_divider(&mut self, id: Option<M::Id>) {
+        self.tree.set_unseen_divider(id);
+    }
+
+    pub fn unseen_divider(&self) -> Option<&M::Id> {
+        self.tree.unseen_divider()
+    }
+
+    pub fn options_mut(&mut self) -> &mut RenderOptions {
+        self.tree.options_mut()
+    }
+
+    /// Switch a message between being shown in full and in shortened form.
+    pub fn toggle_truncated(&mut self, id: &M::Id) {
+        self.tree.toggle_truncated(id);
+    }
+
+    /// Switch a message between showing all lines and only as many as
+    /// [`RenderOptions::max_message_lines`] allows.
+    pub fn toggle_expanded(&mut self, id: &M::Id) {
+        self.tree.toggle_expanded(id);
+    }
+
+    /// Move the cursor to a message, or to the bottom of the chat if `id` is
+    /// `None`. Moving the cursor to a message stops following new messages.
+    pub fn move_cursor_to(&mut self, id: Option<M::Id>) {
+        self.cursor = match id {
+            Some(id) => {
+                if self.follow == Follow::On {
+                    self.follow = Follow::Off;
+                }
+                Cursor::Msg(id)
+            }
+            None => Cursor::Bottom,
+        };
+    }
+
+    /// Replace the message editor's contents, for example with the contents of
+    /// a file requested via [`Reaction::LoadFile`].
+    pub fn set_editor_text(&mut self, widthdb: &mut WidthDb, text: String) {
+        self.editor.set_text(widthdb, text);
+    }
+
     /// A [`Reaction::Composed`] message was sent successfully.
     pub fn send_successful(&mut self, id: M::Id) {
         if let Cursor::Pseudo { .. } = &self.cursor {
@@ -145,6 +459,13 @@ impl<M: Msg, S: MsgStore<M>> ChatState<M, S> {
         }
     }
 
+    /// A [`Reaction::Composed`] message was moved to the outbox instead of
+    /// being sent right away.
+    pub fn send_queued(&mut self) {
+        self.send_failed();
+        self.editor.clear();
+    }
+
     /// A [`Reaction::Composed`] message failed to be sent.
     pub fn send_failed(&mut self) {
         if let Cursor::Pseudo { coming_from, .. } = &self.cursor {
@@ -163,6 +484,7 @@ pub enum Reaction<M: Msg> {
         parent: Option<M::Id>,
         content: String,
     },
+    LoadFile,
 }
 
 impl<M: Msg> Reaction<M> {
@@ -456,6 +456,44 @@ impl<Id: Clone + Eq + Hash> Cursor<Id> {
         Ok(())
     }
 
+    /// Move the editor so it replies to the message before its current parent.
+    /// If the editor is not replying to any message, it replies to the newest
+    /// message instead.
+    pub async fn move_editor_to_older_parent<M, S>(&mut self, store: &S) -> Result<(), S::Error>
+    where
+        M: Msg<Id = Id>,
+        S: MsgStore<M>,
+    {
+        if let Self::Editor { parent, .. } = self {
+            let mut target = parent.clone().map(Self::Msg).unwrap_or(Self::Bottom);
+            target.move_to_older_msg(store).await?;
+            if let Self::Msg(id) = target {
+                *parent = Some(id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the editor so it replies to the message after its current parent.
+    /// If there is no such message, the editor starts a new thread instead.
+    pub async fn move_editor_to_newer_parent<M, S>(&mut self, store: &S) -> Result<(), S::Error>
+    where
+        M: Msg<Id = Id>,
+        S: MsgStore<M>,
+    {
+        if let Self::Editor { parent, .. } = self {
+            if let Some(id) = parent {
+                let mut target = Self::Msg(id.clone());
+                target.move_to_newer_msg(store).await?;
+                *parent = match target {
+                    Self::Msg(id) => Some(id),
+                    _ => None,
+                };
+            }
+        }
+        Ok(())
+    }
+
     /// The outer `Option` shows whether a parent exists or not. The inner
     /// `Option` shows if that parent has an id.
     pub async fn parent_for_normal_tree_reply<M, S>(
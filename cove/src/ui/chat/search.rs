@@ -0,0 +1,112 @@
+//! Searching for text within a single thread or a whole room.
+
+use toss::WidthDb;
+
+use crate::store::{Msg, Tree};
+
+use super::{ChatMsg, RenderOptions};
+
+pub struct Search<Id> {
+    query: String,
+    /// Matching messages in the order they are displayed in.
+    matches: Vec<Id>,
+    current: usize,
+}
+
+impl<Id: Clone + Ord> Search<Id> {
+    /// Find all messages in a tree whose content contains the query, ignoring
+    /// case. The current match is the first one at or after `start`.
+    pub fn in_tree<M>(
+        tree: &Tree<M>,
+        options: &RenderOptions,
+        widthdb: &mut WidthDb,
+        query: String,
+        start: &Id,
+    ) -> Self
+    where
+        M: Msg<Id = Id> + ChatMsg,
+    {
+        let needle = query.to_lowercase();
+        let order = Self::display_order(tree);
+        let start_idx = order.iter().position(|id| id == start).unwrap_or(0);
+
+        let mut matches = vec![];
+        let mut current = None;
+        for (idx, id) in order.into_iter().enumerate() {
+            let Some(msg) = tree.msg(&id) else { continue };
+            let (_, content) = msg.styled(options, widthdb);
+            if content.text().to_lowercase().contains(&needle) {
+                if idx >= start_idx && current.is_none() {
+                    current = Some(matches.len());
+                }
+                matches.push(id);
+            }
+        }
+
+        Self {
+            query,
+            matches,
+            current: current.unwrap_or(0),
+        }
+    }
+
+    /// Use matches found elsewhere, for example by the vault. The matches must
+    /// be sorted from oldest to newest. The current match is the first one at
+    /// or after `start`, or the newest one if there is no such match.
+    pub fn from_matches(query: String, matches: Vec<Id>, start: Option<&Id>) -> Self {
+        let current = start
+            .and_then(|start| matches.iter().position(|id| id >= start))
+            .unwrap_or(matches.len().saturating_sub(1));
+
+        Self {
+            query,
+            matches,
+            current,
+        }
+    }
+
+    fn display_order<M: Msg<Id = Id>>(tree: &Tree<M>) -> Vec<Id> {
+        fn visit<M: Msg>(tree: &Tree<M>, id: &M::Id, order: &mut Vec<M::Id>) {
+            order.push(id.clone());
+            for child in tree.children(id).unwrap_or_default() {
+                visit(tree, child, order);
+            }
+        }
+
+        let mut order = vec![];
+        visit(tree, tree.root(), &mut order);
+        order
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn current(&self) -> Option<&Id> {
+        self.matches.get(self.current)
+    }
+
+    /// One-based position of the current match and the total amount of
+    /// matches.
+    pub fn position(&self) -> (usize, usize) {
+        if self.matches.is_empty() {
+            (0, 0)
+        } else {
+            (self.current + 1, self.matches.len())
+        }
+    }
+
+    pub fn next_match(&mut self) -> Option<&Id> {
+        if !self.matches.is_empty() {
+            self.current = (self.current + 1) % self.matches.len();
+        }
+        self.current()
+    }
+
+    pub fn prev_match(&mut self) -> Option<&Id> {
+        if !self.matches.is_empty() {
+            self.current = (self.current + self.matches.len() - 1) % self.matches.len();
+        }
+        self.current()
+    }
+}
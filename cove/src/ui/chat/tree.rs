@@ -11,12 +11,11 @@ use std::collections::HashSet;
 use async_trait::async_trait;
 use cove_config::Keys;
 use cove_input::InputEvent;
-use jiff::tz::TimeZone;
 use toss::widgets::EditorState;
 use toss::{AsyncWidget, Frame, Pos, Size, WidgetExt, WidthDb};
 
 use crate::store::{Msg, MsgStore};
-use crate::ui::{util, ChatMsg, UiError};
+use crate::ui::{util, ChatMsg, RenderOptions, UiError};
 use crate::util::InfallibleExt;
 
 use self::renderer::{TreeContext, TreeRenderer};
@@ -26,7 +25,7 @@ use super::Reaction;
 
 pub struct TreeViewState<M: Msg, S: MsgStore<M>> {
     store: S,
-    tz: TimeZone,
+    options: RenderOptions,
 
     last_size: Size,
     last_nick: String,
@@ -35,22 +34,108 @@ pub struct TreeViewState<M: Msg, S: MsgStore<M>> {
     last_visible_msgs: Vec<M::Id>,
 
     folded: HashSet<M::Id>,
+    /// Messages shown in their shortened form. Not persisted.
+    truncated: HashSet<M::Id>,
+    /// Messages shown with all lines despite being longer than
+    /// [`RenderOptions::max_message_lines`]. Not persisted.
+    expanded: HashSet<M::Id>,
+    read_mark: Option<M::Id>,
+    unseen_divider: Option<M::Id>,
+    /// Show all messages sorted by id instead of as threads.
+    flat: bool,
 }
 
 impl<M: Msg, S: MsgStore<M>> TreeViewState<M, S> {
-    pub fn new(store: S, tz: TimeZone) -> Self {
+    pub fn new(store: S, options: RenderOptions) -> Self {
         Self {
             store,
-            tz,
+            options,
             last_size: Size::ZERO,
             last_nick: String::new(),
             last_cursor: Cursor::Bottom,
             last_cursor_top: 0,
             last_visible_msgs: vec![],
             folded: HashSet::new(),
+            truncated: HashSet::new(),
+            expanded: HashSet::new(),
+            read_mark: None,
+            unseen_divider: None,
+            flat: false,
         }
     }
 
+    pub fn set_read_mark(&mut self, id: Option<M::Id>) {
+        self.read_mark = id;
+    }
+
+    pub fn set_unseen_divider(&mut self, id: Option<M::Id>) {
+        self.unseen_divider = id;
+    }
+
+    pub fn unseen_divider(&self) -> Option<&M::Id> {
+        self.unseen_divider.as_ref()
+    }
+
+    pub fn toggle_truncated(&mut self, id: &M::Id) {
+        if !self.truncated.remove(id) {
+            self.truncated.insert(id.clone());
+        }
+    }
+
+    pub fn toggle_expanded(&mut self, id: &M::Id) {
+        if !self.expanded.remove(id) {
+            self.expanded.insert(id.clone());
+        }
+    }
+
+    pub fn toggle_flat(&mut self) {
+        self.flat = !self.flat;
+    }
+
+    pub fn options(&self) -> &RenderOptions {
+        &self.options
+    }
+
+    pub fn options_mut(&mut self) -> &mut RenderOptions {
+        &mut self.options
+    }
+
+    /// Unfold a message and all of its ancestors so that it and its replies
+    /// are visible.
+    pub async fn unfold_path(&mut self, id: &M::Id) -> Result<(), S::Error> {
+        let path = self.store.path(id).await?;
+        for segment in path {
+            if self.folded.remove(&segment) {
+                self.store.set_folded(&segment, false).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fold every thread, moving the cursor to the root of its thread so it
+    /// stays visible.
+    async fn fold_all(&mut self, cursor: &mut Cursor<M::Id>) -> Result<(), S::Error> {
+        self.store.fold_all().await?;
+        self.load_folded().await?;
+
+        if let Cursor::Msg(_) = cursor {
+            cursor.move_to_root(&self.store).await?;
+        }
+        Ok(())
+    }
+
+    async fn unfold_all(&mut self) -> Result<(), S::Error> {
+        self.store.unfold_all().await?;
+        self.folded.clear();
+        Ok(())
+    }
+
+    /// Replace the folded messages with the ones persisted in the store.
+    pub async fn load_folded(&mut self) -> Result<(), S::Error> {
+        self.folded = self.store.folded_ids().await?;
+        Ok(())
+    }
+
     async fn handle_movement_input_event(
         &mut self,
         event: &mut InputEvent<'_>,
@@ -68,11 +153,19 @@ impl<M: Msg, S: MsgStore<M>> TreeViewState<M, S> {
 
         // Basic cursor movement
         if event.matches(&keys.cursor.up) {
-            cursor.move_up_in_tree(&self.store, &self.folded).await?;
+            if self.flat {
+                cursor.move_to_older_msg(&self.store).await?;
+            } else {
+                cursor.move_up_in_tree(&self.store, &self.folded).await?;
+            }
             return Ok(true);
         }
         if event.matches(&keys.cursor.down) {
-            cursor.move_down_in_tree(&self.store, &self.folded).await?;
+            if self.flat {
+                cursor.move_to_newer_msg(&self.store).await?;
+            } else {
+                cursor.move_down_in_tree(&self.store, &self.folded).await?;
+            }
             return Ok(true);
         }
         if event.matches(&keys.cursor.to_top) {
@@ -163,13 +256,26 @@ impl<M: Msg, S: MsgStore<M>> TreeViewState<M, S> {
         &mut self,
         event: &mut InputEvent<'_>,
         keys: &Keys,
+        cursor: &mut Cursor<M::Id>,
         id: Option<&M::Id>,
     ) -> Result<bool, S::Error> {
+        if event.matches(&keys.tree.action.fold_all) {
+            self.fold_all(cursor).await?;
+            return Ok(true);
+        }
+
+        if event.matches(&keys.tree.action.unfold_all) {
+            self.unfold_all().await?;
+            return Ok(true);
+        }
+
         if event.matches(&keys.tree.action.fold_tree) {
             if let Some(id) = id {
-                if !self.folded.remove(id) {
+                let folded = !self.folded.remove(id);
+                if folded {
                     self.folded.insert(id.clone());
                 }
+                self.store.set_folded(id, folded).await?;
             }
             return Ok(true);
         }
@@ -265,7 +371,7 @@ impl<M: Msg, S: MsgStore<M>> TreeViewState<M, S> {
         }
 
         if self
-            .handle_action_input_event(event, keys, id.as_ref())
+            .handle_action_input_event(event, keys, cursor, id.as_ref())
             .await?
         {
             return Ok(true);
@@ -282,7 +388,7 @@ impl<M: Msg, S: MsgStore<M>> TreeViewState<M, S> {
         Ok(false)
     }
 
-    fn handle_editor_input_event(
+    async fn handle_editor_input_event(
         &mut self,
         event: &mut InputEvent<'_>,
         keys: &Keys,
@@ -290,34 +396,54 @@ impl<M: Msg, S: MsgStore<M>> TreeViewState<M, S> {
         editor: &mut EditorState,
         coming_from: Option<M::Id>,
         parent: Option<M::Id>,
-    ) -> Reaction<M> {
+    ) -> Result<Reaction<M>, S::Error>
+    where
+        M: ChatMsg + Send + Sync,
+        M::Id: Send + Sync,
+        S: Send + Sync,
+        S::Error: Send,
+    {
         // Abort edit
         if event.matches(&keys.general.abort) {
             *cursor = coming_from.map(Cursor::Msg).unwrap_or(Cursor::Bottom);
-            return Reaction::Handled;
+            return Ok(Reaction::Handled);
+        }
+
+        // Change reply target, keeping the draft
+        if event.matches(&keys.tree.action.reply_to_older) {
+            cursor.move_editor_to_older_parent(&self.store).await?;
+            return Ok(Reaction::Handled);
+        }
+        if event.matches(&keys.tree.action.reply_to_newer) {
+            cursor.move_editor_to_newer_parent(&self.store).await?;
+            return Ok(Reaction::Handled);
         }
 
         // Send message
         if event.matches(&keys.general.confirm) {
             let content = editor.text().to_string();
             if content.trim().is_empty() {
-                return Reaction::Handled;
+                return Ok(Reaction::Handled);
             }
             *cursor = Cursor::Pseudo {
                 coming_from,
                 parent: parent.clone(),
             };
-            return Reaction::Composed { parent, content };
+            return Ok(Reaction::Composed { parent, content });
+        }
+
+        if event.matches(&keys.editor.action.load_file) {
+            return Ok(Reaction::LoadFile);
         }
 
         // TODO Tab-completion
 
         // Editing
         if util::handle_editor_input_event(editor, event, keys, |_| true) {
-            return Reaction::Handled;
+            return Ok(Reaction::Handled);
         }
 
-        Reaction::NotHandled
+        Ok(Reaction::NotHandled)
     }
 
     pub async fn handle_input_event(
@@ -363,6 +489,7 @@ impl<M: Msg, S: MsgStore<M>> TreeViewState<M, S> {
                 let coming_from = coming_from.clone();
                 let parent = parent.clone();
                 self.handle_editor_input_event(event, keys, cursor, editor, coming_from, parent)
+                    .await?
             }
             Cursor::Pseudo { .. } => {
                 if self
@@ -434,6 +561,11 @@ where
     async fn draw(self, frame: &mut Frame) -> Result<(), UiError> {
         let size = frame.size();
 
+        let options = &mut self.state.options;
+        options.compact_timestamps = options
+            .compact_timestamp_width
+            .is_some_and(|width| usize::from(size.width) < width);
+
         let context = TreeContext {
             size,
             nick: self.nick.clone(),
@@ -441,12 +573,17 @@ where
             caesar: self.caesar,
             last_cursor: self.state.last_cursor.clone(),
             last_cursor_top: self.state.last_cursor_top,
+            read_mark: self.state.read_mark.clone(),
+            truncated: self.state.truncated.clone(),
+            expanded: self.state.expanded.clone(),
+            flat: self.state.flat,
+            unseen_divider: self.state.unseen_divider.clone(),
         };
 
         let mut renderer = TreeRenderer::new(
             context,
             &self.state.store,
-            &self.state.tz,
+            &self.state.options,
             &mut self.state.folded,
             self.cursor,
             self.editor,
@@ -4,7 +4,6 @@ use std::collections::HashSet;
 use std::convert::Infallible;
 
 use async_trait::async_trait;
-use jiff::tz::TimeZone;
 use toss::widgets::{EditorState, Empty, Predrawn, Resize};
 use toss::{Size, Widget, WidthDb};
 
@@ -12,7 +11,7 @@ use crate::store::{Msg, MsgStore, Tree};
 use crate::ui::chat::blocks::{Block, Blocks, Range};
 use crate::ui::chat::cursor::Cursor;
 use crate::ui::chat::renderer::{self, overlaps, Renderer};
-use crate::ui::ChatMsg;
+use crate::ui::{ChatMsg, RenderOptions};
 use crate::util::InfallibleExt;
 
 use super::widgets;
@@ -31,6 +30,12 @@ pub enum TreeBlockId<Id> {
     /// rendered. It is used for positioning [`Cursor::Editor`] and
     /// [`Cursor::Pseudo`].
     After(Id),
+    /// If a tree was too large to be loaded fully, a block with the id of its
+    /// root is rendered after all of its messages.
+    Truncated(Id),
+    /// Directly above the oldest message that was unseen when the room was
+    /// opened, a divider with the id of that message is rendered.
+    UnseenDivider(Id),
 }
 
 impl<Id: Clone> TreeBlockId<Id> {
@@ -54,13 +59,15 @@ impl<Id: Clone> TreeBlockId<Id> {
     pub fn any_id(&self) -> Option<&Id> {
         match self {
             Self::Bottom => None,
-            Self::Msg(id) | Self::After(id) => Some(id),
+            Self::Msg(id) | Self::After(id) | Self::Truncated(id) | Self::UnseenDivider(id) => {
+                Some(id)
+            }
         }
     }
 
     pub fn msg_id(&self) -> Option<&Id> {
         match self {
-            Self::Bottom | Self::After(_) => None,
+            Self::Bottom | Self::After(_) | Self::Truncated(_) | Self::UnseenDivider(_) => None,
             Self::Msg(id) => Some(id),
         }
     }
@@ -76,23 +83,34 @@ pub struct TreeContext<Id> {
     pub caesar: i8,
     pub last_cursor: Cursor<Id>,
     pub last_cursor_top: i32,
+    pub read_mark: Option<Id>,
+    /// Messages to show in their shortened form.
+    pub truncated: HashSet<Id>,
+    /// Messages to show with all lines.
+    pub expanded: HashSet<Id>,
+    /// Render messages as a flat list sorted by id, ignoring their parents.
+    pub flat: bool,
+    /// Message to draw the unseen messages divider above.
+    pub unseen_divider: Option<Id>,
 }
 
 pub struct TreeRenderer<'a, M: Msg, S: MsgStore<M>> {
     context: TreeContext<M::Id>,
 
     store: &'a S,
-    tz: &'a TimeZone,
+    options: &'a RenderOptions,
     folded: &'a mut HashSet<M::Id>,
     cursor: &'a mut Cursor<M::Id>,
     editor: &'a mut EditorState,
     widthdb: &'a mut WidthDb,
 
-    /// Root id of the topmost tree in the blocks. When set to `None`, only the
-    /// bottom of the chat history has been rendered.
+    /// Root id of the topmost tree in the blocks, or id of the topmost message
+    /// in flat mode. When set to `None`, only the bottom of the chat history
+    /// has been rendered.
     top_root_id: Option<M::Id>,
-    /// Root id of the bottommost tree in the blocks. When set to `None`, only
-    /// the bottom of the chat history has been rendered.
+    /// Root id of the bottommost tree in the blocks, or id of the bottommost
+    /// message in flat mode. When set to `None`, only the bottom of the chat
+    /// history has been rendered.
     bottom_root_id: Option<M::Id>,
 
     blocks: TreeBlocks<M::Id>,
@@ -110,7 +128,7 @@ where
     pub fn new(
         context: TreeContext<M::Id>,
         store: &'a S,
-        tz: &'a TimeZone,
+        options: &'a RenderOptions,
         folded: &'a mut HashSet<M::Id>,
         cursor: &'a mut Cursor<M::Id>,
         editor: &'a mut EditorState,
@@ -119,7 +137,7 @@ where
         Self {
             context,
             store,
-            tz,
+            options,
             folded,
             cursor,
             editor,
@@ -158,6 +176,7 @@ where
             &self.context.nick,
             self.context.focused,
             self.editor,
+            self.options,
         );
         let widget = Self::predraw(widget, self.context.size, self.widthdb);
         let mut block = Block::new(id, widget, false);
@@ -176,7 +195,7 @@ where
             None => TreeBlockId::Bottom,
         };
 
-        let widget = widgets::pseudo::<M>(indent, &self.context.nick, self.editor);
+        let widget = widgets::pseudo::<M>(indent, &self.context.nick, self.editor, self.options);
         let widget = Self::predraw(widget, self.context.size, self.widthdb);
         Block::new(id, widget, false)
     }
@@ -194,14 +213,33 @@ where
             _ => false,
         };
         let highlighted = highlighted && self.context.focused;
+        let read_mark = self.context.read_mark.as_ref() == Some(&msg_id);
+        let truncated = self.context.truncated.contains(&msg_id);
+        let expanded = self.context.expanded.contains(&msg_id);
+
+        if msg.ignored(self.options) {
+            let widget = widgets::msg_placeholder(
+                widgets::IGNORED,
+                highlighted,
+                self.options,
+                indent,
+                folded_info,
+            );
+            let widget = Self::predraw(widget, self.context.size, self.widthdb);
+            return Block::new(TreeBlockId::Msg(msg_id), widget, true);
+        }
 
         let widget = widgets::msg(
+            self.widthdb,
             highlighted,
-            self.tz.clone(),
+            self.options,
             indent,
             msg,
             self.context.caesar,
             folded_info,
+            read_mark,
+            truncated,
+            expanded,
         );
         let widget = Self::predraw(widget, self.context.size, self.widthdb);
         Block::new(TreeBlockId::Msg(msg_id), widget, true)
@@ -219,17 +257,38 @@ where
         };
         let highlighted = highlighted && self.context.focused;
 
-        let widget = widgets::msg_placeholder(highlighted, indent, folded_info);
+        let widget = widgets::msg_placeholder(
+            widgets::PLACEHOLDER,
+            highlighted,
+            self.options,
+            indent,
+            folded_info,
+        );
         let widget = Self::predraw(widget, self.context.size, self.widthdb);
         Block::new(TreeBlockId::Msg(msg_id.clone()), widget, true)
     }
 
+    fn unseen_divider_block(&mut self, msg_id: &M::Id) -> TreeBlock<M::Id> {
+        let widget = widgets::unseen_divider(self.options);
+        let widget = Self::predraw(widget, self.context.size, self.widthdb);
+        Block::new(TreeBlockId::UnseenDivider(msg_id.clone()), widget, false)
+    }
+
     fn layout_bottom(&mut self) -> TreeBlocks<M::Id> {
         let mut blocks = Blocks::new(0);
 
+        // In flat mode, there are no threads to place the editor in, so replies
+        // are composed at the bottom as well.
+        let flat = self.context.flat;
         match self.cursor {
-            Cursor::Editor { parent: None, .. } => blocks.push_bottom(self.editor_block(0, None)),
-            Cursor::Pseudo { parent: None, .. } => blocks.push_bottom(self.pseudo_block(0, None)),
+            Cursor::Editor { parent, .. } if flat || parent.is_none() => {
+                let parent = parent.clone();
+                blocks.push_bottom(self.editor_block(0, parent.as_ref()))
+            }
+            Cursor::Pseudo { parent, .. } if flat || parent.is_none() => {
+                let parent = parent.clone();
+                blocks.push_bottom(self.pseudo_block(0, parent.as_ref()))
+            }
             _ => blocks.push_bottom(self.zero_height_block(None)),
         }
 
@@ -250,6 +309,10 @@ where
             None
         };
 
+        if self.context.unseen_divider.as_ref() == Some(msg_id) {
+            blocks.push_bottom(self.unseen_divider_block(msg_id));
+        }
+
         // Message itself
         let block = if let Some(msg) = tree.msg(msg_id) {
             self.message_block(indent, msg, folded_info)
@@ -282,13 +345,54 @@ where
         blocks.push_bottom(block);
     }
 
+    fn truncated_block(&mut self, tree: &Tree<M>) -> TreeBlock<M::Id> {
+        let widget = widgets::truncated(tree.len(), self.options);
+        let widget = Self::predraw(widget, self.context.size, self.widthdb);
+        Block::new(TreeBlockId::Truncated(tree.root().clone()), widget, false)
+    }
+
     fn layout_tree(&mut self, tree: Tree<M>) -> TreeBlocks<M::Id> {
         let mut blocks = Blocks::new(0);
         self.layout_subtree(&tree, 0, tree.root(), &mut blocks);
+        if tree.truncated() {
+            blocks.push_bottom(self.truncated_block(&tree));
+        }
         blocks
     }
 
+    async fn layout_msg(&mut self, msg_id: &M::Id) -> Result<TreeBlocks<M::Id>, S::Error> {
+        let block = if let Some(msg) = self.store.msg(msg_id).await? {
+            self.message_block(0, &msg, None)
+        } else {
+            self.message_placeholder_block(0, msg_id, None)
+        };
+
+        let mut blocks = Blocks::new(0);
+        if self.context.unseen_divider.as_ref() == Some(msg_id) {
+            blocks.push_bottom(self.unseen_divider_block(msg_id));
+        }
+        blocks.push_bottom(block);
+        Ok(blocks)
+    }
+
+    /// Lay out the tree with the given root id, or only the message with the
+    /// given id in flat mode.
+    async fn layout_id(&mut self, id: &M::Id) -> Result<TreeBlocks<M::Id>, S::Error> {
+        if self.context.flat {
+            self.layout_msg(id).await
+        } else {
+            let tree = self.store.tree(id).await?;
+            Ok(self.layout_tree(tree))
+        }
+    }
+
     async fn root_id(&self, id: &TreeBlockId<M::Id>) -> Result<Option<M::Id>, S::Error> {
+        if self.context.flat {
+            // Every message is its own "tree", and the editor is always placed
+            // at the bottom.
+            return Ok(id.msg_id().cloned());
+        }
+
         let Some(id) = id.any_id() else {
             return Ok(None);
         };
@@ -307,31 +411,37 @@ where
         self.top_root_id = root_id.clone();
         self.bottom_root_id = root_id.clone();
 
-        let blocks = if let Some(root_id) = root_id {
-            let tree = self.store.tree(root_id).await?;
-
-            // To ensure the cursor block will be rendered, all its parents must
-            // be unfolded.
-            if let TreeBlockId::Msg(id) | TreeBlockId::After(id) = cursor_id {
-                let mut id = id.clone();
-                while let Some(parent_id) = tree.parent(&id) {
-                    self.folded.remove(&parent_id);
-                    id = parent_id;
+        let blocks = match root_id {
+            Some(msg_id) if self.context.flat => self.layout_msg(msg_id).await?,
+            Some(root_id) => {
+                let tree = self.store.tree(root_id).await?;
+
+                // To ensure the cursor block will be rendered, all its parents
+                // must be unfolded.
+                if let TreeBlockId::Msg(id) | TreeBlockId::After(id) = cursor_id {
+                    let mut id = id.clone();
+                    while let Some(parent_id) = tree.parent(&id) {
+                        self.folded.remove(&parent_id);
+                        id = parent_id;
+                    }
                 }
-            }
 
-            self.layout_tree(tree)
-        } else {
-            self.layout_bottom()
+                self.layout_tree(tree)
+            }
+            None => self.layout_bottom(),
         };
         self.blocks.append_bottom(blocks);
 
         Ok(())
     }
 
-    fn make_cursor_visible(&mut self) {
+    fn make_cursor_visible(&mut self, jumped: bool) {
         let cursor_id = TreeBlockId::from_cursor(self.cursor);
-        if *self.cursor == self.context.last_cursor {
+        if jumped && self.options.center_cursor_on_jump {
+            // Cursor moved far away, so there's no sensible previous scroll
+            // position to stay close to
+            renderer::scroll_so_block_is_centered(self, &cursor_id);
+        } else if *self.cursor == self.context.last_cursor {
             // Cursor did not move, so we just need to ensure it overlaps the
             // scroll area
             renderer::scroll_so_block_focus_overlaps_scroll_area(self, &cursor_id);
@@ -362,7 +472,9 @@ where
 
         // Scroll based on last cursor position
         let last_cursor_id = TreeBlockId::from_cursor(&self.context.last_cursor);
-        if !renderer::scroll_to_set_block_top(self, &last_cursor_id, self.context.last_cursor_top) {
+        let jumped =
+            !renderer::scroll_to_set_block_top(self, &last_cursor_id, self.context.last_cursor_top);
+        if jumped {
             // Since the last cursor is not within scrolling distance of our
             // current cursor, we need to estimate whether the last cursor was
             // above or below the current cursor.
@@ -375,7 +487,7 @@ where
         }
 
         // Fulfill scroll constraints
-        self.make_cursor_visible();
+        self.make_cursor_visible(jumped);
         renderer::clamp_scroll_biased_downwards(self);
 
         Ok(())
@@ -399,7 +511,7 @@ where
 
         self.move_cursor_so_it_is_visible();
 
-        self.make_cursor_visible();
+        self.make_cursor_visible(false);
         renderer::clamp_scroll_biased_downwards(self);
 
         Ok(())
@@ -409,7 +521,7 @@ where
         let cursor_id = TreeBlockId::from_cursor(self.cursor);
         renderer::scroll_so_block_is_centered(self, &cursor_id);
 
-        self.make_cursor_visible();
+        self.make_cursor_visible(false);
         renderer::clamp_scroll_biased_downwards(self);
     }
 
@@ -460,7 +572,13 @@ where
     }
 
     fn scrolloff(&self) -> i32 {
-        2 // TODO Make configurable
+        // A scrolloff of more than half the screen would leave no room for the
+        // cursor to move without scrolling.
+        let height: i32 = self.context.size.height.into();
+        let max = (height - 1).max(0) / 2;
+        i32::try_from(self.options.scrolloff)
+            .unwrap_or(i32::MAX)
+            .min(max)
     }
 
     fn blocks(&self) -> &TreeBlocks<M::Id> {
@@ -472,15 +590,15 @@ where
     }
 
     async fn expand_top(&mut self) -> Result<(), Self::Error> {
-        let prev_root_id = if let Some(top_root_id) = &self.top_root_id {
-            self.store.prev_root_id(top_root_id).await?
-        } else {
-            self.store.last_root_id().await?
+        let prev_root_id = match (&self.top_root_id, self.context.flat) {
+            (Some(top_root_id), false) => self.store.prev_root_id(top_root_id).await?,
+            (None, false) => self.store.last_root_id().await?,
+            (Some(top_msg_id), true) => self.store.older_msg_id(top_msg_id).await?,
+            (None, true) => self.store.newest_msg_id().await?,
         };
 
         if let Some(prev_root_id) = prev_root_id {
-            let tree = self.store.tree(&prev_root_id).await?;
-            let blocks = self.layout_tree(tree);
+            let blocks = self.layout_id(&prev_root_id).await?;
             self.blocks.append_top(blocks);
             self.top_root_id = Some(prev_root_id);
         } else {
@@ -496,10 +614,13 @@ where
             return Ok(());
         };
 
-        let next_root_id = self.store.next_root_id(bottom_root_id).await?;
+        let next_root_id = if self.context.flat {
+            self.store.newer_msg_id(bottom_root_id).await?
+        } else {
+            self.store.next_root_id(bottom_root_id).await?
+        };
         if let Some(next_root_id) = next_root_id {
-            let tree = self.store.tree(&next_root_id).await?;
-            let blocks = self.layout_tree(tree);
+            let blocks = self.layout_id(&next_root_id).await?;
             self.blocks.append_bottom(blocks);
             self.bottom_root_id = Some(next_root_id);
         } else {
@@ -23,6 +23,11 @@ where
             caesar: 0,
             last_cursor: self.last_cursor.clone(),
             last_cursor_top: self.last_cursor_top,
+            read_mark: self.read_mark.clone(),
+            truncated: self.truncated.clone(),
+            expanded: self.expanded.clone(),
+            flat: self.flat,
+            unseen_divider: self.unseen_divider.clone(),
         }
     }
 
@@ -37,7 +42,7 @@ where
         let mut renderer = TreeRenderer::new(
             context,
             &self.store,
-            &self.tz,
+            &self.options,
             &mut self.folded,
             cursor,
             editor,
@@ -65,7 +70,7 @@ where
         let mut renderer = TreeRenderer::new(
             context,
             &self.store,
-            &self.tz,
+            &self.options,
             &mut self.folded,
             cursor,
             editor,
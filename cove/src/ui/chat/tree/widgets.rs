@@ -1,32 +1,35 @@
 use std::convert::Infallible;
 
 use crossterm::style::Stylize;
-use jiff::tz::TimeZone;
 use toss::widgets::{Boxed, EditorState, Join2, Join4, Join5, Text};
-use toss::{Style, Styled, WidgetExt};
+use toss::{Style, Styled, WidgetExt, WidthDb};
 
 use crate::store::Msg;
-use crate::ui::chat::widgets::{Indent, Seen, Time};
-use crate::ui::ChatMsg;
+use crate::ui::chat::widgets::{Indent, MarkedText, Seen, Time};
+use crate::ui::{ChatMsg, RenderOptions};
 use crate::util;
 
 pub const PLACEHOLDER: &str = "[...]";
+pub const IGNORED: &str = "<ignored>";
+
+/// Amount of characters shown of messages in their shortened form.
+const TRUNCATED_LENGTH: usize = 80;
 
 pub fn style_placeholder() -> Style {
     Style::new().dark_grey()
 }
 
-fn style_time(highlighted: bool) -> Style {
+fn style_time(highlighted: bool, options: &RenderOptions) -> Style {
     if highlighted {
-        Style::new().black().on_white()
+        options.theme.cursor
     } else {
         Style::new().grey()
     }
 }
 
-fn style_indent(highlighted: bool) -> Style {
+fn style_indent(highlighted: bool, options: &RenderOptions) -> Style {
     if highlighted {
-        Style::new().black().on_white()
+        options.theme.cursor
     } else {
         Style::new().dark_grey()
     }
@@ -40,6 +43,14 @@ fn style_info() -> Style {
     Style::new().italic().dark_grey()
 }
 
+fn style_read_mark() -> Style {
+    Style::new().bold().magenta()
+}
+
+fn style_unseen_divider() -> Style {
+    Style::new().bold().red()
+}
+
 fn style_editor_highlight() -> Style {
     Style::new().black().on_cyan()
 }
@@ -48,16 +59,60 @@ fn style_pseudo_highlight() -> Style {
     Style::new().black().on_yellow()
 }
 
+/// Shorten content to its first line and at most [`TRUNCATED_LENGTH`]
+/// characters.
+fn truncate_content(content: Styled) -> Styled {
+    let text = content.text();
+    let end = text
+        .char_indices()
+        .take(TRUNCATED_LENGTH)
+        .find(|(_, c)| *c == '\n')
+        .map(|(i, _)| i)
+        .or_else(|| text.char_indices().nth(TRUNCATED_LENGTH).map(|(i, _)| i));
+
+    match end {
+        Some(end) => {
+            let (shown, _) = content.split_at(end);
+            shown.then_plain(" ").then(PLACEHOLDER, style_placeholder())
+        }
+        None => content,
+    }
+}
+
+/// Shorten content to its first `max_lines` lines.
+fn clamp_lines(content: Styled, max_lines: usize) -> Styled {
+    let end = content
+        .text()
+        .match_indices('\n')
+        .nth(max_lines.saturating_sub(1))
+        .map(|(i, _)| i);
+
+    match end {
+        Some(end) => {
+            let (shown, _) = content.split_at(end);
+            shown.then_plain("\n").then("… (show more)", style_info())
+        }
+        None => content,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn msg<M: Msg + ChatMsg>(
+    widthdb: &mut WidthDb,
     highlighted: bool,
-    tz: TimeZone,
+    options: &RenderOptions,
     indent: usize,
     msg: &M,
     caesar: i8,
     folded_info: Option<usize>,
+    read_mark: bool,
+    truncated: bool,
+    expanded: bool,
 ) -> Boxed<'static, Infallible> {
-    let (nick, mut content) = msg.styled();
+    let (nick, mut content) = msg.styled(options, widthdb);
 
+    // Rotate before shortening so the rotation covers the entire message and
+    // not the placeholders added while shortening.
     if caesar != 0 {
         // Apply caesar in inverse because we're decoding
         let rotated = util::caesar(content.text(), -caesar);
@@ -66,21 +121,38 @@ pub fn msg<M: Msg + ChatMsg>(
             .then(format!("{rotated} [rot{caesar}]"), style_caesar());
     }
 
+    if truncated {
+        content = truncate_content(content);
+    } else if let Some(max_lines) = options.max_message_lines.filter(|_| !expanded) {
+        content = clamp_lines(content, max_lines);
+    }
+
     if let Some(amount) = folded_info {
         content = content
             .then_plain("\n")
             .then(format!("[{amount} more]"), style_info());
     }
 
+    if read_mark {
+        content = content
+            .then_plain("\n")
+            .then("── read up to here ──", style_read_mark());
+    }
+
     Join5::horizontal(
         Seen::new(msg.seen()).segment().with_fixed(true),
-        Time::new(msg.time().map(|t| t.to_zoned(tz)), style_time(highlighted))
-            .padding()
-            .with_right(1)
-            .with_stretch(true)
-            .segment()
-            .with_fixed(true),
-        Indent::new(indent, style_indent(highlighted))
+        Time::new(
+            msg.time().map(|t| t.to_zoned(options.tz.clone())),
+            options,
+            style_time(highlighted, options),
+        )
+        .padding()
+        .with_right(1)
+        .with_stretch(true)
+        .segment()
+        .with_fixed(true),
+        Indent::new(indent, style_indent(highlighted, options))
+            .with_max_level(options.max_indent)
             .segment()
             .with_fixed(true),
         Join2::vertical(
@@ -89,23 +161,27 @@ pub fn msg<M: Msg + ChatMsg>(
                 .with_right(1)
                 .segment()
                 .with_fixed(true),
-            Indent::new(1, style_indent(false)).segment(),
+            Indent::new(1, style_indent(false, options)).segment(),
         )
         .segment()
         .with_fixed(true),
         // TODO Minimum content width
         // TODO Minimizing and maximizing messages
-        Text::new(content).segment(),
+        MarkedText::new(content)
+            .with_marker(options.wrap_marker.clone(), style_indent(false, options))
+            .segment(),
     )
     .boxed()
 }
 
 pub fn msg_placeholder(
+    text: &str,
     highlighted: bool,
+    options: &RenderOptions,
     indent: usize,
     folded_info: Option<usize>,
 ) -> Boxed<'static, Infallible> {
-    let mut content = Styled::new(PLACEHOLDER, style_placeholder());
+    let mut content = Styled::new(text, style_placeholder());
 
     if let Some(amount) = folded_info {
         content = content
@@ -115,13 +191,55 @@ pub fn msg_placeholder(
 
     Join4::horizontal(
         Seen::new(true).segment().with_fixed(true),
-        Time::new(None, style_time(highlighted))
+        Time::new(None, options, style_time(highlighted, options))
+            .padding()
+            .with_right(1)
+            .with_stretch(true)
+            .segment()
+            .with_fixed(true),
+        Indent::new(indent, style_indent(highlighted, options))
+            .with_max_level(options.max_indent)
+            .segment()
+            .with_fixed(true),
+        Text::new(content).segment(),
+    )
+    .boxed()
+}
+
+pub fn truncated(shown: usize, options: &RenderOptions) -> Boxed<'static, Infallible> {
+    let content = Styled::new(
+        format!("[tree too large, showing first {shown} messages]"),
+        style_info(),
+    );
+
+    Join4::horizontal(
+        Seen::new(true).segment().with_fixed(true),
+        Time::new(None, options, style_time(false, options))
+            .padding()
+            .with_right(1)
+            .with_stretch(true)
+            .segment()
+            .with_fixed(true),
+        Indent::new(1, style_indent(false, options))
+            .segment()
+            .with_fixed(true),
+        Text::new(content).segment(),
+    )
+    .boxed()
+}
+
+pub fn unseen_divider(options: &RenderOptions) -> Boxed<'static, Infallible> {
+    let content = Styled::new("── new messages ──", style_unseen_divider());
+
+    Join4::horizontal(
+        Seen::new(true).segment().with_fixed(true),
+        Time::new(None, options, style_time(false, options))
             .padding()
             .with_right(1)
             .with_stretch(true)
             .segment()
             .with_fixed(true),
-        Indent::new(indent, style_indent(highlighted))
+        Indent::new(0, style_indent(false, options))
             .segment()
             .with_fixed(true),
         Text::new(content).segment(),
@@ -134,6 +252,7 @@ pub fn editor<'a, M: ChatMsg>(
     nick: &str,
     focus: bool,
     editor: &'a mut EditorState,
+    options: &RenderOptions,
 ) -> Boxed<'a, Infallible> {
     let (nick, content) = M::edit(nick, editor.text());
     let editor = editor
@@ -143,13 +262,14 @@ pub fn editor<'a, M: ChatMsg>(
 
     Join5::horizontal(
         Seen::new(true).segment().with_fixed(true),
-        Time::new(None, style_editor_highlight())
+        Time::new(None, options, style_editor_highlight())
             .padding()
             .with_right(1)
             .with_stretch(true)
             .segment()
             .with_fixed(true),
         Indent::new(indent, style_editor_highlight())
+            .with_max_level(options.max_indent)
             .segment()
             .with_fixed(true),
         Join2::vertical(
@@ -158,7 +278,7 @@ pub fn editor<'a, M: ChatMsg>(
                 .with_right(1)
                 .segment()
                 .with_fixed(true),
-            Indent::new(1, style_indent(false)).segment(),
+            Indent::new(1, style_indent(false, options)).segment(),
         )
         .segment()
         .with_fixed(true),
@@ -171,18 +291,20 @@ pub fn pseudo<'a, M: ChatMsg>(
     indent: usize,
     nick: &str,
     editor: &'a mut EditorState,
+    options: &RenderOptions,
 ) -> Boxed<'a, Infallible> {
     let (nick, content) = M::edit(nick, editor.text());
 
     Join5::horizontal(
         Seen::new(true).segment().with_fixed(true),
-        Time::new(None, style_pseudo_highlight())
+        Time::new(None, options, style_pseudo_highlight())
             .padding()
             .with_right(1)
             .with_stretch(true)
             .segment()
             .with_fixed(true),
         Indent::new(indent, style_pseudo_highlight())
+            .with_max_level(options.max_indent)
             .segment()
             .with_fixed(true),
         Join2::vertical(
@@ -191,7 +313,7 @@ pub fn pseudo<'a, M: ChatMsg>(
                 .with_right(1)
                 .segment()
                 .with_fixed(true),
-            Indent::new(1, style_indent(false)).segment(),
+            Indent::new(1, style_indent(false, options)).segment(),
         )
         .segment()
         .with_fixed(true),
@@ -1,23 +1,61 @@
 use std::convert::Infallible;
 
+use cove_config::TimeFormat;
 use crossterm::style::Stylize;
+use jiff::fmt::strtime;
 use jiff::Zoned;
 use toss::widgets::{Boxed, Empty, Text};
-use toss::{Frame, Pos, Size, Style, Widget, WidgetExt, WidthDb};
+use toss::{Frame, Pos, Size, Style, Styled, Widget, WidgetExt, WidthDb};
 
+use unicode_width::UnicodeWidthStr;
+
+use crate::ui::RenderOptions;
 use crate::util::InfallibleExt;
 
 pub const INDENT_STR: &str = "│ ";
+pub const INDENT_CLAMPED_STR: &str = "» ";
 pub const INDENT_WIDTH: usize = 2;
 
 pub struct Indent {
     level: usize,
+    max_level: Option<usize>,
     style: Style,
 }
 
 impl Indent {
     pub fn new(level: usize, style: Style) -> Self {
-        Self { level, style }
+        Self {
+            level,
+            max_level: None,
+            style,
+        }
+    }
+
+    /// Draw at most `max_level` levels of indentation.
+    ///
+    /// If the indentation is clamped, its last level is drawn differently to
+    /// show that it is deeper than shown.
+    pub fn with_max_level(mut self, max_level: Option<usize>) -> Self {
+        self.max_level = max_level;
+        self
+    }
+
+    fn visual_level(&self) -> usize {
+        match self.max_level {
+            Some(max) => self.level.min(max),
+            None => self.level,
+        }
+    }
+
+    fn indent_string(&self) -> String {
+        let level = self.visual_level();
+        if level < self.level && level > 0 {
+            let mut string = INDENT_STR.repeat(level - 1);
+            string.push_str(INDENT_CLAMPED_STR);
+            string
+        } else {
+            INDENT_STR.repeat(level)
+        }
     }
 }
 
@@ -28,13 +66,15 @@ impl<E> Widget<E> for Indent {
         _max_width: Option<u16>,
         _max_height: Option<u16>,
     ) -> Result<Size, E> {
-        let width = (INDENT_WIDTH * self.level).try_into().unwrap_or(u16::MAX);
+        let width = (INDENT_WIDTH * self.visual_level())
+            .try_into()
+            .unwrap_or(u16::MAX);
         Ok(Size::new(width, 0))
     }
 
     fn draw(self, frame: &mut Frame) -> Result<(), E> {
         let size = frame.size();
-        let indent_string = INDENT_STR.repeat(self.level);
+        let indent_string = self.indent_string();
 
         for y in 0..size.height {
             frame.write(Pos::new(0, y.into()), (&indent_string, self.style))
@@ -44,28 +84,156 @@ impl<E> Widget<E> for Indent {
     }
 }
 
+/// Text that prefixes lines it had to wrap with a marker.
+pub struct MarkedText {
+    text: Styled,
+    marker: Option<(String, Style)>,
+}
+
+impl MarkedText {
+    pub fn new<S: Into<Styled>>(text: S) -> Self {
+        Self {
+            text: text.into(),
+            marker: None,
+        }
+    }
+
+    pub fn with_marker(mut self, marker: Option<String>, style: Style) -> Self {
+        self.marker = marker.map(|marker| (marker, style));
+        self
+    }
+
+    fn text(&self, widthdb: &mut WidthDb, max_width: Option<u16>) -> Text {
+        let (Some((marker, style)), Some(max_width)) = (&self.marker, max_width) else {
+            return Text::new(self.text.clone());
+        };
+
+        let marker_width = widthdb.width(marker);
+        let width = usize::from(max_width).saturating_sub(marker_width).max(1);
+
+        // Split into the original lines first, then wrap each line separately
+        // so only wrapped lines receive the marker.
+        let newlines = self
+            .text
+            .text()
+            .char_indices()
+            .filter(|(_, c)| *c == '\n')
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        let mut result = Styled::default();
+        let lines = self.text.clone().split_at_indices(&newlines);
+        for (i, mut line) in lines.into_iter().enumerate() {
+            if i > 0 {
+                // Every line but the first starts with the newline it was split at
+                line = line.split_at(1).1;
+                result = result.then_plain("\n");
+            }
+
+            let breaks = widthdb.wrap(line.text(), width);
+            let parts = line.split_at_indices(&breaks);
+            for (j, part) in parts.into_iter().enumerate() {
+                if j > 0 {
+                    result = result.then_plain("\n").then(marker, *style);
+                }
+                result = result.and_then(part);
+            }
+        }
+
+        Text::new(result)
+    }
+}
+
+impl<E> Widget<E> for MarkedText {
+    fn size(
+        &self,
+        widthdb: &mut WidthDb,
+        max_width: Option<u16>,
+        max_height: Option<u16>,
+    ) -> Result<Size, E> {
+        let text = self.text(widthdb, max_width);
+        Ok(text.size(widthdb, max_width, max_height).infallible())
+    }
+
+    fn draw(self, frame: &mut Frame) -> Result<(), E> {
+        let text = self.text(frame.widthdb(), Some(frame.size().width));
+        text.draw(frame).infallible();
+        Ok(())
+    }
+}
+
 const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
 const TIME_WIDTH: u16 = 16;
 
+const SMART_DATE_FORMAT: &str = "%Y-%m-%d";
+const SMART_TIME_FORMAT: &str = "%H:%M";
+const SMART_WIDTH: u16 = 10;
+
+const COMPACT_WIDTH: u16 = 5;
+
 pub struct Time(Boxed<'static, Infallible>);
 
 impl Time {
-    pub fn new(time: Option<Zoned>, style: Style) -> Self {
+    pub fn new(time: Option<Zoned>, options: &RenderOptions, style: Style) -> Self {
+        let width = Self::width(options);
         let widget = if let Some(time) = time {
-            let text = time.strftime(TIME_FORMAT).to_string();
+            // Keep the column aligned regardless of which format was used
+            let text = Self::format(&time, options);
+            let padding = usize::from(width).saturating_sub(text.width());
+            let text = format!("{text}{}", " ".repeat(padding));
             Text::new((text, style))
                 .background()
                 .with_style(style)
                 .boxed()
         } else {
             Empty::new()
-                .with_width(TIME_WIDTH)
+                .with_width(width)
                 .background()
                 .with_style(style)
                 .boxed()
         };
         Self(widget)
     }
+
+    fn format(time: &Zoned, options: &RenderOptions) -> String {
+        if options.compact_timestamps {
+            return time.strftime(SMART_TIME_FORMAT).to_string();
+        }
+
+        if let Some(pattern) = &options.timestamp_format {
+            // The pattern was already validated when loading the config
+            return strtime::format(pattern, time).unwrap_or_default();
+        }
+
+        match options.time_format {
+            TimeFormat::Full => time.strftime(TIME_FORMAT).to_string(),
+            TimeFormat::Smart => {
+                let today = Zoned::now().with_time_zone(time.time_zone().clone());
+                if time.date() == today.date() {
+                    time.strftime(SMART_TIME_FORMAT).to_string()
+                } else {
+                    time.strftime(SMART_DATE_FORMAT).to_string()
+                }
+            }
+        }
+    }
+
+    fn width(options: &RenderOptions) -> u16 {
+        if options.compact_timestamps {
+            return COMPACT_WIDTH;
+        }
+
+        if options.timestamp_format.is_some() {
+            let sample = Zoned::now().with_time_zone(options.tz.clone());
+            let width = Self::format(&sample, options).width();
+            return width.try_into().unwrap_or(u16::MAX);
+        }
+
+        match options.time_format {
+            TimeFormat::Full => TIME_WIDTH,
+            TimeFormat::Smart => SMART_WIDTH,
+        }
+    }
 }
 
 impl<E> Widget<E> for Time {
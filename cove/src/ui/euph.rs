@@ -1,8 +1,13 @@
 mod account;
 mod auth;
+mod confirm_seen;
+mod confirm_send;
 mod inspect;
 mod links;
+mod load_file;
 mod nick;
 mod nick_list;
+mod outbox;
 mod popup;
 pub mod room;
+mod search;
@@ -97,7 +97,7 @@ impl AccountUiState {
 
     /// Returns `false` if the account UI should not be displayed any longer.
     pub fn stabilize(&mut self, state: Option<&euph::State>) -> bool {
-        if let Some(euph::State::Connected(_, conn::State::Joined(state))) = state {
+        if let Some(euph::State::Connected(_, conn::State::Joined(state), _)) = state {
             match (&self, &state.account) {
                 (Self::LoggedOut(_), Some(view)) => *self = Self::LoggedIn(LoggedIn(view.clone())),
                 (Self::LoggedIn(_), None) => *self = Self::LoggedOut(LoggedOut::new()),
@@ -0,0 +1,34 @@
+use cove_config::Keys;
+use cove_input::InputEvent;
+use crossterm::style::Stylize;
+use toss::widgets::Text;
+use toss::{Style, Styled, Widget};
+
+use crate::ui::widgets::Popup;
+use crate::ui::{key_bindings, UiError};
+
+use super::popup::PopupResult;
+
+pub fn widget(count: usize, keys: &Keys) -> impl Widget<UiError> {
+    let text = Styled::new_plain("You are about to mark ")
+        .then(format!("{count} messages"), Style::new().bold())
+        .then_plain(" as seen.\n\nPress ")
+        .and_then(key_bindings::format_binding(&keys.general.confirm))
+        .then_plain(" to mark them or ")
+        .and_then(key_bindings::format_binding(&keys.general.abort))
+        .then_plain(" to cancel.");
+
+    Popup::new(Text::new(text), "Mark messages as seen?")
+}
+
+pub fn handle_input_event(event: &mut InputEvent<'_>, keys: &Keys) -> PopupResult {
+    if event.matches(&keys.general.abort) {
+        return PopupResult::Close;
+    }
+
+    if event.matches(&keys.general.confirm) {
+        return PopupResult::MarkOlderSeen;
+    }
+
+    PopupResult::NotHandled
+}
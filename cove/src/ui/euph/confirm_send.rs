@@ -0,0 +1,63 @@
+use cove_config::Keys;
+use cove_input::InputEvent;
+use crossterm::style::Stylize;
+use toss::widgets::Text;
+use toss::{Style, Styled, Widget};
+
+use crate::ui::widgets::Popup;
+use crate::ui::{key_bindings, UiError};
+
+use super::popup::PopupResult;
+
+const PREVIEW_LINES: usize = 10;
+
+/// Whether a message is large enough that sending it should be confirmed.
+pub fn needs_confirmation(
+    content: &str,
+    max_lines: Option<usize>,
+    max_chars: Option<usize>,
+) -> bool {
+    let too_many_lines = max_lines.is_some_and(|max| content.lines().count() > max);
+    let too_many_chars = max_chars.is_some_and(|max| content.chars().count() > max);
+    too_many_lines || too_many_chars
+}
+
+pub fn widget(content: &str, keys: &Keys) -> impl Widget<UiError> {
+    let lines = content.lines().count();
+    let chars = content.chars().count();
+
+    let mut preview = content
+        .lines()
+        .take(PREVIEW_LINES)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if lines > PREVIEW_LINES {
+        preview.push_str("\n…");
+    }
+
+    let text = Styled::new_plain("You are about to send ")
+        .then(format!("{lines} lines"), Style::new().bold())
+        .then_plain(" (")
+        .then(format!("{chars} characters"), Style::new().bold())
+        .then_plain("):\n\n")
+        .then(preview, Style::new().grey())
+        .then_plain("\n\nPress ")
+        .and_then(key_bindings::format_binding(&keys.general.confirm))
+        .then_plain(" to send or ")
+        .and_then(key_bindings::format_binding(&keys.general.abort))
+        .then_plain(" to keep editing.");
+
+    Popup::new(Text::new(text), "Send message?")
+}
+
+pub fn handle_input_event(event: &mut InputEvent<'_>, keys: &Keys) -> PopupResult {
+    if event.matches(&keys.general.abort) {
+        return PopupResult::CancelSend;
+    }
+
+    if event.matches(&keys.general.confirm) {
+        return PopupResult::Send;
+    }
+
+    PopupResult::NotHandled
+}
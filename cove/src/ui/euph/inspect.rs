@@ -115,6 +115,20 @@ pub fn message_widget(msg: &Message) -> impl Widget<UiError> {
 
     text = message_lines(text, msg);
 
+    // The previous_edit_id identifies an edit, not a message. Euphoria doesn't
+    // send previous versions of edited messages, so they're never in the vault.
+    if let Some(id) = msg.previous_edit_id {
+        text = text
+            .then_plain("\n")
+            .then("Previous version", heading_style)
+            .then_plain("\n");
+        line!(text, "edit id", id);
+        line!(text, "edited", msg.edited.map(|t| t.0), optional);
+        text = text
+            .then("<not in vault>", Style::new().italic().grey())
+            .then_plain("\n");
+    }
+
     text = text
         .then_plain("\n")
         .then("Sender", heading_style)
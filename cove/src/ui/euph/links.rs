@@ -3,11 +3,13 @@ use cove_input::InputEvent;
 use crossterm::event::KeyCode;
 use crossterm::style::Stylize;
 use linkify::{LinkFinder, LinkKind};
+use tokio::sync::mpsc;
 use toss::widgets::{Join2, Text};
 use toss::{Style, Styled, Widget, WidgetExt};
 
+use crate::link_titles;
 use crate::ui::widgets::{ListBuilder, ListState, Popup};
-use crate::ui::{key_bindings, util, UiError};
+use crate::ui::{key_bindings, util, UiError, UiEvent};
 
 use super::popup::PopupResult;
 
@@ -20,14 +22,24 @@ pub struct LinksState {
 const NUMBER_KEYS: [char; 10] = ['1', '2', '3', '4', '5', '6', '7', '8', '9', '0'];
 
 impl LinksState {
-    pub fn new(config: &'static Config, content: &str) -> Self {
-        let links = LinkFinder::new()
+    pub fn new(
+        config: &'static Config,
+        content: &str,
+        ui_event_tx: &mpsc::UnboundedSender<UiEvent>,
+    ) -> Self {
+        let links: Vec<String> = LinkFinder::new()
             .url_must_have_scheme(false)
             .kinds(&[LinkKind::Url])
             .links(content)
             .map(|l| l.as_str().to_string())
             .collect();
 
+        if config.link_titles {
+            for link in &links {
+                link_titles::fetch(link.clone(), ui_event_tx.clone());
+            }
+        }
+
         Self {
             config,
             links,
@@ -35,7 +47,7 @@ impl LinksState {
         }
     }
 
-    pub fn widget(&mut self) -> impl Widget<UiError> + '_ {
+    pub fn widget(&mut self, keys: &Keys) -> impl Widget<UiError> + '_ {
         let style_selected = Style::new().black().on_white();
 
         let mut list_builder = ListBuilder::new();
@@ -46,6 +58,11 @@ impl LinksState {
 
         for (id, link) in self.links.iter().enumerate() {
             let link = link.clone();
+            let title = if self.config.link_titles {
+                link_titles::get(&link)
+            } else {
+                None
+            };
             if let Some(&number_key) = NUMBER_KEYS.get(id) {
                 list_builder.add_sel(id, move |selected| {
                     let text = if selected {
@@ -57,7 +74,7 @@ impl LinksState {
                             .then_plain(" ")
                             .then_plain(link)
                     };
-                    Text::new(text)
+                    Text::new(Self::with_title(text, title))
                 });
             } else {
                 list_builder.add_sel(id, move |selected| {
@@ -66,16 +83,14 @@ impl LinksState {
                     } else {
                         Styled::new_plain(format!("    {link}"))
                     };
-                    Text::new(text)
+                    Text::new(Self::with_title(text, title))
                 });
             }
         }
 
         let hint_style = Style::new().grey().italic();
         let hint = Styled::new("Open links with ", hint_style)
-            .and_then(key_bindings::format_binding(
-                &self.config.keys.general.confirm,
-            ))
+            .and_then(key_bindings::format_binding(&keys.general.confirm))
             .then(" or the number keys.", hint_style);
 
         Popup::new(
@@ -91,6 +106,13 @@ impl LinksState {
         )
     }
 
+    fn with_title(text: Styled, title: Option<String>) -> Styled {
+        match title {
+            Some(title) => text.then(format!(" - {title}"), Style::new().grey()),
+            None => text,
+        }
+    }
+
     fn open_link_by_id(&self, id: usize) -> PopupResult {
         if let Some(link) = self.links.get(id) {
             // The `http://` or `https://` schema is necessary for open::that to
@@ -0,0 +1,41 @@
+use cove_config::Keys;
+use cove_input::InputEvent;
+use toss::widgets::EditorState;
+use toss::Widget;
+
+use crate::ui::widgets::Popup;
+use crate::ui::{util, UiError};
+
+use super::popup::PopupResult;
+
+pub fn new() -> EditorState {
+    EditorState::new()
+}
+
+pub fn widget(editor: &mut EditorState) -> impl Widget<UiError> + '_ {
+    Popup::new(editor.widget(), "Load file")
+}
+
+pub fn handle_input_event(
+    event: &mut InputEvent<'_>,
+    keys: &Keys,
+    editor: &mut EditorState,
+) -> PopupResult {
+    if event.matches(&keys.general.abort) {
+        return PopupResult::Close;
+    }
+
+    if event.matches(&keys.general.confirm) {
+        let path = editor.text().trim().to_string();
+        if path.is_empty() {
+            return PopupResult::Close;
+        }
+        return PopupResult::LoadFile { path };
+    }
+
+    if util::handle_editor_input_event(editor, event, keys, |c| c != '\n') {
+        return PopupResult::Handled;
+    }
+
+    PopupResult::NotHandled
+}
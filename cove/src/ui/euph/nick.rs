@@ -1,47 +1,98 @@
 use cove_config::Keys;
 use cove_input::InputEvent;
+use crossterm::style::Stylize;
 use euphoxide::conn::Joined;
-use toss::widgets::EditorState;
-use toss::{Style, Widget};
+use toss::widgets::{EditorState, Join2, Text};
+use toss::{Style, Styled, Widget, WidgetExt};
 
-use crate::euph::{self, Room};
+use crate::euph::{self, NickColors};
 use crate::ui::widgets::Popup;
 use crate::ui::{util, UiError};
 
 use super::popup::PopupResult;
 
-pub fn new(joined: Joined) -> EditorState {
-    EditorState::with_initial_text(joined.session.name)
-}
-
-pub fn widget(editor: &mut EditorState) -> impl Widget<UiError> + '_ {
-    let inner = editor
-        .widget()
-        .with_highlight(|s| euph::style_nick_exact(s, Style::new()));
+/// Maximum nick length enforced by euphoria (heim) servers.
+const MAX_NICK_LENGTH: usize = 36;
 
-    Popup::new(inner, "Choose nick")
+pub struct NickState {
+    editor: EditorState,
+    /// Reason the server gave for rejecting the previously chosen nick.
+    error: Option<String>,
 }
 
-pub fn handle_input_event(
-    event: &mut InputEvent<'_>,
-    keys: &Keys,
-    room: &Option<Room>,
-    editor: &mut EditorState,
-) -> PopupResult {
-    if event.matches(&keys.general.abort) {
-        return PopupResult::Close;
+impl NickState {
+    pub fn new(joined: Joined) -> Self {
+        Self {
+            editor: EditorState::with_initial_text(joined.session.name),
+            error: None,
+        }
     }
 
-    if event.matches(&keys.general.confirm) {
-        if let Some(room) = &room {
-            let _ = room.nick(editor.text().to_string());
+    /// Reopen the popup after the server rejected `nick`, so it can be fixed
+    /// instead of retyped.
+    pub fn rejected(nick: String, reason: String) -> Self {
+        Self {
+            editor: EditorState::with_initial_text(nick),
+            error: Some(reason),
         }
-        return PopupResult::Close;
     }
 
-    if util::handle_editor_input_event(editor, event, keys, |c| c != '\n') {
-        return PopupResult::Handled;
+    fn hint(&self) -> Option<String> {
+        let nick = self.editor.text();
+        if nick.trim().is_empty() {
+            Some("Nick must not be empty.".to_string())
+        } else if nick.chars().count() > MAX_NICK_LENGTH {
+            Some(format!(
+                "Nick must be at most {MAX_NICK_LENGTH} characters long."
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn widget(&mut self) -> impl Widget<UiError> + '_ {
+        let mut info = Styled::default();
+        if let Some(error) = &self.error {
+            info = info
+                .then("Rejected: ", Style::new().red().bold())
+                .then_plain(error);
+        }
+        if let Some(hint) = self.hint() {
+            if !info.text().is_empty() {
+                info = info.then_plain("\n");
+            }
+            info = info.then(hint, Style::new().grey().italic());
+        }
+
+        let editor = self
+            .editor
+            .widget()
+            .with_highlight(|s| euph::style_nick_exact(s, Style::new(), &NickColors::new()));
+
+        let inner = if info.text().is_empty() {
+            editor.first2()
+        } else {
+            Join2::vertical(editor.segment(), Text::new(info).segment().with_fixed(true)).second2()
+        };
+
+        Popup::new(inner, "Choose nick")
     }
 
-    PopupResult::NotHandled
+    pub fn handle_input_event(&mut self, event: &mut InputEvent<'_>, keys: &Keys) -> PopupResult {
+        if event.matches(&keys.general.abort) {
+            return PopupResult::Close;
+        }
+
+        if event.matches(&keys.general.confirm) {
+            return PopupResult::SetNick {
+                nick: self.editor.text().to_string(),
+            };
+        }
+
+        if util::handle_editor_input_event(&mut self.editor, event, keys, |c| c != '\n') {
+            return PopupResult::Handled;
+        }
+
+        PopupResult::NotHandled
+    }
 }
@@ -1,23 +1,162 @@
+use std::collections::{HashMap, HashSet};
 use std::iter;
 
-use crossterm::style::{Color, Stylize};
+use cove_config::{Keys, NickListSort};
+use cove_input::InputEvent;
+use crossterm::style::Stylize;
 use euphoxide::api::{NickEvent, SessionId, SessionType, SessionView, UserId};
 use euphoxide::conn::{Joined, SessionInfo};
-use toss::widgets::{Background, Text};
-use toss::{Style, Styled, Widget, WidgetExt};
+use toss::widgets::{Background, EditorState, Join2, Text};
+use toss::{Style, Styled, Widget, WidgetExt, WidthDb};
 
-use crate::euph;
+use crate::euph::{self, NickColors};
 use crate::ui::widgets::{ListBuilder, ListState};
-use crate::ui::UiError;
+use crate::ui::{util, UiError};
+
+pub struct NickListState {
+    list: ListState<SessionId>,
+    /// Only sessions whose name contains this text are shown.
+    filter: EditorState,
+    /// Whether the filter is currently being edited.
+    filtering: bool,
+    /// Sessions in the order they joined the room.
+    joined_order: Vec<SessionId>,
+}
+
+impl NickListState {
+    pub fn new() -> Self {
+        Self {
+            list: ListState::new(),
+            filter: EditorState::new(),
+            filtering: false,
+            joined_order: vec![],
+        }
+    }
+
+    /// Append newly joined sessions to and remove parted sessions from the
+    /// join order.
+    fn update_joined_order(&mut self, sessions: &[HalfSession]) {
+        let present = sessions
+            .iter()
+            .map(|s| &s.session_id)
+            .collect::<HashSet<_>>();
+        self.joined_order.retain(|id| present.contains(id));
+
+        let known = self.joined_order.iter().cloned().collect::<HashSet<_>>();
+        let mut new = sessions
+            .iter()
+            .filter(|s| !known.contains(&s.session_id))
+            .collect::<Vec<_>>();
+        new.sort_unstable();
+        self.joined_order
+            .extend(new.into_iter().map(|s| s.session_id.clone()));
+    }
+
+    pub fn selected(&self) -> Option<&SessionId> {
+        self.list.selected()
+    }
+
+    pub fn handle_input_event(&mut self, event: &mut InputEvent<'_>, keys: &Keys) -> bool {
+        if self.filtering {
+            if event.matches(&keys.general.abort) {
+                self.filter.clear();
+                self.filtering = false;
+                return true;
+            }
+
+            if event.matches(&keys.general.confirm) {
+                self.filtering = false;
+                return true;
+            }
+
+            if util::handle_editor_input_event(&mut self.filter, event, keys, |c| c != '\n') {
+                return true;
+            }
+        } else if event.matches(&keys.room.action.filter_nick_list) {
+            self.filtering = true;
+            return true;
+        }
+
+        util::handle_list_input_event(&mut self.list, event, keys)
+    }
+}
 
 pub fn widget<'a>(
-    list: &'a mut ListState<SessionId>,
+    state: &'a mut NickListState,
     joined: &Joined,
+    sort: NickListSort,
     focused: bool,
+    max_nick_width: Option<usize>,
+    nick_colors: &NickColors,
+    widthdb: &mut WidthDb,
 ) -> impl Widget<UiError> + 'a {
+    let sessions = joined
+        .listing
+        .values()
+        .map(HalfSession::from_session_info)
+        .chain(iter::once(HalfSession::from_session_view(&joined.session)))
+        .collect::<Vec<_>>();
+    state.update_joined_order(&sessions);
+
+    let filter = state.filter.text().to_lowercase();
+    let mut sessions = sessions
+        .into_iter()
+        .filter(|sess| sess.name.to_lowercase().contains(&filter))
+        .collect::<Vec<_>>();
+
     let mut list_builder = ListBuilder::new();
-    render_rows(&mut list_builder, joined, focused);
-    list_builder.build(list)
+    match sort {
+        NickListSort::Type => render_rows_by_type(
+            &mut list_builder,
+            sessions,
+            &joined.session,
+            focused,
+            max_nick_width,
+            nick_colors,
+            widthdb,
+        ),
+        NickListSort::Alphabet | NickListSort::Joined => {
+            if sort == NickListSort::Joined {
+                let positions = state
+                    .joined_order
+                    .iter()
+                    .enumerate()
+                    .map(|(i, id)| (id, i))
+                    .collect::<HashMap<_, _>>();
+                sessions.sort_by_key(|s| positions.get(&s.session_id).copied());
+            } else {
+                sessions.sort_unstable();
+            }
+            render_section(
+                &mut list_builder,
+                "Sessions",
+                &sessions,
+                &joined.session,
+                focused,
+                max_nick_width,
+                nick_colors,
+                widthdb,
+            );
+        }
+    }
+    let list = list_builder.build(&mut state.list);
+
+    if filter.is_empty() && !state.filtering {
+        return list.first2();
+    }
+
+    let filter = Join2::horizontal(
+        Text::new(("/", Style::new().grey()))
+            .segment()
+            .with_fixed(true),
+        state
+            .filter
+            .widget()
+            .with_focus(focused && state.filtering)
+            .segment(),
+    );
+
+    Join2::vertical(filter.segment().with_fixed(true), list.segment()).second2()
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -58,21 +197,20 @@ impl HalfSession {
     }
 }
 
-fn render_rows(
+fn render_rows_by_type(
     list_builder: &mut ListBuilder<'_, SessionId, Background<Text>>,
-    joined: &Joined,
+    sessions: Vec<HalfSession>,
+    own_session: &SessionView,
     focused: bool,
+    max_nick_width: Option<usize>,
+    nick_colors: &NickColors,
+    widthdb: &mut WidthDb,
 ) {
     let mut people = vec![];
     let mut bots = vec![];
     let mut lurkers = vec![];
     let mut nurkers = vec![];
 
-    let sessions = joined
-        .listing
-        .values()
-        .map(HalfSession::from_session_info)
-        .chain(iter::once(HalfSession::from_session_view(&joined.session)));
     for sess in sessions {
         match sess.id.session_type() {
             Some(SessionType::Bot) if sess.name.is_empty() => nurkers.push(sess),
@@ -87,18 +225,58 @@ fn render_rows(
     lurkers.sort_unstable();
     nurkers.sort_unstable();
 
-    render_section(list_builder, "People", &people, &joined.session, focused);
-    render_section(list_builder, "Bots", &bots, &joined.session, focused);
-    render_section(list_builder, "Lurkers", &lurkers, &joined.session, focused);
-    render_section(list_builder, "Nurkers", &nurkers, &joined.session, focused);
+    render_section(
+        list_builder,
+        "People",
+        &people,
+        own_session,
+        focused,
+        max_nick_width,
+        nick_colors,
+        widthdb,
+    );
+    render_section(
+        list_builder,
+        "Bots",
+        &bots,
+        own_session,
+        focused,
+        max_nick_width,
+        nick_colors,
+        widthdb,
+    );
+    render_section(
+        list_builder,
+        "Lurkers",
+        &lurkers,
+        own_session,
+        focused,
+        max_nick_width,
+        nick_colors,
+        widthdb,
+    );
+    render_section(
+        list_builder,
+        "Nurkers",
+        &nurkers,
+        own_session,
+        focused,
+        max_nick_width,
+        nick_colors,
+        widthdb,
+    );
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_section(
     list_builder: &mut ListBuilder<'_, SessionId, Background<Text>>,
     name: &str,
     sessions: &[HalfSession],
     own_session: &SessionView,
     focused: bool,
+    max_nick_width: Option<usize>,
+    nick_colors: &NickColors,
+    widthdb: &mut WidthDb,
 ) {
     if sessions.is_empty() {
         return;
@@ -116,7 +294,15 @@ fn render_section(
     list_builder.add_unsel(Text::new(row).background());
 
     for session in sessions {
-        render_row(list_builder, session, own_session, focused);
+        render_row(
+            list_builder,
+            session,
+            own_session,
+            focused,
+            max_nick_width,
+            nick_colors,
+            widthdb,
+        );
     }
 }
 
@@ -125,6 +311,9 @@ fn render_row(
     session: &HalfSession,
     own_session: &SessionView,
     focused: bool,
+    max_nick_width: Option<usize>,
+    nick_colors: &NickColors,
+    widthdb: &mut WidthDb,
 ) {
     let (name, style, style_inv, perms_style_inv) = if session.name.is_empty() {
         let name = "lurk".to_string();
@@ -133,9 +322,8 @@ fn render_row(
         (name, style, style_inv, style_inv)
     } else {
         let name = &session.name as &str;
-        let (r, g, b) = euph::nick_color(name);
-        let name = euph::EMOJI.replace(name).to_string();
-        let color = Color::Rgb { r, g, b };
+        let color = euph::nick_color(name, nick_colors);
+        let name = euph::truncate_nick(widthdb, &euph::EMOJI.replace(name), max_nick_width);
         let style = Style::new().bold().with(color);
         let style_inv = Style::new().bold().black().on(color);
         let perms_style_inv = Style::new().black().on(color);
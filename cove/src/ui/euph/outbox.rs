@@ -0,0 +1,223 @@
+use cove_config::Keys;
+use cove_input::InputEvent;
+use crossterm::style::Stylize;
+use euphoxide::api::MessageId;
+use tokio::sync::oneshot;
+use tokio::sync::oneshot::error::TryRecvError;
+use toss::widgets::{Join2, Text};
+use toss::{Style, Styled, Widget, WidgetExt};
+
+use crate::euph;
+use crate::ui::widgets::{ListBuilder, ListState, Popup};
+use crate::ui::{key_bindings, util, UiError};
+
+use super::popup::PopupResult;
+
+enum Status {
+    /// Waiting for the room to be joined.
+    Queued,
+    /// Sent, but not yet acknowledged by the server.
+    Sending(oneshot::Receiver<MessageId>),
+    /// Sending failed. Only retried when requested explicitly.
+    Failed,
+}
+
+pub struct OutboxMsg {
+    id: usize,
+    pub parent: Option<MessageId>,
+    pub content: String,
+    status: Status,
+}
+
+/// Outgoing messages of a room that have not (yet) been sent successfully.
+///
+/// Queued messages are sent as soon as the room is joined. Once the server
+/// acknowledges a message, it leaves the outbox.
+#[derive(Default)]
+pub struct Outbox {
+    next_id: usize,
+    msgs: Vec<OutboxMsg>,
+}
+
+impl Outbox {
+    pub fn is_empty(&self) -> bool {
+        self.msgs.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.msgs.len()
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.msgs
+            .iter()
+            .filter(|msg| matches!(msg.status, Status::Failed))
+            .count()
+    }
+
+    fn push(&mut self, parent: Option<MessageId>, content: String, status: Status) {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.msgs.push(OutboxMsg {
+            id,
+            parent,
+            content,
+            status,
+        });
+    }
+
+    /// Add a message to be sent once the room is joined.
+    pub fn queue(&mut self, parent: Option<MessageId>, content: String) {
+        self.push(parent, content, Status::Queued);
+    }
+
+    /// Add a message that could not be sent.
+    pub fn failed(&mut self, parent: Option<MessageId>, content: String) {
+        self.push(parent, content, Status::Failed);
+    }
+
+    /// Queue a failed message again.
+    pub fn retry(&mut self, id: usize) {
+        if let Some(msg) = self.msgs.iter_mut().find(|msg| msg.id == id) {
+            if let Status::Failed = msg.status {
+                msg.status = Status::Queued;
+            }
+        }
+    }
+
+    /// Remove a message from the outbox unless it is currently being sent.
+    pub fn take(&mut self, id: usize) -> Option<OutboxMsg> {
+        let idx = self.msgs.iter().position(|msg| msg.id == id)?;
+        if let Status::Sending(_) = self.msgs[idx].status {
+            return None;
+        }
+        Some(self.msgs.remove(idx))
+    }
+
+    /// Send all queued messages. Must only be called while the room is joined.
+    pub fn flush(&mut self, room: &euph::Room) {
+        for msg in &mut self.msgs {
+            if let Status::Queued = msg.status {
+                match room.send(msg.parent, msg.content.clone()) {
+                    Ok(id_rx) => msg.status = Status::Sending(id_rx),
+                    // The connection is gone, try again after reconnecting.
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+
+    /// Remove acknowledged messages and mark unacknowledged ones as failed.
+    pub fn stabilize(&mut self) {
+        self.msgs.retain_mut(|msg| {
+            let Status::Sending(id_rx) = &mut msg.status else {
+                return true;
+            };
+            match id_rx.try_recv() {
+                Ok(_) => false,
+                Err(TryRecvError::Empty) => true,
+                Err(TryRecvError::Closed) => {
+                    msg.status = Status::Failed;
+                    true
+                }
+            }
+        });
+    }
+}
+
+pub struct OutboxState {
+    list: ListState<usize>,
+}
+
+impl OutboxState {
+    pub fn new() -> Self {
+        Self {
+            list: ListState::new(),
+        }
+    }
+
+    fn status_styled(status: &Status) -> Styled {
+        match status {
+            Status::Queued => Styled::new("queued ", Style::new().yellow()),
+            Status::Sending(_) => Styled::new("sending", Style::new().grey()),
+            Status::Failed => Styled::new("failed ", Style::new().red().bold()),
+        }
+    }
+
+    pub fn widget<'a>(&'a mut self, outbox: &Outbox, keys: &Keys) -> impl Widget<UiError> + 'a {
+        let style_selected = Style::new().black().on_white();
+
+        let mut list_builder = ListBuilder::new();
+
+        if outbox.is_empty() {
+            list_builder.add_unsel(Text::new(("Outbox is empty", Style::new().grey().italic())));
+        }
+
+        for msg in &outbox.msgs {
+            let status = Self::status_styled(&msg.status);
+            let mut preview = msg.content.lines().next().unwrap_or_default().to_string();
+            if msg.content.lines().nth(1).is_some() {
+                preview.push_str(" …");
+            }
+            if msg.parent.is_some() {
+                preview = format!("(reply) {preview}");
+            }
+
+            list_builder.add_sel(msg.id, move |selected| {
+                let text = if selected {
+                    status
+                        .then(" ", style_selected)
+                        .then(preview, style_selected)
+                } else {
+                    status.then_plain(" ").then_plain(preview)
+                };
+                Text::new(text)
+            });
+        }
+
+        let hint_style = Style::new().grey().italic();
+        let hint = Styled::new("Retry with ", hint_style)
+            .and_then(key_bindings::format_binding(&keys.room.outbox.retry))
+            .then(", edit with ", hint_style)
+            .and_then(key_bindings::format_binding(&keys.room.outbox.edit))
+            .then(", discard with ", hint_style)
+            .and_then(key_bindings::format_binding(&keys.room.outbox.discard))
+            .then(".", hint_style);
+
+        Popup::new(
+            Join2::vertical(
+                list_builder.build(&mut self.list).segment(),
+                Text::new(hint)
+                    .padding()
+                    .with_top(1)
+                    .segment()
+                    .with_fixed(true),
+            ),
+            "Outbox",
+        )
+    }
+
+    pub fn handle_input_event(&mut self, event: &mut InputEvent<'_>, keys: &Keys) -> PopupResult {
+        if event.matches(&keys.general.abort) {
+            return PopupResult::Close;
+        }
+
+        if util::handle_list_input_event(&mut self.list, event, keys) {
+            return PopupResult::Handled;
+        }
+
+        if let Some(&id) = self.list.selected() {
+            if event.matches(&keys.room.outbox.retry) {
+                return PopupResult::RetryOutboxMsg { id };
+            }
+            if event.matches(&keys.room.outbox.edit) {
+                return PopupResult::EditOutboxMsg { id };
+            }
+            if event.matches(&keys.room.outbox.discard) {
+                return PopupResult::DiscardOutboxMsg { id };
+            }
+        }
+
+        PopupResult::NotHandled
+    }
+}
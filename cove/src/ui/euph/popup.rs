@@ -8,19 +8,60 @@ use crate::ui::widgets::Popup;
 use crate::ui::UiError;
 
 pub enum RoomPopup {
-    Error { description: String, reason: String },
+    Error {
+        description: String,
+        reason: String,
+        /// How often this error occurred in a row.
+        count: usize,
+    },
 }
 
 impl RoomPopup {
-    fn server_error_widget(description: &str, reason: &str) -> impl Widget<UiError> {
+    pub fn error(description: String, reason: String) -> Self {
+        Self::Error {
+            description,
+            reason,
+            count: 1,
+        }
+    }
+
+    /// Count another occurrence of an identical error instead of adding a new
+    /// popup. Returns whether the popups were identical.
+    pub fn coalesce(&mut self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Self::Error {
+                    description,
+                    reason,
+                    count,
+                },
+                Self::Error {
+                    description: other_description,
+                    reason: other_reason,
+                    count: other_count,
+                },
+            ) if description == other_description && reason == other_reason => {
+                *count += other_count;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn server_error_widget(description: &str, reason: &str, count: usize) -> impl Widget<UiError> {
         let border_style = Style::new().red().bold();
+        let title = if count > 1 {
+            format!("Error (×{count})")
+        } else {
+            "Error".to_string()
+        };
         let text = Styled::new_plain(description)
             .then_plain("\n\n")
             .then("Reason:", Style::new().bold())
             .then_plain(" ")
             .then_plain(reason);
 
-        Popup::new(Text::new(text), ("Error", border_style)).with_border_style(border_style)
+        Popup::new(Text::new(text), (title, border_style)).with_border_style(border_style)
     }
 
     pub fn widget(&self) -> impl Widget<UiError> {
@@ -28,7 +69,8 @@ impl RoomPopup {
             Self::Error {
                 description,
                 reason,
-            } => Self::server_error_widget(description, reason),
+                count,
+            } => Self::server_error_widget(description, reason, *count),
         }
     }
 }
@@ -38,4 +80,13 @@ pub enum PopupResult {
     Handled,
     Close,
     ErrorOpeningLink { link: String, error: io::Error },
+    LoadFile { path: String },
+    SetNick { nick: String },
+    Send,
+    CancelSend,
+    MarkOlderSeen,
+    RetryOutboxMsg { id: usize },
+    EditOutboxMsg { id: usize },
+    DiscardOutboxMsg { id: usize },
+    Search { query: String },
 }
@@ -1,28 +1,55 @@
 use std::collections::VecDeque;
-
-use cove_config::{Config, Keys};
+use std::convert::Infallible;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{fs, mem};
+
+use cookie::Cookie;
+use cove_config::{Config, Keys, NotifyLevel, OpenRoomCursor};
 use cove_input::InputEvent;
 use crossterm::style::Stylize;
-use euphoxide::api::{Data, Message, MessageId, PacketType, SessionId};
+use euphoxide::api::{Data, Message, MessageId, PacketType, SendEvent, Snowflake};
 use euphoxide::bot::instance::{Event, ServerConfig};
 use euphoxide::conn::{self, Joined, Joining, SessionInfo};
 use jiff::tz::TimeZone;
+use tokio::select;
 use tokio::sync::oneshot::error::TryRecvError;
 use tokio::sync::{mpsc, oneshot};
 use toss::widgets::{BoxedAsync, EditorState, Join2, Layer, Text};
-use toss::{Style, Styled, Widget, WidgetExt};
+use toss::{Style, Styled, Widget, WidgetExt, WidthDb};
 
-use crate::euph;
+use crate::clipboard;
+use crate::euph::{self, Highlight, NickColors, SmallMessage};
 use crate::macros::logging_unwrap;
-use crate::ui::chat::{ChatState, Reaction};
-use crate::ui::widgets::ListState;
+use crate::ui::chat::{ChatState, Follow, Reaction, RenderOptions};
+use crate::ui::theme::Theme;
 use crate::ui::{util, UiError, UiEvent};
 use crate::vault::EuphRoomVault;
 
 use super::account::AccountUiState;
 use super::links::LinksState;
+use super::nick::NickState;
+use super::nick_list::NickListState;
+use super::outbox::{Outbox, OutboxState};
 use super::popup::{PopupResult, RoomPopup};
-use super::{auth, inspect, nick, nick_list};
+use super::{auth, confirm_seen, confirm_send, inspect, load_file, nick, nick_list, search};
+
+/// Maximum number of matches when searching a whole room.
+const SEARCH_LIMIT: usize = 1000;
+
+/// Minimum time between two runs of the `notify_command` for a room.
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a notice is shown in the room's status.
+const NOTICE_DURATION: Duration = Duration::from_secs(3);
+
+/// Time between redraws while waiting to reconnect.
+const RETRY_COUNTDOWN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Name of the cookie euphoria uses to identify the session (and, if logged
+/// in, the account) of a client.
+const SESSION_COOKIE: &str = "a";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Focus {
@@ -34,11 +61,23 @@ enum Focus {
 enum State {
     Normal,
     Auth(EditorState),
-    Nick(EditorState),
+    Nick(NickState),
     Account(AccountUiState),
     Links(LinksState),
+    LoadFile(EditorState),
+    SearchThread(EditorState),
+    Search(EditorState),
+    ConfirmSend {
+        parent: Option<MessageId>,
+        content: String,
+    },
+    ConfirmMarkSeen {
+        id: MessageId,
+        count: usize,
+    },
     InspectMessage(Message),
     InspectSession(SessionInfo),
+    Outbox(OutboxState),
 }
 
 type EuphChatState = ChatState<euph::SmallMessage, EuphRoomVault>;
@@ -50,15 +89,53 @@ pub struct EuphRoom {
     ui_event_tx: mpsc::UnboundedSender<UiEvent>,
 
     room: Option<euph::Room>,
+    metrics: euph::Metrics,
 
     focus: Focus,
     state: State,
     popups: VecDeque<RoomPopup>,
 
     chat: EuphChatState,
-    last_msg_sent: Option<oneshot::Receiver<MessageId>>,
-
-    nick_list: ListState<SessionId>,
+    /// Message sent from the editor, kept around until the server
+    /// acknowledges it so it can be moved to the outbox if sending fails.
+    last_msg_sent: Option<(Option<MessageId>, String, oneshot::Receiver<MessageId>)>,
+    outbox: Outbox,
+    /// Whether to send the next message without the compose prefix and suffix.
+    skip_affixes: bool,
+    /// Whether the configured password was sent automatically. It is only
+    /// sent once, or once per connection if `auto_auth` is enabled.
+    password_sent: bool,
+    /// Whether the configured password was rejected since the last connect.
+    /// Until then, authentication happens without asking the user.
+    password_rejected: bool,
+    /// Nick that was requested but not yet confirmed by the server.
+    pending_nick: Option<String>,
+    opened: bool,
+    /// Message the user marked as "read up to here", persisted in the vault.
+    read_mark: Option<MessageId>,
+    /// Newest message of the room, shown in the rooms list.
+    preview: Option<SmallMessage>,
+    /// Set whenever messages are added so the preview is loaded again on the
+    /// next redraw.
+    preview_outdated: bool,
+    /// Amount of messages in the room, shown in the rooms list. Cleared
+    /// whenever messages are added so it is counted again on the next redraw.
+    msg_count: Option<usize>,
+    /// Amount of unseen messages mentioning the user, only counted if the room
+    /// notifies on mentions. Cleared whenever messages are added or seen, or
+    /// the nick changes, so it is counted again on the next redraw.
+    unseen_mentions_count: Option<usize>,
+    nick_colors: NickColors,
+    highlights: Vec<Highlight>,
+    last_notification: Option<Instant>,
+    /// Short message shown in the room's status until it expires.
+    notice: Option<(&'static str, Instant)>,
+
+    nick_list: NickListState,
+
+    /// `Some` while waiting to reconnect. Set to `None` to drop the sender and
+    /// stop redrawing the "retry in" countdown every second.
+    retry_countdown_canary: Option<oneshot::Sender<Infallible>>,
 }
 
 impl EuphRoom {
@@ -68,20 +145,60 @@ impl EuphRoom {
         room_config: cove_config::EuphRoom,
         vault: EuphRoomVault,
         tz: TimeZone,
+        theme: &Theme,
         ui_event_tx: mpsc::UnboundedSender<UiEvent>,
     ) -> Self {
+        let mut render_options = RenderOptions::new(tz);
+        render_options.time_format = config.time_format;
+        render_options.timestamp_format = config.timestamp_format.clone();
+        render_options.compact_timestamp_width = config.compact_timestamp_width;
+        render_options.emoji = config.emoji;
+        render_options.max_nick_width = config.chat_max_nick_width;
+        render_options.max_indent = config.max_indent;
+        render_options.max_message_lines = config.max_message_lines;
+        render_options.scrolloff = config.scrolloff();
+        render_options.center_cursor_on_jump = config.center_cursor_on_jump;
+        render_options.ignored_users = config.ignore.iter().cloned().collect();
+        render_options.wrap_marker = config.wrap_marker.clone();
+        let mut nick_colors = euph::parse_nick_colors(&room_config.nick_colors);
+        theme.apply_to_nick_colors(&mut nick_colors);
+        render_options.nick_colors = nick_colors.clone();
+        render_options.theme = theme.clone();
+        let highlights = euph::parse_highlights(&config.highlight);
+        render_options.highlights = highlights.clone();
+        if let Some(server) = config.euph.servers.get(&vault.room().domain) {
+            render_options.plain_me = server.plain_me;
+        }
+
         Self {
             config,
             server_config,
             room_config,
             ui_event_tx,
             room: None,
+            metrics: euph::Metrics::default(),
             focus: Focus::Chat,
             state: State::Normal,
             popups: VecDeque::new(),
-            chat: ChatState::new(vault, tz),
+            chat: ChatState::new(vault, render_options),
             last_msg_sent: None,
-            nick_list: ListState::new(),
+            outbox: Outbox::default(),
+            skip_affixes: false,
+            password_sent: false,
+            password_rejected: false,
+            pending_nick: None,
+            opened: false,
+            read_mark: None,
+            preview: None,
+            preview_outdated: true,
+            msg_count: None,
+            unseen_mentions_count: None,
+            nick_colors,
+            highlights,
+            last_notification: None,
+            notice: None,
+            nick_list: NickListState::new(),
+            retry_countdown_canary: None,
         }
     }
 
@@ -89,6 +206,13 @@ impl EuphRoom {
         self.chat.store()
     }
 
+    pub fn set_theme(&mut self, theme: &Theme) {
+        theme.apply_to_nick_colors(&mut self.nick_colors);
+        let options = self.chat.options_mut();
+        theme.apply_to_nick_colors(&mut options.nick_colors);
+        options.theme = theme.clone();
+    }
+
     fn domain(&self) -> &str {
         &self.vault().room().domain
     }
@@ -97,24 +221,21 @@ impl EuphRoom {
         &self.vault().room().name
     }
 
-    pub fn connect(&mut self, next_instance_id: &mut usize) {
+    pub fn connect(&mut self, instance_ids: &Arc<AtomicUsize>) {
         if self.room.is_none() {
-            let room = self.vault().room();
             let instance_config = self
                 .server_config
                 .clone()
                 .room(self.vault().room().name.clone())
-                .name(format!("{room:?}-{}", next_instance_id))
                 .human(true)
                 .username(self.room_config.username.clone())
-                .force_username(self.room_config.force_username)
-                .password(self.room_config.password.clone());
-            *next_instance_id = next_instance_id.wrapping_add(1);
+                .force_username(self.room_config.force_username.unwrap_or_default());
 
             let tx = self.ui_event_tx.clone();
             self.room = Some(euph::Room::new(
                 self.vault().clone(),
                 instance_config,
+                instance_ids.clone(),
                 move |e| {
                     let _ = tx.send(UiEvent::Euph(e));
                 },
@@ -122,8 +243,64 @@ impl EuphRoom {
         }
     }
 
+    /// Show an error popup.
+    ///
+    /// Identical consecutive errors are combined into a single popup. If there
+    /// are too many popups, the oldest ones are dropped.
+    fn push_error(&mut self, description: String, reason: String) {
+        let popup = RoomPopup::error(description, reason);
+        if let Some(newest) = self.popups.front_mut() {
+            if newest.coalesce(&popup) {
+                return;
+            }
+        }
+
+        self.popups.push_front(popup);
+        self.popups.truncate(self.config.max_popups());
+    }
+
+    /// Whether the server bounced us because the room requires an account
+    /// that the current session isn't logged in to. Password-protected rooms
+    /// bounce as well, so those are left to the usual authentication.
+    fn requires_account(&self) -> bool {
+        if self.room_config.password.is_some() {
+            return false;
+        }
+        let Some(euph::State::Connected(_, conn::State::Joining(joining), _)) = self.room_state()
+        else {
+            return false;
+        };
+        let Some(hello) = &joining.hello else {
+            return false;
+        };
+        hello.room_is_private && hello.account.is_none()
+    }
+
+    /// Remove the session cookie the server no longer accepts so the next
+    /// connection starts a fresh session, then ask the user to log in again.
+    async fn session_rejected(&mut self) {
+        let cookies = {
+            let mut cookies = self.server_config.cookies.lock().unwrap();
+            cookies.remove(Cookie::from(SESSION_COOKIE));
+            cookies.clone()
+        };
+        let domain = self.domain().to_string();
+        logging_unwrap!(self.vault().vault().set_cookies(domain, cookies).await);
+
+        self.push_error(
+            format!("{} requires an account.", self.name()),
+            "the stored session isn't logged in and was removed, please log in again".to_string(),
+        );
+    }
+
     pub fn disconnect(&mut self) {
         self.room = None;
+        self.retry_countdown_canary = None;
+        self.metrics.disconnected();
+    }
+
+    pub fn metrics(&self) -> &euph::Metrics {
+        &self.metrics
     }
 
     pub fn room_state(&self) -> Option<&euph::State> {
@@ -146,16 +323,131 @@ impl EuphRoom {
         if let Some(room) = &self.room {
             if room.stopped() {
                 self.room = None;
+                self.retry_countdown_canary = None;
             }
         }
     }
 
+    /// Called whenever the room is opened from the rooms list. Places the
+    /// cursor according to the `open_room_cursor` option the first time.
+    pub async fn opened(&mut self) {
+        self.update_unseen_divider().await;
+
+        if self.opened {
+            return;
+        }
+        self.opened = true;
+
+        self.read_mark = logging_unwrap!(self.vault().read_mark().await);
+        self.chat.set_read_mark(self.read_mark);
+        logging_unwrap!(self.chat.load_folded().await);
+
+        let id = match self.config.open_room_cursor {
+            OpenRoomCursor::Newest => None,
+            OpenRoomCursor::OldestUnseen => {
+                logging_unwrap!(self.vault().oldest_unseen_msg_id().await)
+            }
+            OpenRoomCursor::LastRead => logging_unwrap!(self.vault().newest_seen_msg_id().await),
+        };
+        self.chat.move_cursor_to(id);
+    }
+
+    /// Place the unseen messages divider above the oldest unseen message if
+    /// there are older seen messages. Anchoring it to an unseen message keeps
+    /// it meaningful in both flat and tree mode. It stays there until the room
+    /// is opened again or all messages have been seen.
+    async fn update_unseen_divider(&mut self) {
+        let oldest = logging_unwrap!(self.vault().oldest_msg_id().await);
+        let oldest_unseen = logging_unwrap!(self.vault().oldest_unseen_msg_id().await);
+        let divider = oldest_unseen.filter(|id| Some(*id) != oldest);
+        self.chat.set_unseen_divider(divider);
+    }
+
+    pub async fn move_cursor_to_oldest_unseen(&mut self) {
+        let id = logging_unwrap!(self.vault().oldest_unseen_msg_id().await);
+        if let Some(id) = id {
+            logging_unwrap!(self.chat.unfold_path(&id).await);
+        }
+        self.chat.move_cursor_to(id);
+    }
+
+    pub fn pinned(&self) -> bool {
+        self.room_config.pinned
+    }
+
+    /// Amount of unseen messages that should draw the user's attention
+    /// according to the room's notify level.
     pub async fn unseen_msgs_count(&self) -> usize {
+        if self.room_config.notify == NotifyLevel::Mentions {
+            return self.unseen_mentions_count.unwrap_or_default();
+        }
         logging_unwrap!(self.vault().unseen_msgs_count().await)
     }
 
+    pub fn notify(&self) -> NotifyLevel {
+        self.room_config.notify
+    }
+
+    /// Amount of unseen messages mentioning the user as of the last
+    /// [`Self::update_unseen_mentions_count`].
+    pub fn unseen_mentions_count(&self) -> usize {
+        self.unseen_mentions_count.unwrap_or_default()
+    }
+
+    /// Count the unseen mentions if the room notifies on mentions and they may
+    /// have changed since they were last counted.
+    pub async fn update_unseen_mentions_count(&mut self) {
+        if self.room_config.notify != NotifyLevel::Mentions || self.unseen_mentions_count.is_some()
+        {
+            return;
+        }
+
+        let nick = self
+            .room_state_joined()
+            .map(|joined| joined.session.name.clone())
+            .or_else(|| self.room_config.username.clone());
+        let highlights = self.highlights.clone();
+        let count = logging_unwrap!(self.vault().unseen_mentions_count(nick, highlights).await);
+        self.unseen_mentions_count = Some(count);
+    }
+
+    pub fn msg_count(&self) -> Option<usize> {
+        self.msg_count
+    }
+
+    /// Count the messages for [`Self::msg_count`] if messages were added since
+    /// they were last counted.
+    pub async fn update_msg_count(&mut self) {
+        if self.msg_count.is_none() {
+            self.msg_count = Some(logging_unwrap!(self.vault().msg_count().await));
+        }
+    }
+
+    pub fn preview(&self) -> Option<&SmallMessage> {
+        self.preview.as_ref()
+    }
+
+    /// Load the newest message for [`Self::preview`] if messages were added
+    /// since it was last loaded.
+    pub async fn update_preview(&mut self) {
+        if !self.preview_outdated {
+            return;
+        }
+        self.preview_outdated = false;
+
+        let newest = logging_unwrap!(self.vault().newest_msg_id().await);
+        if self.preview.as_ref().map(|msg| msg.id) == newest {
+            return;
+        }
+
+        self.preview = match newest {
+            Some(id) => logging_unwrap!(self.vault().msg(id).await),
+            None => None,
+        };
+    }
+
     async fn stabilize_pseudo_msg(&mut self) {
-        if let Some(id_rx) = &mut self.last_msg_sent {
+        if let Some((_, _, id_rx)) = &mut self.last_msg_sent {
             match id_rx.try_recv() {
                 Ok(id) => {
                     self.chat.send_successful(id);
@@ -163,13 +455,24 @@ impl EuphRoom {
                 }
                 Err(TryRecvError::Empty) => {} // Wait a bit longer
                 Err(TryRecvError::Closed) => {
-                    self.chat.send_failed();
-                    self.last_msg_sent = None;
+                    if let Some((parent, content, _)) = self.last_msg_sent.take() {
+                        self.outbox.failed(parent, content);
+                    }
+                    self.chat.send_queued();
                 }
             }
         }
     }
 
+    fn stabilize_outbox(&mut self) {
+        self.outbox.stabilize();
+        if let Some(room) = &self.room {
+            if room.state().joined().is_some() {
+                self.outbox.flush(room);
+            }
+        }
+    }
+
     fn stabilize_focus(&mut self) {
         if self.room_state_joined().is_none() {
             self.focus = Focus::Chat; // There is no nick list to focus on
@@ -186,11 +489,12 @@ impl EuphRoom {
                     conn::State::Joining(Joining {
                         bounce: Some(_), ..
                     }),
+                    _,
                 )),
             ) => {} // Nothing to see here
             (State::Auth(_), _) => self.state = State::Normal,
 
-            (State::Nick(_), Some(euph::State::Connected(_, conn::State::Joined(_)))) => {}
+            (State::Nick(_), Some(euph::State::Connected(_, conn::State::Joined(_), _))) => {}
             (State::Nick(_), _) => self.state = State::Normal,
 
             (State::Account(account), state) => {
@@ -205,12 +509,22 @@ impl EuphRoom {
 
     async fn stabilize(&mut self) {
         self.stabilize_pseudo_msg().await;
+        self.stabilize_outbox();
         self.stabilize_focus();
         self.stabilize_state();
     }
 
-    pub async fn widget(&mut self) -> BoxedAsync<'_, UiError> {
+    /// The `keys` are the room's key bindings, with any room-specific overrides
+    /// applied. They are used for the key hints shown in popups.
+    pub async fn widget(&mut self, widthdb: &mut WidthDb, keys: &Keys) -> BoxedAsync<'_, UiError> {
         self.stabilize().await;
+        self.update_unseen_mentions_count().await;
+
+        if self.chat.unseen_divider().is_some()
+            && logging_unwrap!(self.vault().unseen_msgs_count().await) == 0
+        {
+            self.chat.set_unseen_divider(None);
+        }
 
         let room_state = self.room.as_ref().map(|room| room.state());
         let status_widget = self.status_widget(room_state).await;
@@ -221,6 +535,9 @@ impl EuphRoom {
                 &mut self.nick_list,
                 joined,
                 self.focus,
+                self.config,
+                &self.nick_colors,
+                widthdb,
             ),
             None => Self::widget_without_nick_list(&mut self.chat, status_widget),
         };
@@ -230,15 +547,35 @@ impl EuphRoom {
         match &mut self.state {
             State::Normal => {}
             State::Auth(editor) => layers.push(auth::widget(editor).desync().boxed_async()),
-            State::Nick(editor) => layers.push(nick::widget(editor).desync().boxed_async()),
+            State::Nick(nick) => layers.push(nick.widget().desync().boxed_async()),
             State::Account(account) => layers.push(account.widget().desync().boxed_async()),
-            State::Links(links) => layers.push(links.widget().desync().boxed_async()),
+            State::Links(links) => layers.push(links.widget(keys).desync().boxed_async()),
+            State::LoadFile(editor) => {
+                layers.push(load_file::widget(editor).desync().boxed_async())
+            }
+            State::SearchThread(editor) => layers.push(
+                search::widget(editor, "Search thread")
+                    .desync()
+                    .boxed_async(),
+            ),
+            State::Search(editor) => {
+                layers.push(search::widget(editor, "Search room").desync().boxed_async())
+            }
+            State::ConfirmSend { content, .. } => {
+                layers.push(confirm_send::widget(content, keys).desync().boxed_async())
+            }
+            State::ConfirmMarkSeen { count, .. } => {
+                layers.push(confirm_seen::widget(*count, keys).desync().boxed_async())
+            }
             State::InspectMessage(message) => {
                 layers.push(inspect::message_widget(message).desync().boxed_async())
             }
             State::InspectSession(session) => {
                 layers.push(inspect::session_widget(session).desync().boxed_async())
             }
+            State::Outbox(outbox) => {
+                layers.push(outbox.widget(&self.outbox, keys).desync().boxed_async())
+            }
         }
 
         for popup in &self.popups {
@@ -261,18 +598,30 @@ impl EuphRoom {
         .boxed_async()
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn widget_with_nick_list<'a>(
         chat: &'a mut EuphChatState,
         status_widget: impl Widget<UiError> + Send + Sync + 'static,
-        nick_list: &'a mut ListState<SessionId>,
+        nick_list: &'a mut NickListState,
         joined: &Joined,
         focus: Focus,
+        config: &Config,
+        nick_colors: &NickColors,
+        widthdb: &mut WidthDb,
     ) -> BoxedAsync<'a, UiError> {
-        let nick_list_widget = nick_list::widget(nick_list, joined, focus == Focus::NickList)
-            .padding()
-            .with_right(1)
-            .border()
-            .desync();
+        let nick_list_widget = nick_list::widget(
+            nick_list,
+            joined,
+            config.nick_list_sort,
+            focus == Focus::NickList,
+            config.nick_list_max_nick_width,
+            nick_colors,
+            widthdb,
+        )
+        .padding()
+        .with_right(1)
+        .border()
+        .desync();
 
         let chat_widget = chat.widget(joined.session.name.clone(), focus == Focus::Chat);
 
@@ -294,25 +643,90 @@ impl EuphRoom {
 
         info = match state {
             None | Some(euph::State::Stopped) => info.then_plain(", archive"),
-            Some(euph::State::Disconnected) => info.then_plain(", waiting..."),
+            Some(state @ euph::State::Disconnected(_)) => match state.retry_in() {
+                Some(retry_in) => info.then_plain(format!(
+                    ", waiting (retry in {})...",
+                    util::format_duration(retry_in)
+                )),
+                None => info.then_plain(", waiting..."),
+            },
             Some(euph::State::Connecting) => info.then_plain(", connecting..."),
-            Some(euph::State::Connected(_, conn::State::Joining(j))) if j.bounce.is_some() => {
-                info.then_plain(", auth required")
+            Some(euph::State::Connected(_, conn::State::Joining(j), _)) if j.bounce.is_some() => {
+                if self.password_sent && !self.password_rejected {
+                    info.then_plain(", authenticating...")
+                } else {
+                    info.then_plain(", auth required")
+                }
             }
-            Some(euph::State::Connected(_, conn::State::Joining(_))) => {
+            Some(euph::State::Connected(_, conn::State::Joining(_), _)) => {
                 info.then_plain(", joining...")
             }
-            Some(euph::State::Connected(_, conn::State::Joined(j))) => {
+            Some(euph::State::Connected(_, conn::State::Joined(j), _)) => {
                 let nick = &j.session.name;
                 if nick.is_empty() {
                     info.then_plain(", present without nick")
                 } else {
-                    info.then_plain(", present as ")
-                        .and_then(euph::style_nick(nick, Style::new()))
+                    info.then_plain(", present as ").and_then(euph::style_nick(
+                        nick,
+                        Style::new(),
+                        &self.nick_colors,
+                    ))
                 }
             }
         };
 
+        if state.and_then(|s| s.conn_tx()).is_some() {
+            match self.room.as_ref().and_then(|r| r.backfill()) {
+                None => {}
+                Some(euph::Backfill::Gap(progress)) => {
+                    info = info.then_plain(format!(", backfill {:.0}%", progress * 100.0));
+                }
+                Some(euph::Backfill::History) => info = info.then_plain(", backfilling"),
+            }
+        }
+
+        if self.config.show_latency {
+            if let Some(latency) = state.and_then(|s| s.latency()) {
+                info = info.then_plain(format!(", {}ms", latency.as_millis()));
+            }
+        }
+
+        if let Some((query, current, total)) = self.chat.search_status() {
+            info = info.then_plain(format!(", match {current}/{total} for {query:?}"));
+        }
+
+        if let Some((notice, since)) = self.notice {
+            if since.elapsed() < NOTICE_DURATION {
+                info = info.then(format!(", {notice}"), Style::new().yellow());
+            }
+        }
+
+        match self.chat.follow() {
+            Follow::Off => {}
+            Follow::On => info = info.then(", following", Style::new().green()),
+            Follow::Interrupted => {
+                info = info.then(", stopped following", Style::new().yellow());
+            }
+        }
+
+        if self.skip_affixes {
+            info = info.then(", without affixes", Style::new().yellow());
+        }
+
+        if !self.outbox.is_empty() {
+            info = info.then_plain(", ").then(
+                format!("{} unsent", self.outbox.len()),
+                Style::new().yellow(),
+            );
+            let failed = self.outbox.failed_count();
+            if failed > 0 {
+                info = info
+                    .then_plain(" (")
+                    .then(format!("{failed} failed"), Style::new().red().bold())
+                    .then_plain(")");
+            }
+        }
+
         let unseen = self.unseen_msgs_count().await;
         if unseen > 0 {
             info = info
@@ -334,20 +748,77 @@ impl EuphRoom {
             .title(title)
     }
 
+    /// A notice explaining why an unseen message jump won't find a message
+    /// to jump to, if that's the case.
+    async fn unseen_jump_notice(
+        &self,
+        event: &mut InputEvent<'_>,
+        keys: &Keys,
+    ) -> Option<&'static str> {
+        if self.chat.follow() == Follow::On {
+            return None;
+        }
+
+        let vault = self.vault();
+        if event.matches(&keys.tree.cursor.to_older_unseen_message) {
+            let older = match self.chat.cursor() {
+                Some(id) => logging_unwrap!(vault.older_unseen_msg_id(*id).await),
+                None if self.chat.at_bottom() => {
+                    logging_unwrap!(vault.newest_unseen_msg_id().await)
+                }
+                None => return None,
+            };
+            older.is_none().then_some("no older unseen messages")
+        } else if event.matches(&keys.tree.cursor.to_newer_unseen_message) {
+            let newer = match self.chat.cursor() {
+                Some(id) => logging_unwrap!(vault.newer_unseen_msg_id(*id).await),
+                None if self.chat.at_bottom() => None,
+                None => return None,
+            };
+            newer.is_none().then_some("no newer unseen messages")
+        } else {
+            None
+        }
+    }
+
     async fn handle_chat_input_event(&mut self, event: &mut InputEvent<'_>, keys: &Keys) -> bool {
-        let can_compose = self.room_state_joined().is_some();
+        if let Some(notice) = self.unseen_jump_notice(event, keys).await {
+            self.notice = Some((notice, Instant::now()));
+        }
+
+        if self.bulk_seen_needs_confirmation(event, keys).await {
+            return true;
+        }
+
+        // Messages composed while not joined end up in the outbox.
+        let can_compose = self.room.is_some();
 
         let reaction = self.chat.handle_input_event(event, keys, can_compose).await;
         let reaction = logging_unwrap!(reaction);
 
         match reaction {
             Reaction::NotHandled => {}
-            Reaction::Handled => return true,
+            Reaction::Handled => {
+                // Messages may have been marked as seen.
+                self.unseen_mentions_count = None;
+                return true;
+            }
+            Reaction::LoadFile => {
+                self.state = State::LoadFile(load_file::new());
+                return true;
+            }
             Reaction::Composed { parent, content } => {
-                if let Some(room) = &self.room {
-                    match room.send(parent, content) {
-                        Ok(id_rx) => self.last_msg_sent = Some(id_rx),
-                        Err(_) => self.chat.send_failed(),
+                if self.room.is_some() {
+                    let content = self.add_affixes(content);
+                    let needs_confirmation = confirm_send::needs_confirmation(
+                        &content,
+                        self.config.send_confirm_lines,
+                        self.config.send_confirm_chars,
+                    );
+                    if needs_confirmation {
+                        self.state = State::ConfirmSend { parent, content };
+                    } else {
+                        self.send(parent, content);
                     }
                     return true;
                 }
@@ -357,7 +828,115 @@ impl EuphRoom {
         false
     }
 
+    /// Ask for confirmation instead of marking messages as seen if too many
+    /// messages would be affected.
+    async fn bulk_seen_needs_confirmation(
+        &mut self,
+        event: &mut InputEvent<'_>,
+        keys: &Keys,
+    ) -> bool {
+        if !event.matches(&keys.tree.action.mark_older_seen) {
+            return false;
+        }
+        let Some(threshold) = self.config.confirm_bulk_seen_threshold else {
+            return false;
+        };
+
+        let id = match self.chat.cursor() {
+            Some(&id) => id,
+            None if self.chat.at_bottom() => MessageId(Snowflake::MAX),
+            None => return false,
+        };
+
+        let count = logging_unwrap!(self.vault().older_unseen_msgs_count(id).await);
+        if count <= threshold {
+            return false;
+        }
+
+        self.state = State::ConfirmMarkSeen { id, count };
+        true
+    }
+
+    fn add_affixes(&mut self, content: String) -> String {
+        if mem::take(&mut self.skip_affixes) {
+            return content;
+        }
+        let (prefix, suffix) = self.config.compose_affixes(self.domain());
+        format!("{prefix}{content}{suffix}")
+    }
+
+    /// Undo [`Self::add_affixes`] so the affixes aren't added twice when the
+    /// content is sent again.
+    fn strip_affixes(&self, content: String) -> String {
+        let (prefix, suffix) = self.config.compose_affixes(self.domain());
+        match content
+            .strip_prefix(prefix)
+            .and_then(|c| c.strip_suffix(suffix))
+        {
+            Some(stripped) => stripped.to_string(),
+            None => content,
+        }
+    }
+
+    fn send(&mut self, parent: Option<MessageId>, content: String) {
+        let Some(room) = &self.room else {
+            self.chat.send_failed();
+            return;
+        };
+        if room.state().joined().is_none() {
+            self.outbox.queue(parent, content);
+            self.chat.send_queued();
+            return;
+        }
+        match room.send(parent, content.clone()) {
+            Ok(id_rx) => self.last_msg_sent = Some((parent, content, id_rx)),
+            Err(_) => {
+                self.outbox.failed(parent, content);
+                self.chat.send_queued();
+            }
+        }
+    }
+
     async fn handle_room_input_event(&mut self, event: &mut InputEvent<'_>, keys: &Keys) -> bool {
+        if event.matches(&keys.room.action.outbox) {
+            self.state = State::Outbox(OutboxState::new());
+            return true;
+        }
+        if event.matches(&keys.room.action.skip_affixes) {
+            self.skip_affixes = !self.skip_affixes;
+            return true;
+        }
+        if event.matches(&keys.room.action.set_read_mark) {
+            if let Some(&id) = self.chat.cursor() {
+                self.read_mark = if self.read_mark == Some(id) {
+                    None
+                } else {
+                    Some(id)
+                };
+                logging_unwrap!(self.vault().set_read_mark(self.read_mark).await);
+                self.chat.set_read_mark(self.read_mark);
+            }
+            return true;
+        }
+        if event.matches(&keys.room.action.mark_read) {
+            logging_unwrap!(self.vault().mark_all_seen().await);
+            self.unseen_mentions_count = None;
+            return true;
+        }
+        if event.matches(&keys.room.action.reconnect) {
+            let room = self.vault().room().clone();
+            let _ = self.ui_event_tx.send(UiEvent::Reconnect(room));
+            self.notice = Some(("reconnecting", Instant::now()));
+            return true;
+        }
+        if event.matches(&keys.room.action.to_read_mark) {
+            if let Some(id) = self.read_mark {
+                logging_unwrap!(self.chat.unfold_path(&id).await);
+                self.chat.move_cursor_to(Some(id));
+            }
+            return true;
+        }
+
         match self.room_state() {
             // Authenticating
             Some(euph::State::Connected(
@@ -365,6 +944,7 @@ impl EuphRoom {
                 conn::State::Joining(Joining {
                     bounce: Some(_), ..
                 }),
+                _,
             )) => {
                 if event.matches(&keys.room.action.authenticate) {
                     self.state = State::Auth(auth::new());
@@ -373,9 +953,9 @@ impl EuphRoom {
             }
 
             // Joined
-            Some(euph::State::Connected(_, conn::State::Joined(joined))) => {
+            Some(euph::State::Connected(_, conn::State::Joined(joined), _)) => {
                 if event.matches(&keys.room.action.nick) {
-                    self.state = State::Nick(nick::new(joined.clone()));
+                    self.state = State::Nick(NickState::new(joined.clone()));
                     return true;
                 }
                 if event.matches(&keys.room.action.more_messages) {
@@ -421,10 +1001,67 @@ impl EuphRoom {
             return true;
         }
 
+        if event.matches(&keys.tree.action.search_thread) {
+            if self.chat.cursor().is_some() {
+                self.state = State::SearchThread(search::new());
+            }
+            return true;
+        }
+
+        if event.matches(&keys.room.action.search) {
+            self.state = State::Search(search::new());
+            return true;
+        }
+
+        if event.matches(&keys.tree.action.toggle_truncated) {
+            if let Some(&id) = self.chat.cursor() {
+                self.chat.toggle_truncated(&id);
+            }
+            return true;
+        }
+
+        if event.matches(&keys.tree.action.toggle_expanded) {
+            if let Some(&id) = self.chat.cursor() {
+                self.chat.toggle_expanded(&id);
+            }
+            return true;
+        }
+
         if event.matches(&keys.tree.action.links) {
             if let Some(id) = self.chat.cursor() {
                 if let Some(msg) = logging_unwrap!(self.vault().msg(*id).await) {
-                    self.state = State::Links(LinksState::new(self.config, &msg.content));
+                    self.state = State::Links(LinksState::new(
+                        self.config,
+                        &msg.content,
+                        &self.ui_event_tx,
+                    ));
+                }
+            }
+            return true;
+        }
+
+        if event.matches(&keys.tree.action.copy) {
+            if let Some(id) = self.chat.cursor() {
+                if let Some(msg) = logging_unwrap!(self.vault().msg(*id).await) {
+                    self.copy_to_clipboard(msg.content);
+                }
+            }
+            return true;
+        }
+
+        if event.matches(&keys.tree.action.copy_link) {
+            if let Some(&id) = self.chat.cursor() {
+                let link = self.permalink(id);
+                self.copy_to_clipboard(link);
+            }
+            return true;
+        }
+
+        if event.matches(&keys.tree.action.open_permalink) {
+            if let Some(&id) = self.chat.cursor() {
+                let link = self.permalink(id);
+                if let Err(error) = open::that(&link) {
+                    self.push_error(format!("Failed to open link: {link}"), format!("{error}"));
                 }
             }
             return true;
@@ -433,12 +1070,28 @@ impl EuphRoom {
         false
     }
 
+    fn permalink(&self, id: MessageId) -> String {
+        let room = self.vault().room();
+        self.config
+            .euph
+            .permalink(&room.domain, &room.name, &id.0.to_string())
+    }
+
+    fn copy_to_clipboard(&mut self, text: String) {
+        if let Err(error) = clipboard::copy(text) {
+            self.push_error(
+                "Failed to copy to clipboard".to_string(),
+                format!("{error}"),
+            );
+        }
+    }
+
     fn handle_nick_list_focus_input_event(
         &mut self,
         event: &mut InputEvent<'_>,
         keys: &Keys,
     ) -> bool {
-        if util::handle_list_input_event(&mut self.nick_list, event, keys) {
+        if self.nick_list.handle_input_event(event, keys) {
             return true;
         }
 
@@ -472,12 +1125,14 @@ impl EuphRoom {
                 }
             }
             Focus::NickList => {
-                if event.matches(&keys.general.abort) || event.matches(&keys.general.focus) {
-                    self.focus = Focus::Chat;
+                // The nick list goes first so that aborting clears its filter
+                // before leaving the nick list.
+                if self.handle_nick_list_focus_input_event(event, keys) {
                     return true;
                 }
 
-                if self.handle_nick_list_focus_input_event(event, keys) {
+                if event.matches(&keys.general.abort) || event.matches(&keys.general.focus) {
+                    self.focus = Focus::Chat;
                     return true;
                 }
             }
@@ -499,12 +1154,19 @@ impl EuphRoom {
         let result = match &mut self.state {
             State::Normal => return self.handle_normal_input_event(event, keys).await,
             State::Auth(editor) => auth::handle_input_event(event, keys, &self.room, editor),
-            State::Nick(editor) => nick::handle_input_event(event, keys, &self.room, editor),
+            State::Nick(nick) => nick.handle_input_event(event, keys),
             State::Account(account) => account.handle_input_event(event, keys, &self.room),
             State::Links(links) => links.handle_input_event(event, keys),
+            State::LoadFile(editor) => load_file::handle_input_event(event, keys, editor),
+            State::SearchThread(editor) | State::Search(editor) => {
+                search::handle_input_event(event, keys, editor)
+            }
+            State::ConfirmSend { .. } => confirm_send::handle_input_event(event, keys),
+            State::ConfirmMarkSeen { .. } => confirm_seen::handle_input_event(event, keys),
             State::InspectMessage(_) | State::InspectSession(_) => {
                 inspect::handle_input_event(event, keys)
             }
+            State::Outbox(outbox) => outbox.handle_input_event(event, keys),
         };
 
         match result {
@@ -515,29 +1177,146 @@ impl EuphRoom {
                 true
             }
             PopupResult::ErrorOpeningLink { link, error } => {
-                self.popups.push_front(RoomPopup::Error {
-                    description: format!("Failed to open link: {link}"),
-                    reason: format!("{error}"),
-                });
+                self.push_error(format!("Failed to open link: {link}"), format!("{error}"));
+                true
+            }
+            PopupResult::LoadFile { path } => {
+                self.state = State::Normal;
+                match fs::read_to_string(&path) {
+                    Ok(text) => {
+                        let text = text.strip_suffix('\n').unwrap_or(&text).to_string();
+                        self.chat.set_editor_text(event.widthdb(), text);
+                    }
+                    Err(error) => {
+                        self.push_error(format!("Failed to load file: {path}"), format!("{error}"))
+                    }
+                }
+                true
+            }
+            PopupResult::SetNick { nick } => {
+                self.state = State::Normal;
+                if let Some(room) = &self.room {
+                    if room.nick(nick.clone()).is_ok() {
+                        self.pending_nick = Some(nick);
+                    }
+                }
+                true
+            }
+            PopupResult::Send => {
+                if let State::ConfirmSend { parent, content } =
+                    mem::replace(&mut self.state, State::Normal)
+                {
+                    self.send(parent, content);
+                }
+                true
+            }
+            PopupResult::MarkOlderSeen => {
+                if let State::ConfirmMarkSeen { id, .. } =
+                    mem::replace(&mut self.state, State::Normal)
+                {
+                    logging_unwrap!(self.vault().set_older_seen(id, true).await);
+                    self.unseen_mentions_count = None;
+                }
+                true
+            }
+            PopupResult::CancelSend => {
+                self.state = State::Normal;
+                self.chat.send_failed();
+                true
+            }
+            PopupResult::Search { query } => {
+                let whole_room = matches!(self.state, State::Search(_));
+                self.state = State::Normal;
+                if whole_room {
+                    let matches = logging_unwrap!(
+                        self.vault().search_msgs(query.clone(), SEARCH_LIMIT).await
+                    );
+                    logging_unwrap!(self.chat.show_search_results(query, matches).await);
+                } else {
+                    logging_unwrap!(self.chat.search_thread(event.widthdb(), query).await);
+                }
+                true
+            }
+            PopupResult::RetryOutboxMsg { id } => {
+                self.outbox.retry(id);
+                self.stabilize_outbox();
+                true
+            }
+            PopupResult::EditOutboxMsg { id } => {
+                if let Some(msg) = self.outbox.take(id) {
+                    self.state = State::Normal;
+                    // Place the cursor so that replying continues the thread
+                    // the message was originally meant for.
+                    self.chat.move_cursor_to(msg.parent);
+                    let content = self.strip_affixes(msg.content);
+                    self.chat.set_editor_text(event.widthdb(), content);
+                }
+                true
+            }
+            PopupResult::DiscardOutboxMsg { id } => {
+                self.outbox.take(id);
                 true
             }
         }
     }
 
-    pub async fn handle_event(&mut self, event: Event) -> bool {
+    /// Handle an event of the room's connection. `focused` is whether the room
+    /// is currently open.
+    pub async fn handle_event(&mut self, event: Event, focused: bool) -> bool {
         let Some(room) = &self.room else { return false };
 
-        if event.config().name != room.instance().config().name {
+        if event.config().name != room.instance_config().name {
             // If we allowed names other than the current one, old instances
             // that haven't yet shut down properly could mess up our state.
             return false;
         }
 
+        self.metrics.handle_event(&event);
+        if let Event::Connected(_, _) = &event {
+            self.password_rejected = false;
+            if self.auto_auth() {
+                self.password_sent = false;
+            }
+        }
+
+        if self.config.unfold_on_new_reply {
+            if let Event::Packet(_, packet, _) = &event {
+                if let Ok(Data::SendEvent(SendEvent(msg))) = &packet.content {
+                    if let Some(parent) = &msg.parent {
+                        logging_unwrap!(self.chat.unfold_path(parent).await);
+                    }
+                }
+            }
+        }
+
+        let adds_msgs = match &event {
+            Event::Packet(_, packet, _) => matches!(
+                packet.content,
+                Ok(Data::SendEvent(_)
+                    | Data::SendReply(_)
+                    | Data::SnapshotEvent(_)
+                    | Data::LogReply(_))
+            ),
+            _ => false,
+        };
+        // Must be checked before the room handles the event since it still
+        // knows the hello of the connection that's being bounced.
+        let session_rejected = match &event {
+            Event::Packet(_, packet, _) => {
+                matches!(packet.content, Ok(Data::BounceEvent(_))) && self.requires_account()
+            }
+            _ => false,
+        };
+        let changes_nick = match &event {
+            Event::Packet(_, packet, _) => matches!(packet.content, Ok(Data::NickReply(_))),
+            _ => false,
+        };
+
         // We handle the packet internally first because the room event handling
         // will consume it while we only need a reference.
         let handled = if let Event::Packet(_, packet, _) = &event {
             match &packet.content {
-                Ok(data) => self.handle_euph_data(data),
+                Ok(data) => self.handle_euph_data(data, focused),
                 Err(reason) => self.handle_euph_error(packet.r#type, reason),
             }
         } else {
@@ -552,10 +1331,60 @@ impl EuphRoom {
             .handle_event(event)
             .await;
 
+        // The room stored the messages in the vault while handling the event.
+        if adds_msgs {
+            self.msg_count = None;
+            self.preview_outdated = true;
+        }
+        if adds_msgs || changes_nick {
+            self.unseen_mentions_count = None;
+        }
+
+        if session_rejected {
+            self.session_rejected().await;
+        }
+
+        self.update_retry_countdown();
+
+        // Send queued messages as soon as the room is (re-)joined.
+        if !self.outbox.is_empty() {
+            self.stabilize_outbox();
+        }
+
         handled
     }
 
-    fn handle_euph_data(&mut self, data: &Data) -> bool {
+    /// Regularly redraw while waiting to reconnect so the "retry in"
+    /// countdown doesn't lag behind.
+    fn update_retry_countdown(&mut self) {
+        let waiting = matches!(self.room_state(), Some(euph::State::Disconnected(Some(_))));
+        if !waiting {
+            self.retry_countdown_canary = None;
+            return;
+        }
+        if self.retry_countdown_canary.is_some() {
+            return;
+        }
+
+        let (tx, mut rx) = oneshot::channel();
+        self.retry_countdown_canary = Some(tx);
+        let ui_event_tx = self.ui_event_tx.clone();
+        tokio::task::spawn(async move {
+            let mut interval = tokio::time::interval(RETRY_COUNTDOWN_INTERVAL);
+            loop {
+                select! {
+                    _ = &mut rx => break,
+                    _ = interval.tick() => {
+                        if ui_event_tx.send(UiEvent::Redraw).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    fn handle_euph_data(&mut self, data: &Data, focused: bool) -> bool {
         // These packets don't result in any noticeable change in the UI.
         #[allow(clippy::match_like_matches_macro)]
         let handled = match data {
@@ -571,6 +1400,28 @@ impl EuphRoom {
             _ => true,
         };
 
+        if let Data::NickReply(_) = data {
+            self.pending_nick = None;
+        }
+        if let Data::BounceEvent(_) = data {
+            self.send_password();
+        }
+        if let Data::AuthReply(reply) = data {
+            if !reply.success && self.password_sent && !self.password_rejected {
+                // The configured password was sent automatically and didn't
+                // work, so the user needs to enter the correct one.
+                self.password_rejected = true;
+                if let State::Normal = self.state {
+                    self.state = State::Auth(auth::new());
+                }
+            }
+        }
+        if let Data::SendEvent(SendEvent(msg)) = data {
+            if !focused {
+                self.notify(msg);
+            }
+        }
+
         // Because the euphoria API is very carefully designed with emphasis on
         // consistency, some failures are not normal errors but instead
         // error-free replies that encode their own error.
@@ -584,16 +1435,72 @@ impl EuphRoom {
         if let Some((action, reason)) = error {
             let description = format!("Failed to {action}.");
             let reason = reason.unwrap_or_else(|| "no idea, the server wouldn't say".to_string());
-            self.popups.push_front(RoomPopup::Error {
-                description,
-                reason,
-            });
+            self.push_error(description, reason);
         }
 
         handled
     }
 
+    /// Run the `notify_command` if the message mentions us or matches one of
+    /// the highlight rules.
+    fn notify(&mut self, msg: &Message) {
+        let Some(command) = &self.config.notify_command else {
+            return;
+        };
+        if self.room_config.notify == NotifyLevel::None {
+            return;
+        }
+
+        let mentioned = self
+            .room_state_joined()
+            .is_some_and(|joined| euph::mentions(&msg.content, &joined.session.name));
+        let highlighted = self.highlights.iter().any(|h| h.is_match(&msg.content));
+        if !mentioned && !highlighted {
+            return;
+        }
+
+        if let Some(last) = self.last_notification {
+            if last.elapsed() < NOTIFY_INTERVAL {
+                return;
+            }
+        }
+        self.last_notification = Some(Instant::now());
+
+        euph::notify(
+            command,
+            &self.vault().room().name,
+            &msg.sender.name,
+            &msg.content,
+        );
+    }
+
+    fn auto_auth(&self) -> bool {
+        self.room_config.autojoin && self.room_config.auto_auth
+    }
+
+    /// Authenticate with the configured password unless it was already sent
+    /// or rejected.
+    fn send_password(&mut self) {
+        if self.password_sent || self.password_rejected {
+            return;
+        }
+        let (Some(room), Some(password)) = (&self.room, &self.room_config.password) else {
+            return;
+        };
+
+        self.password_sent = true;
+        let _ = room.auth(password.clone());
+    }
+
     fn handle_euph_error(&mut self, r#type: PacketType, reason: &str) -> bool {
+        if let PacketType::NickReply = r#type {
+            if let Some(nick) = self.pending_nick.take() {
+                // Let the user fix the nick instead of showing an error popup.
+                self.state = State::Nick(NickState::rejected(nick, reason.to_string()));
+                return true;
+            }
+        }
+
         let action = match r#type {
             PacketType::AuthReply => "authenticate",
             PacketType::NickReply => "set nick",
@@ -617,10 +1524,7 @@ impl EuphRoom {
             _ => return false,
         };
         let description = format!("Failed to {action}.");
-        self.popups.push_front(RoomPopup::Error {
-            description,
-            reason: reason.to_string(),
-        });
+        self.push_error(description, reason.to_string());
         true
     }
 }
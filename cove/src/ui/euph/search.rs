@@ -0,0 +1,41 @@
+use cove_config::Keys;
+use cove_input::InputEvent;
+use toss::widgets::EditorState;
+use toss::Widget;
+
+use crate::ui::widgets::Popup;
+use crate::ui::{util, UiError};
+
+use super::popup::PopupResult;
+
+pub fn new() -> EditorState {
+    EditorState::new()
+}
+
+pub fn widget<'a>(editor: &'a mut EditorState, title: &'a str) -> impl Widget<UiError> + 'a {
+    Popup::new(editor.widget(), title)
+}
+
+pub fn handle_input_event(
+    event: &mut InputEvent<'_>,
+    keys: &Keys,
+    editor: &mut EditorState,
+) -> PopupResult {
+    if event.matches(&keys.general.abort) {
+        return PopupResult::Close;
+    }
+
+    if event.matches(&keys.general.confirm) {
+        let query = editor.text().to_string();
+        if query.trim().is_empty() {
+            return PopupResult::Close;
+        }
+        return PopupResult::Search { query };
+    }
+
+    if util::handle_editor_input_event(editor, event, keys, |c| c != '\n') {
+        return PopupResult::Handled;
+    }
+
+    PopupResult::NotHandled
+}
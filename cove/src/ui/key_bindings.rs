@@ -2,7 +2,7 @@
 
 use std::convert::Infallible;
 
-use cove_config::{Config, Keys};
+use cove_config::Keys;
 use cove_input::{InputEvent, KeyBinding, KeyBindingInfo, KeyGroupInfo};
 use crossterm::style::Stylize;
 use toss::widgets::{Either2, Join2, Padding, Text};
@@ -68,11 +68,12 @@ fn render_group_info(builder: &mut Builder, group_info: KeyGroupInfo<'_>) {
 
 pub fn widget<'a>(
     list: &'a mut ListState<Infallible>,
-    config: &Config,
+    keys: &Keys,
+    border_style: Style,
 ) -> impl Widget<UiError> + 'a {
     let mut list_builder = ListBuilder::new();
 
-    for group_info in config.keys.groups() {
+    for group_info in keys.groups() {
         if !list_builder.is_empty() {
             render_empty(&mut list_builder);
         }
@@ -81,9 +82,9 @@ pub fn widget<'a>(
 
     let scroll_info_style = Style::new().grey().italic();
     let scroll_info = Styled::new("(Scroll with ", scroll_info_style)
-        .and_then(format_binding(&config.keys.cursor.down))
+        .and_then(format_binding(&keys.cursor.down))
         .then(" and ", scroll_info_style)
-        .and_then(format_binding(&config.keys.cursor.up))
+        .and_then(format_binding(&keys.cursor.up))
         .then(")", scroll_info_style);
 
     let inner = Join2::vertical(
@@ -95,7 +96,7 @@ pub fn widget<'a>(
             .with_growing(false),
     );
 
-    Popup::new(inner, "Key bindings")
+    Popup::new(inner, "Key bindings").with_border_style(border_style)
 }
 
 pub fn handle_input_event(
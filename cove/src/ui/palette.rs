@@ -0,0 +1,196 @@
+//! A popup listing the actions available on the current screen, filtered by a
+//! fuzzy search.
+
+use cove_config::Keys;
+use cove_input::{InputEvent, KeyBinding};
+use crossterm::event::KeyEvent;
+use crossterm::style::Stylize;
+use toss::widgets::{EditorState, Join2, Text};
+use toss::{Style, Styled, Widget, WidgetExt};
+
+use super::key_bindings::format_binding;
+use super::widgets::{ListBuilder, ListState, Popup};
+use super::{util, UiError};
+
+/// The screen the palette was opened from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    RoomList,
+    Room,
+    Log,
+}
+
+impl Context {
+    fn shows_group(self, group: &str) -> bool {
+        match group {
+            "general" | "scroll" | "cursor" => true,
+            "rooms.action" => self == Self::RoomList,
+            // The outbox bindings only work while the outbox popup is open,
+            // where they would otherwise trigger tree actions like replying.
+            "room.action" | "tree.action" => self == Self::Room,
+            "tree.cursor" => self == Self::Room || self == Self::Log,
+            _ => false,
+        }
+    }
+}
+
+struct Action {
+    name: &'static str,
+    description: &'static str,
+    binding: KeyBinding,
+}
+
+impl Action {
+    fn matches(&self, query: &str) -> bool {
+        fuzzy_match(query, self.description) || fuzzy_match(query, self.name)
+    }
+}
+
+/// Whether all characters of the query appear in the text in the same order,
+/// ignoring case.
+fn fuzzy_match(query: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !c.is_whitespace())
+        .all(|q| text.any(|t| t == q))
+}
+
+pub struct PaletteState {
+    filter: EditorState,
+    list: ListState<usize>,
+    actions: Vec<Action>,
+}
+
+pub enum PaletteResult {
+    NotHandled,
+    Handled,
+    Close,
+    /// Close the palette and perform the action bound to this key.
+    Execute(KeyEvent),
+}
+
+impl PaletteState {
+    pub fn new(keys: &Keys, context: Context) -> Self {
+        let mut actions = vec![];
+        for group_info in keys.groups() {
+            if !context.shows_group(group_info.name) {
+                continue;
+            }
+            for binding_info in group_info.bindings {
+                // Opening the palette from within itself does nothing useful
+                if group_info.name == "general" && binding_info.name == "palette" {
+                    continue;
+                }
+                actions.push(Action {
+                    name: binding_info.name,
+                    description: binding_info.description,
+                    binding: binding_info.binding.clone(),
+                });
+            }
+        }
+
+        Self {
+            filter: EditorState::new(),
+            list: ListState::new(),
+            actions,
+        }
+    }
+
+    fn matching(&self) -> impl Iterator<Item = usize> + '_ {
+        let query = self.filter.text();
+        self.actions
+            .iter()
+            .enumerate()
+            .filter(move |(_, action)| action.matches(query))
+            .map(|(i, _)| i)
+    }
+
+    /// The selected action, or the best match if the selection is outdated.
+    fn selected(&self) -> Option<&Action> {
+        let selected = self.list.selected().copied();
+        let mut matching = self.matching().peekable();
+        let first = matching.peek().copied();
+        let idx = match selected {
+            Some(idx) if matching.any(|i| i == idx) => idx,
+            _ => first?,
+        };
+        Some(&self.actions[idx])
+    }
+
+    pub fn widget(&mut self, border_style: Style) -> impl Widget<UiError> + '_ {
+        let query = self.filter.text().to_string();
+
+        let mut list_builder = ListBuilder::new();
+        for (i, action) in self.actions.iter().enumerate() {
+            if !action.matches(&query) {
+                continue;
+            }
+
+            let binding = format_binding(&action.binding);
+            let description = action.description;
+            list_builder.add_sel(i, move |selected| {
+                let style = if selected {
+                    Style::new().black().on_white()
+                } else {
+                    Style::new()
+                };
+                let text = Styled::new(description, style)
+                    .then("  ", style)
+                    .and_then(binding);
+                Text::new(text)
+                    .with_wrap(false)
+                    .background()
+                    .with_style(style)
+            });
+        }
+
+        let filter = Join2::horizontal(
+            Text::new(("> ", Style::new().grey()))
+                .segment()
+                .with_fixed(true),
+            self.filter.widget().with_focus(true).segment(),
+        );
+
+        let inner = Join2::vertical(
+            filter.segment().with_fixed(true),
+            list_builder.build(&mut self.list).segment(),
+        );
+
+        Popup::new(inner, "Command palette").with_border_style(border_style)
+    }
+
+    pub fn handle_input_event(&mut self, event: &mut InputEvent<'_>, keys: &Keys) -> PaletteResult {
+        if event.matches(&keys.general.abort) || event.matches(&keys.general.palette) {
+            return PaletteResult::Close;
+        }
+
+        if event.matches(&keys.general.confirm) {
+            let key = self
+                .selected()
+                .and_then(|action| action.binding.keys().first());
+            return match key {
+                Some(key) => PaletteResult::Execute(key.to_key_event()),
+                None => PaletteResult::Handled,
+            };
+        }
+
+        // The list cursor is moved with keys that don't produce characters, so
+        // typing into the filter is never shadowed.
+        if event.matches(&keys.editor.cursor.up) {
+            self.list.move_cursor_up();
+            return PaletteResult::Handled;
+        }
+        if event.matches(&keys.editor.cursor.down) {
+            self.list.move_cursor_down();
+            return PaletteResult::Handled;
+        }
+
+        if util::handle_editor_input_event(&mut self.filter, event, keys, |c| c != '\n') {
+            return PaletteResult::Handled;
+        }
+
+        PaletteResult::NotHandled
+    }
+}
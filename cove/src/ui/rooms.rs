@@ -1,13 +1,16 @@
 mod connect;
 mod delete;
+mod metrics;
 
+use std::cmp::Reverse;
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 use std::iter;
+use std::sync::atomic::AtomicUsize;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use cove_config::{Config, Keys, RoomsSortOrder};
+use cove_config::{Config, Keys, NickListSummary, NotifyLevel, RoomsSortOrder};
 use cove_input::InputEvent;
 use crossterm::style::Stylize;
 use euphoxide::api::SessionType;
@@ -15,26 +18,35 @@ use euphoxide::bot::instance::{Event, ServerConfig};
 use euphoxide::conn::{self, Joined};
 use jiff::tz::TimeZone;
 use tokio::sync::mpsc;
-use toss::widgets::{BoxedAsync, Empty, Join2, Text};
-use toss::{Style, Styled, Widget, WidgetExt};
+use toss::widgets::{BoxedAsync, EditorState, Empty, Join2, Text};
+use toss::{Style, Styled, Widget, WidgetExt, WidthDb};
 
-use crate::euph;
+use crate::euph::{self, SmallMessage};
 use crate::macros::logging_unwrap;
 use crate::vault::{EuphVault, RoomIdentifier, Vault};
 use crate::version::{NAME, VERSION};
 
 use self::connect::{ConnectResult, ConnectState};
-use self::delete::{DeleteResult, DeleteState};
+use self::delete::{DeleteResult, DeleteState, DeleteSummary};
+use self::metrics::MetricsResult;
 
 use super::euph::room::EuphRoom;
+use super::theme::Theme;
 use super::widgets::{ListBuilder, ListState};
 use super::{key_bindings, util, UiError, UiEvent};
 
+/// Number of recently joined rooms offered for completion when connecting.
+const RECENT_ROOMS: usize = 50;
+
+/// Maximum width of the message preview in the rooms list.
+const PREVIEW_WIDTH: usize = 60;
+
 enum State {
     ShowList,
     ShowRoom(RoomIdentifier),
     Connect(ConnectState),
     Delete(DeleteState),
+    Metrics,
 }
 
 #[derive(Clone, Copy)]
@@ -54,7 +66,8 @@ impl Order {
 
 struct EuphServer {
     config: ServerConfig,
-    next_instance_id: usize,
+    /// Shared by all rooms on this server so their instances get unique names.
+    instance_ids: Arc<AtomicUsize>,
 }
 
 impl EuphServer {
@@ -67,7 +80,7 @@ impl EuphServer {
 
         Self {
             config,
-            next_instance_id: 0,
+            instance_ids: Arc::new(AtomicUsize::new(0)),
         }
     }
 }
@@ -75,6 +88,7 @@ impl EuphServer {
 pub struct Rooms {
     config: &'static Config,
     tz: TimeZone,
+    theme: Theme,
 
     vault: Vault,
     ui_event_tx: mpsc::UnboundedSender<UiEvent>,
@@ -83,30 +97,60 @@ pub struct Rooms {
 
     list: ListState<RoomIdentifier>,
     order: Order,
+    /// Only rooms whose domain or name contains this text are shown.
+    filter: EditorState,
+    /// Whether the filter is currently being edited.
+    filtering: bool,
 
     euph_servers: HashMap<String, EuphServer>,
     euph_rooms: HashMap<RoomIdentifier, EuphRoom>,
+
+    /// Key bindings of rooms with room-specific overrides.
+    room_keys: HashMap<RoomIdentifier, Keys>,
+
+    /// When each room was last viewed, or connected to if it hasn't been
+    /// viewed since. Used for `idle_disconnect_after`.
+    last_viewed: HashMap<RoomIdentifier, Instant>,
+    /// Rooms that were disconnected for being idle and should be reconnected
+    /// once they are viewed again.
+    idle_disconnected: HashSet<RoomIdentifier>,
 }
 
 impl Rooms {
     pub async fn new(
         config: &'static Config,
         tz: TimeZone,
+        theme: Theme,
         vault: Vault,
         ui_event_tx: mpsc::UnboundedSender<UiEvent>,
     ) -> Self {
         let mut result = Self {
             config,
             tz,
+            theme,
             vault,
             ui_event_tx,
             state: State::ShowList,
             list: ListState::new(),
             order: Order::from_rooms_sort_order(config.rooms_sort_order),
+            filter: EditorState::new(),
+            filtering: false,
             euph_servers: HashMap::new(),
             euph_rooms: HashMap::new(),
+            room_keys: HashMap::new(),
+            last_viewed: HashMap::new(),
+            idle_disconnected: HashSet::new(),
         };
 
+        for (domain, server) in &config.euph.servers {
+            for name in server.rooms.keys() {
+                if let Some(keys) = config.room_keys(domain, name) {
+                    let id = RoomIdentifier::new(domain.clone(), name.clone());
+                    result.room_keys.insert(id, keys);
+                }
+            }
+        }
+
         if !config.offline {
             for (domain, server) in &config.euph.servers {
                 for (name, room) in &server.rooms {
@@ -147,12 +191,16 @@ impl Rooms {
                 self.config.euph_room(&room.domain, &room.name),
                 self.vault.euph().room(room),
                 self.tz.clone(),
+                &self.theme,
                 self.ui_event_tx.clone(),
             )
         })
     }
 
     async fn connect_to_room(&mut self, room: RoomIdentifier) {
+        self.last_viewed.insert(room.clone(), Instant::now());
+        self.idle_disconnected.remove(&room);
+
         let server =
             Self::get_or_insert_server(&self.vault, &mut self.euph_servers, room.domain.clone())
                 .await;
@@ -164,11 +212,12 @@ impl Rooms {
                 self.config.euph_room(&room.domain, &room.name),
                 self.vault.euph().room(room),
                 self.tz.clone(),
+                &self.theme,
                 self.ui_event_tx.clone(),
             )
         });
 
-        room.connect(&mut server.next_instance_id);
+        room.connect(&server.instance_ids);
     }
 
     async fn connect_to_all_rooms(&mut self) {
@@ -177,7 +226,85 @@ impl Rooms {
                 Self::get_or_insert_server(&self.vault, &mut self.euph_servers, id.domain.clone())
                     .await;
 
-            room.connect(&mut server.next_instance_id);
+            room.connect(&server.instance_ids);
+        }
+    }
+
+    async fn show_room(&mut self, room: RoomIdentifier) {
+        if self.idle_disconnected.contains(&room) {
+            self.connect_to_room(room.clone()).await;
+        }
+        self.last_viewed.insert(room.clone(), Instant::now());
+
+        if let Some(euph_room) = self.euph_rooms.get_mut(&room) {
+            euph_room.opened().await;
+        }
+        self.state = State::ShowRoom(room);
+    }
+
+    /// Disconnect from rooms that haven't been viewed for longer than
+    /// `idle_disconnect_after`.
+    pub fn disconnect_idle_rooms(&mut self) {
+        let Some(idle_after) = self.config.idle_disconnect_after() else {
+            return;
+        };
+
+        // The room being shown is being viewed right now.
+        if let State::ShowRoom(id) = &self.state {
+            self.last_viewed.insert(id.clone(), Instant::now());
+        }
+
+        for (id, room) in &mut self.euph_rooms {
+            if room.stopped() {
+                continue;
+            }
+
+            let autojoin = self.config.euph_room(&id.domain, &id.name).autojoin;
+            if autojoin && !self.config.idle_disconnect_autojoin {
+                continue;
+            }
+
+            let last_viewed = *self
+                .last_viewed
+                .entry(id.clone())
+                .or_insert_with(Instant::now);
+            if last_viewed.elapsed() >= idle_after {
+                room.disconnect();
+                self.idle_disconnected.insert(id.clone());
+            }
+        }
+    }
+
+    /// Open the next room with unseen messages in order of importance, starting
+    /// after the selected room, and move to its oldest unseen message.
+    async fn show_next_unseen_room(&mut self) {
+        let mut rooms = vec![];
+        for (id, room) in &self.euph_rooms {
+            let unseen = room.unseen_msgs_count().await;
+            if unseen > 0 {
+                rooms.push((id, room.room_state(), unseen, room.pinned()));
+            }
+        }
+        Self::sort_rooms(
+            &mut rooms,
+            Order::Importance,
+            self.config.rooms_sort_by_unseen_count,
+        );
+
+        let selected = self.list.selected();
+        let next = match rooms.iter().position(|(id, _, _, _)| Some(*id) == selected) {
+            Some(idx) => rooms.get(idx + 1).or(rooms.first()),
+            None => rooms.first(),
+        };
+        let Some((next, _, _, _)) = next else {
+            return;
+        };
+        let next = (*next).clone();
+
+        self.list.move_cursor_to_next_matching(|id| *id == next);
+        self.show_room(next.clone()).await;
+        if let Some(room) = self.euph_rooms.get_mut(&next) {
+            room.move_cursor_to_oldest_unseen().await;
         }
     }
 
@@ -187,6 +314,11 @@ impl Rooms {
         }
     }
 
+    pub async fn reconnect_to_room(&mut self, room: RoomIdentifier) {
+        self.disconnect_from_room(&room);
+        self.connect_to_room(room).await;
+    }
+
     fn disconnect_from_all_rooms(&mut self) {
         for room in self.euph_rooms.values_mut() {
             room.disconnect();
@@ -236,10 +368,60 @@ impl Rooms {
         }
     }
 
-    pub async fn widget(&mut self) -> BoxedAsync<'_, UiError> {
+    async fn delete_summary(&self, room: RoomIdentifier) -> DeleteSummary {
+        let vault = self.vault.euph().room(room);
+        let msg_count = logging_unwrap!(vault.msg_count().await);
+        let time_range = logging_unwrap!(vault.time_range().await).map(|(oldest, newest)| {
+            (
+                oldest.as_timestamp().to_zoned(self.tz.clone()),
+                newest.as_timestamp().to_zoned(self.tz.clone()),
+            )
+        });
+        let content_size = logging_unwrap!(vault.content_size().await);
+
+        DeleteSummary {
+            msg_count,
+            time_range,
+            content_size,
+        }
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        for room in self.euph_rooms.values_mut() {
+            room.set_theme(&theme);
+        }
+        self.theme = theme;
+    }
+
+    /// Whether a single room is currently shown instead of the room list.
+    pub fn showing_room(&self) -> bool {
+        matches!(self.state, State::ShowRoom(_))
+    }
+
+    /// Key bindings currently in effect, taking room-specific overrides of the
+    /// shown room into account.
+    pub fn keys(&self) -> &Keys {
+        match &self.state {
+            State::ShowRoom(id) => self.room_keys.get(id).unwrap_or(&self.config.keys),
+            _ => &self.config.keys,
+        }
+    }
+
+    pub async fn widget(&mut self, widthdb: &mut WidthDb) -> BoxedAsync<'_, UiError> {
         match &self.state {
             State::ShowRoom(_) => {}
-            _ => self.stabilize_rooms().await,
+            _ => {
+                self.stabilize_rooms().await;
+                for room in self.euph_rooms.values_mut() {
+                    if self.config.rooms_message_preview {
+                        room.update_preview().await;
+                    }
+                    if self.config.show_message_counts {
+                        room.update_msg_count().await;
+                    }
+                    room.update_unseen_mentions_count().await;
+                }
+            }
         }
 
         match &mut self.state {
@@ -249,16 +431,21 @@ impl Rooms {
                 &mut self.list,
                 self.order,
                 &self.euph_rooms,
+                &mut self.filter,
+                self.filtering,
+                &self.theme,
+                widthdb,
             )
             .await
             .desync()
             .boxed_async(),
 
             State::ShowRoom(id) => {
+                let keys = self.room_keys.get(id).unwrap_or(&self.config.keys);
                 self.euph_rooms
                     .get_mut(id)
                     .expect("room exists after stabilization")
-                    .widget()
+                    .widget(widthdb, keys)
                     .await
             }
 
@@ -268,9 +455,13 @@ impl Rooms {
                 &mut self.list,
                 self.order,
                 &self.euph_rooms,
+                &mut self.filter,
+                self.filtering,
+                &self.theme,
+                widthdb,
             )
             .await
-            .below(connect.widget())
+            .below(connect.widget(&self.config.keys))
             .desync()
             .boxed_async(),
 
@@ -280,15 +471,35 @@ impl Rooms {
                 &mut self.list,
                 self.order,
                 &self.euph_rooms,
+                &mut self.filter,
+                self.filtering,
+                &self.theme,
+                widthdb,
+            )
+            .await
+            .below(delete.widget(&self.config.keys))
+            .desync()
+            .boxed_async(),
+
+            State::Metrics => Self::rooms_widget(
+                &self.vault,
+                self.config,
+                &mut self.list,
+                self.order,
+                &self.euph_rooms,
+                &mut self.filter,
+                self.filtering,
+                &self.theme,
+                widthdb,
             )
             .await
-            .below(delete.widget())
+            .below(metrics::widget(&self.euph_rooms))
             .desync()
             .boxed_async(),
         }
     }
 
-    fn format_pbln(joined: &Joined) -> String {
+    fn format_pbln(joined: &Joined, format: NickListSummary) -> Option<String> {
         let mut p = 0_usize;
         let mut b = 0_usize;
         let mut l = 0_usize;
@@ -311,39 +522,69 @@ impl Rooms {
             }
         }
 
+        let counts = [
+            (p, "p", "person", "people"),
+            (b, "b", "bot", "bots"),
+            (l, "l", "lurker", "lurkers"),
+            (n, "n", "nurker", "nurkers"),
+        ];
+
         // There must always be either one p, b, l or n since we're including
         // ourselves.
-        let mut result = vec![];
-        if p > 0 {
-            result.push(format!("{p}p"));
-        }
-        if b > 0 {
-            result.push(format!("{b}b"));
-        }
-        if l > 0 {
-            result.push(format!("{l}l"));
+        let counts = counts.into_iter().filter(|(amount, ..)| *amount > 0);
+        match format {
+            NickListSummary::Compact => Some(
+                counts
+                    .map(|(amount, short, _, _)| format!("{amount}{short}"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            NickListSummary::Verbose => Some(
+                counts
+                    .map(|(amount, _, one, many)| match amount {
+                        1 => format!("1 {one}"),
+                        _ => format!("{amount} {many}"),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            NickListSummary::Hidden => None,
         }
-        if n > 0 {
-            result.push(format!("{n}n"));
-        }
-        result.join(" ")
     }
 
-    fn format_room_state(state: Option<&euph::State>) -> Option<String> {
+    fn format_room_state(state: Option<&euph::State>, format: NickListSummary) -> Option<String> {
         match state {
             None | Some(euph::State::Stopped) => None,
-            Some(euph::State::Disconnected) => Some("waiting".to_string()),
+            Some(state @ euph::State::Disconnected(_)) => match state.retry_in() {
+                Some(retry_in) => Some(format!(
+                    "waiting (retry in {})",
+                    util::format_duration(retry_in)
+                )),
+                None => Some("waiting".to_string()),
+            },
             Some(euph::State::Connecting) => Some("connecting".to_string()),
-            Some(euph::State::Connected(_, connected)) => match connected {
+            Some(euph::State::Connected(_, connected, _)) => match connected {
                 conn::State::Joining(joining) if joining.bounce.is_some() => {
                     Some("auth required".to_string())
                 }
                 conn::State::Joining(_) => Some("joining".to_string()),
-                conn::State::Joined(joined) => Some(Self::format_pbln(joined)),
+                conn::State::Joined(joined) => Self::format_pbln(joined, format),
             },
         }
     }
 
+    /// Whether the room is in a state that likely requires the user's
+    /// attention, e.g. because it can't connect or needs a password.
+    fn is_failing(state: Option<&euph::State>) -> bool {
+        match state {
+            Some(euph::State::Disconnected(_)) => true,
+            Some(euph::State::Connected(_, conn::State::Joining(joining), _)) => {
+                joining.bounce.is_some()
+            }
+            _ => false,
+        }
+    }
+
     fn format_unseen_msgs(unseen: usize) -> Option<String> {
         if unseen == 0 {
             None
@@ -352,49 +593,84 @@ impl Rooms {
         }
     }
 
-    fn format_room_info(state: Option<&euph::State>, unseen: usize) -> Styled {
-        let unseen_style = Style::new().bold().green();
-
-        let state = Self::format_room_state(state);
-        let unseen = Self::format_unseen_msgs(unseen);
-
-        match (state, unseen) {
-            (None, None) => Styled::default(),
-            (None, Some(u)) => Styled::new_plain(" (")
-                .then(u, unseen_style)
-                .then_plain(")"),
-            (Some(s), None) => Styled::new_plain(" (").then_plain(s).then_plain(")"),
-            (Some(s), Some(u)) => Styled::new_plain(" (")
-                .then_plain(s)
-                .then_plain(", ")
-                .then(u, unseen_style)
-                .then_plain(")"),
+    fn format_room_info(
+        state: Option<&euph::State>,
+        unseen: usize,
+        msgs: Option<usize>,
+        format: NickListSummary,
+        unseen_style: Style,
+    ) -> Styled {
+        let msgs_style = Style::new().dark_grey();
+
+        let state = Self::format_room_state(state, format).map(|s| (s, Style::new()));
+        let unseen = Self::format_unseen_msgs(unseen).map(|u| (u, unseen_style));
+        let msgs = msgs.map(|m| (format!("{m}"), msgs_style));
+
+        let mut result = Styled::default();
+        for (i, (text, style)) in [state, unseen, msgs].into_iter().flatten().enumerate() {
+            result = result.then_plain(if i == 0 { " (" } else { ", " });
+            result = result.then(text, style);
+        }
+        if !result.text().is_empty() {
+            result = result.then_plain(")");
         }
+        result
+    }
+
+    fn format_preview(widthdb: &mut WidthDb, msg: &SmallMessage) -> String {
+        let nick = euph::EMOJI.replace(&msg.nick);
+        let line = msg.content.lines().next().unwrap_or_default();
+        euph::truncate(widthdb, &format!("{nick}: {line}"), PREVIEW_WIDTH)
     }
 
-    fn sort_rooms(rooms: &mut [(&RoomIdentifier, Option<&euph::State>, usize)], order: Order) {
+    /// Sort rooms according to the order. Pinned rooms always come first.
+    fn sort_rooms(
+        rooms: &mut [(&RoomIdentifier, Option<&euph::State>, usize, bool)],
+        order: Order,
+        by_unseen_count: bool,
+    ) {
         match order {
-            Order::Alphabet => rooms.sort_unstable_by_key(|(id, _, _)| *id),
-            Order::Importance => rooms
-                .sort_unstable_by_key(|(id, state, unseen)| (state.is_none(), *unseen == 0, *id)),
+            Order::Alphabet => rooms.sort_unstable_by_key(|(id, _, _, pinned)| (!*pinned, *id)),
+            Order::Importance => rooms.sort_unstable_by_key(|(id, state, unseen, pinned)| {
+                let count = if by_unseen_count { *unseen } else { 0 };
+                (!*pinned, state.is_none(), *unseen == 0, Reverse(count), *id)
+            }),
         }
     }
 
     async fn render_rows(
         list_builder: &mut ListBuilder<'_, RoomIdentifier, Text>,
+        config: &Config,
         order: Order,
         euph_rooms: &HashMap<RoomIdentifier, EuphRoom>,
+        filter: &str,
+        theme: &Theme,
+        widthdb: &mut WidthDb,
     ) {
         let mut rooms = vec![];
         for (id, room) in euph_rooms {
+            let matches = id.domain.to_lowercase().contains(filter)
+                || id.name.to_lowercase().contains(filter);
+            if !matches {
+                continue;
+            }
+
             let state = room.room_state();
             let unseen = room.unseen_msgs_count().await;
-            rooms.push((id, state, unseen));
+            rooms.push((id, state, unseen, room.pinned()));
         }
-        Self::sort_rooms(&mut rooms, order);
-        for (id, state, unseen) in rooms {
+        Self::sort_rooms(&mut rooms, order, config.rooms_sort_by_unseen_count);
+        for (id, state, unseen, pinned) in rooms {
             let id = id.clone();
-            let info = Self::format_room_info(state, unseen);
+            let msgs = euph_rooms[&id]
+                .msg_count()
+                .filter(|_| config.show_message_counts);
+            let info =
+                Self::format_room_info(state, unseen, msgs, config.nick_list_summary, theme.unseen);
+            let preview = euph_rooms[&id]
+                .preview()
+                .filter(|_| config.rooms_message_preview)
+                .map(|msg| Self::format_preview(widthdb, msg));
             list_builder.add_sel(id.clone(), move |selected| {
                 let domain_style = if selected {
                     Style::new().black().on_white()
@@ -408,21 +684,41 @@ impl Rooms {
                     Style::new().bold().blue()
                 };
 
-                let text = Styled::new(format!("{} ", id.domain), domain_style)
+                let mut text = if pinned {
+                    Styled::new("★ ", room_style)
+                } else {
+                    Styled::default()
+                };
+                text = text
+                    .then(format!("{} ", id.domain), domain_style)
                     .then(format!("&{}", id.name), room_style)
                     .and_then(info);
 
+                if let Some(preview) = preview {
+                    let preview_style = if selected {
+                        Style::new().black().on_white()
+                    } else {
+                        Style::new().grey()
+                    };
+                    text = text.then(format!("  {preview}"), preview_style);
+                }
+
                 Text::new(text)
             });
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn rooms_widget<'a>(
         vault: &Vault,
         config: &Config,
         list: &'a mut ListState<RoomIdentifier>,
         order: Order,
         euph_rooms: &HashMap<RoomIdentifier, EuphRoom>,
+        filter: &'a mut EditorState,
+        filtering: bool,
+        theme: &Theme,
+        widthdb: &mut WidthDb,
     ) -> impl Widget<UiError> + 'a {
         let version_info = Styled::new_plain("Welcome to ")
             .then(format!("{NAME} {VERSION}"), Style::new().yellow().bold())
@@ -446,26 +742,85 @@ impl Rooms {
             .iter()
             .filter(|r| r.1.room_state().is_some())
             .count();
-        let total_unseen = logging_unwrap!(vault.euph().total_unseen_msgs_count().await);
+        let failing_rooms = euph_rooms
+            .values()
+            .filter(|r| Self::is_failing(r.room_state()))
+            .count();
+        // Only rooms notifying about all messages are counted by the vault.
+        // Rooms notifying on mentions contribute their cached mention count.
+        let excluded = euph_rooms
+            .iter()
+            .filter(|(_, room)| room.notify() != NotifyLevel::All)
+            .map(|(id, _)| id.clone())
+            .collect();
+        let mut total_unseen =
+            logging_unwrap!(vault.euph().total_unseen_msgs_count(excluded).await);
+        total_unseen += euph_rooms
+            .values()
+            .filter(|room| room.notify() == NotifyLevel::Mentions)
+            .map(|room| room.unseen_mentions_count())
+            .sum::<usize>();
+
+        heading = heading.then_plain(format!(" ({connected_rooms}/{total_rooms}"));
+        if failing_rooms > 0 {
+            heading = heading.then_plain(", ").then(
+                format!("{failing_rooms} failing"),
+                Style::new().bold().red(),
+            );
+        } else if total_rooms > 0 && connected_rooms == total_rooms {
+            heading = heading
+                .then_plain(", ")
+                .then("all connected", Style::new().grey());
+        }
         if total_unseen > 0 {
             heading = heading
-                .then_plain(format!(" ({connected_rooms}/{total_rooms}, "))
-                .then(format!("{total_unseen}"), Style::new().bold().green())
-                .then_plain(")");
+                .then_plain(", ")
+                .then(format!("{total_unseen}"), theme.unseen);
             title.push_str(&format!(" ({total_unseen})"));
-        } else {
-            heading = heading.then_plain(format!(" ({connected_rooms}/{total_rooms})"))
         }
+        heading = heading.then_plain(")");
 
+        let filter_text = filter.text().to_lowercase();
         let mut list_builder = ListBuilder::new();
-        Self::render_rows(&mut list_builder, order, euph_rooms).await;
+        Self::render_rows(
+            &mut list_builder,
+            config,
+            order,
+            euph_rooms,
+            &filter_text,
+            theme,
+            widthdb,
+        )
+        .await;
+        let list = list_builder.build(list);
 
-        Join2::horizontal(
+        let rooms = if filter_text.is_empty() && !filtering {
             Join2::vertical(
                 Text::new(heading).segment().with_fixed(true),
-                list_builder.build(list).segment(),
+                list.segment(),
+            )
+            .first2()
+        } else {
+            let filter = Join2::horizontal(
+                Text::new(("/", Style::new().grey()))
+                    .segment()
+                    .with_fixed(true),
+                filter.widget().with_focus(filtering).segment(),
+            );
+            Join2::vertical(
+                Join2::vertical(
+                    Text::new(heading).segment().with_fixed(true),
+                    filter.segment().with_fixed(true),
+                )
+                .segment()
+                .with_fixed(true),
+                list.segment(),
             )
-            .segment(),
+            .second2()
+        };
+
+        Join2::horizontal(
+            rooms.segment(),
             Join2::vertical(info.segment().with_growing(false), Empty::new().segment())
                 .segment()
                 .with_growing(false),
@@ -478,10 +833,43 @@ impl Rooms {
         event: &mut InputEvent<'_>,
         keys: &Keys,
     ) -> bool {
+        if self.filtering {
+            if event.matches(&keys.general.abort) {
+                self.filter.clear();
+                self.filtering = false;
+                return true;
+            }
+
+            // Open the top match
+            if event.matches(&keys.general.confirm) {
+                self.filtering = false;
+                self.list.move_cursor_to_top();
+                if let Some(name) = self.list.selected() {
+                    self.show_room(name.clone()).await;
+                }
+                return true;
+            }
+
+            if util::handle_editor_input_event(&mut self.filter, event, keys, |c| c != '\n') {
+                return true;
+            }
+
+            return false;
+        }
+
+        if event.matches(&keys.rooms.action.filter) {
+            self.filtering = true;
+            return true;
+        }
+        if event.matches(&keys.general.abort) && !self.filter.text().is_empty() {
+            self.filter.clear();
+            return true;
+        }
+
         // Open room
         if event.matches(&keys.general.confirm) {
             if let Some(name) = self.list.selected() {
-                self.state = State::ShowRoom(name.clone());
+                self.show_room(name.clone()).await;
             }
             return true;
         }
@@ -508,6 +896,12 @@ impl Rooms {
             }
             return true;
         }
+        if event.matches(&keys.rooms.action.reconnect) {
+            if let Some(room) = self.list.selected() {
+                self.reconnect_to_room(room.clone()).await;
+            }
+            return true;
+        }
         if event.matches(&keys.rooms.action.disconnect_all) {
             self.disconnect_from_all_rooms();
             return true;
@@ -531,12 +925,15 @@ impl Rooms {
             return true;
         }
         if event.matches(&keys.rooms.action.new) {
-            self.state = State::Connect(ConnectState::new());
+            let recent = logging_unwrap!(self.vault.euph().recent_rooms(RECENT_ROOMS).await);
+            let domain = self.config.euph.default_domain().to_string();
+            self.state = State::Connect(ConnectState::new(domain, recent));
             return true;
         }
         if event.matches(&keys.rooms.action.delete) {
             if let Some(room) = self.list.selected() {
-                self.state = State::Delete(DeleteState::new(room.clone()));
+                let summary = self.delete_summary(room.clone()).await;
+                self.state = State::Delete(DeleteState::new(room.clone(), summary));
             }
             return true;
         }
@@ -547,6 +944,23 @@ impl Rooms {
             };
             return true;
         }
+        if event.matches(&keys.rooms.action.metrics) {
+            self.state = State::Metrics;
+            return true;
+        }
+        if event.matches(&keys.rooms.action.to_next_unseen) {
+            self.show_next_unseen_room().await;
+            return true;
+        }
+        if event.matches(&keys.rooms.action.to_next_failing) {
+            let euph_rooms = &self.euph_rooms;
+            self.list.move_cursor_to_next_matching(|id| {
+                euph_rooms
+                    .get(id)
+                    .is_some_and(|room| Self::is_failing(room.room_state()))
+            });
+            return true;
+        }
 
         false
     }
@@ -562,6 +976,7 @@ impl Rooms {
             }
             State::ShowRoom(name) => {
                 if let Some(room) = self.euph_rooms.get_mut(name) {
+                    let keys = self.room_keys.get(name).unwrap_or(keys);
                     if room.handle_input_event(event, keys).await {
                         return true;
                     }
@@ -578,7 +993,7 @@ impl Rooms {
                 }
                 ConnectResult::Connect(room) => {
                     self.connect_to_room(room.clone()).await;
-                    self.state = State::ShowRoom(room);
+                    self.show_room(room).await;
                     return true;
                 }
                 ConnectResult::Handled => {
@@ -602,6 +1017,13 @@ impl Rooms {
                 }
                 DeleteResult::Unhandled => {}
             },
+            State::Metrics => match metrics::handle_input_event(event, keys) {
+                MetricsResult::Close => {
+                    self.state = State::ShowList;
+                    return true;
+                }
+                MetricsResult::Unhandled => {}
+            },
         }
 
         false
@@ -614,7 +1036,8 @@ impl Rooms {
             return false;
         };
 
-        let handled = room.handle_event(event).await;
+        let focused = matches!(&self.state, State::ShowRoom(id) if *id == room_id);
+        let handled = room.handle_event(event, focused).await;
 
         let room_visible = match &self.state {
             State::ShowRoom(id) => *id == room_id,
@@ -1,13 +1,71 @@
 use cove_config::Keys;
 use cove_input::InputEvent;
 use crossterm::style::Stylize;
-use toss::widgets::{EditorState, Empty, Join2, Join3, Text};
+use toss::widgets::{Boxed, EditorState, Empty, Join2, Join3, Text};
 use toss::{Style, Styled, Widget, WidgetExt};
 
 use crate::ui::widgets::Popup;
-use crate::ui::{util, UiError};
+use crate::ui::{key_bindings, util, UiError};
 use crate::vault::RoomIdentifier;
 
+/// Maximum number of completion candidates shown below the editors.
+const SHOWN_CANDIDATES: usize = 5;
+
+/// Schemes accepted when a full room URL is entered instead of a room name.
+const URL_SCHEMES: &[&str] = &["wss://", "https://"];
+
+/// Schemes of unencrypted room URLs. Cove always connects via TLS, so these are
+/// rejected instead of silently connecting securely anyways.
+const INSECURE_URL_SCHEMES: &[&str] = &["ws://", "http://"];
+
+/// Characters that may appear in a room URL in addition to room characters.
+fn is_url_char(c: char) -> bool {
+    util::is_room_char(c) || "&:/.-#?".contains(c)
+}
+
+/// Only rejects hosts that can't possibly work. Anything else, like IPv6
+/// literals or names with underscores, is left for the connection to accept or
+/// reject.
+fn is_valid_host(host: &str) -> bool {
+    !host.is_empty() && !host.contains(char::is_whitespace)
+}
+
+fn is_valid_room_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(util::is_room_char)
+}
+
+/// Parse a room URL like `wss://euphoria.leet.nu/room/test/ws` or
+/// `https://euphoria.leet.nu/room/test/`.
+fn parse_room_url(url: &str) -> Result<RoomIdentifier, String> {
+    if INSECURE_URL_SCHEMES.iter().any(|s| url.starts_with(s)) {
+        return Err(format!(
+            "unencrypted url {url:?}, use wss:// or https:// instead"
+        ));
+    }
+
+    let rest = URL_SCHEMES
+        .iter()
+        .find_map(|scheme| url.strip_prefix(scheme))
+        .ok_or_else(|| format!("unknown url scheme in {url:?}"))?;
+
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    if !is_valid_host(host) {
+        return Err(format!("invalid host {host:?}"));
+    }
+
+    let path = path.split(['#', '?']).next().unwrap_or_default();
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+    let name = match (segments.next(), segments.next(), segments.next()) {
+        (Some("room"), Some(name), None | Some("ws")) => name,
+        _ => return Err(format!("expected a path like /room/<name>/ in {url:?}")),
+    };
+    if segments.next().is_some() || !is_valid_room_name(name) {
+        return Err(format!("invalid room in {url:?}"));
+    }
+
+    Ok(RoomIdentifier::new(host.to_string(), name.to_string()))
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum Focus {
     Name,
@@ -23,10 +81,24 @@ impl Focus {
     }
 }
 
+/// Completion of the room and domain based on recently joined rooms.
+struct Completion {
+    candidates: Vec<RoomIdentifier>,
+    current: usize,
+}
+
 pub struct ConnectState {
     focus: Focus,
     name: EditorState,
     domain: EditorState,
+    /// Whether the domain was edited by the user. Until then, it only contains
+    /// the pre-filled default domain, which shouldn't restrict completion.
+    domain_edited: bool,
+    /// Recently joined rooms, newest first.
+    recent: Vec<RoomIdentifier>,
+    completion: Option<Completion>,
+    /// Shown in a popup until dismissed.
+    error: Option<String>,
 }
 
 pub enum ConnectResult {
@@ -37,15 +109,85 @@ pub enum ConnectResult {
 }
 
 impl ConnectState {
-    pub fn new() -> Self {
+    pub fn new(default_domain: String, recent: Vec<RoomIdentifier>) -> Self {
         Self {
             focus: Focus::Name,
             name: EditorState::new(),
-            domain: EditorState::with_initial_text("euphoria.leet.nu".to_string()),
+            domain: EditorState::with_initial_text(default_domain),
+            domain_edited: false,
+            recent,
+            completion: None,
+            error: None,
         }
     }
 
+    /// The room to connect to, either from a full room URL entered as the
+    /// room name or from the room name and domain editors.
+    fn room(&self) -> Result<RoomIdentifier, String> {
+        let name = self.name.text().trim();
+        if name.contains("://") {
+            return parse_room_url(name);
+        }
+
+        let name = name.strip_prefix('&').unwrap_or(name);
+        if !is_valid_room_name(name) {
+            return Err(format!("invalid room name {name:?}"));
+        }
+
+        let domain = self.domain.text().trim();
+        if !is_valid_host(domain) {
+            return Err(format!("invalid domain {domain:?}"));
+        }
+
+        Ok(RoomIdentifier::new(domain.to_string(), name.to_string()))
+    }
+
+    /// Recent rooms matching what was typed into the editors so far.
+    fn candidates(&self) -> Vec<RoomIdentifier> {
+        let name = self.name.text();
+        let domain = if self.domain_edited {
+            self.domain.text()
+        } else {
+            ""
+        };
+        self.recent
+            .iter()
+            .filter(|id| id.name.contains(name) && id.domain.contains(domain))
+            .cloned()
+            .collect()
+    }
+
+    fn complete(&mut self, event: &mut InputEvent<'_>) {
+        if let Some(completion) = &mut self.completion {
+            completion.current = (completion.current + 1) % completion.candidates.len();
+        } else {
+            let candidates = self.candidates();
+            if candidates.is_empty() {
+                return;
+            }
+            self.completion = Some(Completion {
+                candidates,
+                current: 0,
+            });
+        }
+
+        let Some(completion) = &self.completion else {
+            return;
+        };
+        let id = completion.candidates[completion.current].clone();
+        self.name.set_text(event.widthdb(), id.name);
+        self.domain.set_text(event.widthdb(), id.domain);
+    }
+
     pub fn handle_input_event(&mut self, event: &mut InputEvent<'_>, keys: &Keys) -> ConnectResult {
+        if self.error.is_some() {
+            if event.matches(&keys.general.abort) || event.matches(&keys.general.confirm) {
+                self.error = None;
+                return ConnectResult::Handled;
+            }
+            return ConnectResult::Unhandled;
+        }
+
         if event.matches(&keys.general.abort) {
             return ConnectResult::Close;
         }
@@ -55,33 +197,98 @@ impl ConnectState {
             return ConnectResult::Handled;
         }
 
+        if event.matches(&keys.rooms.action.complete) {
+            self.complete(event);
+            return ConnectResult::Handled;
+        }
+
         if event.matches(&keys.general.confirm) {
-            let id = RoomIdentifier {
-                domain: self.domain.text().to_string(),
-                name: self.name.text().to_string(),
-            };
-            if !id.domain.is_empty() && !id.name.is_empty() {
-                return ConnectResult::Connect(id);
+            if self.name.text().is_empty() {
+                return ConnectResult::Handled;
+            }
+            match self.room() {
+                Ok(id) => return ConnectResult::Connect(id),
+                Err(error) => {
+                    self.error = Some(error);
+                    return ConnectResult::Handled;
+                }
             }
         }
 
         let handled = match self.focus {
             Focus::Name => {
-                util::handle_editor_input_event(&mut self.name, event, keys, util::is_room_char)
+                util::handle_editor_input_event(&mut self.name, event, keys, is_url_char)
             }
             Focus::Domain => {
-                util::handle_editor_input_event(&mut self.domain, event, keys, |c| c != '\n')
+                let before = self.domain.text().to_string();
+                let handled =
+                    util::handle_editor_input_event(&mut self.domain, event, keys, |c| c != '\n');
+                // Moving the cursor around shouldn't narrow down the candidates.
+                self.domain_edited |= self.domain.text() != before;
+                handled
             }
         };
 
         if handled {
+            // Editing starts a new completion based on the edited text.
+            self.completion = None;
             return ConnectResult::Handled;
         }
 
         ConnectResult::Unhandled
     }
 
-    pub fn widget(&mut self) -> impl Widget<UiError> + '_ {
+    fn candidates_text(&self, keys: &Keys) -> Styled {
+        let hint_style = Style::new().grey().italic();
+        let (candidates, current) = match &self.completion {
+            Some(completion) => (completion.candidates.clone(), Some(completion.current)),
+            None => (self.candidates(), None),
+        };
+
+        if candidates.is_empty() {
+            return Styled::default();
+        }
+
+        let mut text = Styled::default();
+
+        // Keep the current candidate visible while cycling through them.
+        let skip = current.map_or(0, |c| (c + 1).saturating_sub(SHOWN_CANDIDATES));
+        for (i, id) in candidates
+            .iter()
+            .enumerate()
+            .skip(skip)
+            .take(SHOWN_CANDIDATES)
+        {
+            let style = if current == Some(i) {
+                Style::new().black().on_white()
+            } else {
+                Style::new()
+            };
+            text = text
+                .then_plain("\n")
+                .then(format!("&{}@{}", id.name, id.domain), style);
+        }
+
+        text.then_plain("\n")
+            .then("Complete with ", hint_style)
+            .and_then(key_bindings::format_binding(&keys.rooms.action.complete))
+            .then(".", hint_style)
+    }
+
+    fn error_widget(error: String) -> impl Widget<UiError> {
+        let border_style = Style::new().red().bold();
+        let text = Styled::new_plain("Can't connect to room.")
+            .then_plain("\n\n")
+            .then("Reason:", Style::new().bold())
+            .then_plain(" ")
+            .then_plain(error);
+        Popup::new(Text::new(text), ("Error", border_style)).with_border_style(border_style)
+    }
+
+    pub fn widget(&mut self, keys: &Keys) -> Boxed<'_, UiError> {
+        let candidates = self.candidates_text(keys);
+        let error = self.error.clone();
+
         let room_style = Style::new().bold().blue();
         let domain_style = Style::new().grey();
 
@@ -110,11 +317,16 @@ impl ConnectState {
                 .segment(),
         );
 
-        let inner = Join2::vertical(
+        let inner = Join3::vertical(
             name.segment().with_fixed(true),
             domain.segment().with_fixed(true),
+            Text::new(candidates).segment().with_fixed(true),
         );
 
-        Popup::new(inner, "Connect to")
+        let popup = Popup::new(inner, "Connect to");
+        match error {
+            Some(error) => popup.below(Self::error_widget(error)).boxed(),
+            None => popup.boxed(),
+        }
     }
 }
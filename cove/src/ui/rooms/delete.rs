@@ -1,15 +1,26 @@
 use cove_config::Keys;
 use cove_input::InputEvent;
 use crossterm::style::Stylize;
+use jiff::Zoned;
 use toss::widgets::{EditorState, Empty, Join2, Text};
 use toss::{Style, Styled, Widget, WidgetExt};
 
 use crate::ui::widgets::Popup;
-use crate::ui::{util, UiError};
+use crate::ui::{key_bindings, util, UiError};
 use crate::vault::RoomIdentifier;
 
+/// What would be lost by deleting a room.
+pub struct DeleteSummary {
+    pub msg_count: usize,
+    pub time_range: Option<(Zoned, Zoned)>,
+    pub content_size: usize,
+}
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
 pub struct DeleteState {
     id: RoomIdentifier,
+    summary: DeleteSummary,
     name: EditorState,
 }
 
@@ -21,9 +32,10 @@ pub enum DeleteResult {
 }
 
 impl DeleteState {
-    pub fn new(id: RoomIdentifier) -> Self {
+    pub fn new(id: RoomIdentifier, summary: DeleteSummary) -> Self {
         Self {
             id,
+            summary,
             name: EditorState::new(),
         }
     }
@@ -44,7 +56,29 @@ impl DeleteState {
         DeleteResult::Unhandled
     }
 
-    pub fn widget(&mut self) -> impl Widget<UiError> + '_ {
+    fn summary_text(&self) -> Styled {
+        let value_style = Style::new().bold();
+        let msgs = match self.summary.msg_count {
+            1 => "1 message".to_string(),
+            n => format!("{n} messages"),
+        };
+
+        let mut text = Styled::new_plain("The room contains ").then(msgs, value_style);
+
+        if let Some((oldest, newest)) = &self.summary.time_range {
+            text = text
+                .then_plain(" from ")
+                .then(oldest.strftime(DATE_FORMAT).to_string(), value_style)
+                .then_plain(" to ")
+                .then(newest.strftime(DATE_FORMAT).to_string(), value_style);
+        }
+
+        text.then_plain(", taking up roughly ")
+            .then(util::format_size(self.summary.content_size), value_style)
+            .then_plain(".")
+    }
+
+    pub fn widget(&mut self, keys: &Keys) -> impl Widget<UiError> + '_ {
         let warn_style = Style::new().bold().red();
         let room_style = Style::new().bold().blue();
         let text = Styled::new_plain("Are you sure you want to delete ")
@@ -53,12 +87,16 @@ impl DeleteState {
             .then_plain(" on the ")
             .then(&self.id.domain, Style::new().grey())
             .then_plain(" server?\n\n")
+            .and_then(self.summary_text())
+            .then_plain("\n\n")
             .then_plain("This will delete the entire room history from your vault. ")
             .then_plain("To shrink your vault afterwards, run ")
             .then("cove gc", Style::new().italic().grey())
             .then_plain(".\n\n")
             .then_plain("To confirm the deletion, ")
-            .then_plain("enter the full name of the room and press enter:");
+            .then_plain("enter the full name of the room and press ")
+            .and_then(key_bindings::format_binding(&keys.general.confirm))
+            .then_plain(":");
 
         let inner = Join2::vertical(
             // The Join prevents the text from filling up the entire available
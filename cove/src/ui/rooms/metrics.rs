@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use cove_config::Keys;
+use cove_input::InputEvent;
+use crossterm::style::Stylize;
+use toss::widgets::Text;
+use toss::{Style, Styled, Widget};
+
+use crate::euph::Metrics;
+use crate::ui::euph::room::EuphRoom;
+use crate::ui::widgets::Popup;
+use crate::ui::{util, UiError};
+use crate::vault::RoomIdentifier;
+
+pub enum MetricsResult {
+    Close,
+    Unhandled,
+}
+
+fn format_line(name: &str, metrics: &Metrics, name_width: usize) -> String {
+    format!(
+        "{name:<name_width$}  {:>8}  {:>10}  {:>10}  {:>10}",
+        metrics.msgs,
+        util::format_size(metrics.content_bytes),
+        metrics.reconnects(),
+        util::format_duration(metrics.uptime()),
+    )
+}
+
+/// Plain text table of the metrics of all rooms, followed by their sum.
+fn metrics_text(euph_rooms: &HashMap<RoomIdentifier, EuphRoom>) -> String {
+    let mut rooms = euph_rooms
+        .iter()
+        .map(|(id, room)| (format!("&{}@{}", id.name, id.domain), room.metrics()))
+        .collect::<Vec<_>>();
+    rooms.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+    let name_width = rooms
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max("total".len());
+
+    let mut lines = vec![format!(
+        "{:<name_width$}  {:>8}  {:>10}  {:>10}  {:>10}",
+        "room", "messages", "content", "reconnects", "uptime"
+    )];
+    for (name, metrics) in &rooms {
+        lines.push(format_line(name, metrics, name_width));
+    }
+    lines.push(format!(
+        "{:<name_width$}  {:>8}  {:>10}  {:>10}",
+        "total",
+        rooms.iter().map(|(_, m)| m.msgs).sum::<usize>(),
+        util::format_size(rooms.iter().map(|(_, m)| m.content_bytes).sum()),
+        rooms.iter().map(|(_, m)| m.reconnects()).sum::<usize>(),
+    ));
+
+    lines.join("\n")
+}
+
+pub fn widget(euph_rooms: &HashMap<RoomIdentifier, EuphRoom>) -> impl Widget<UiError> {
+    let text = Styled::new_plain(metrics_text(euph_rooms))
+        .then_plain("\n\n")
+        .then(
+            "Counters cover this session only and are not persisted.",
+            Style::new().grey().italic(),
+        );
+    Popup::new(Text::new(text), "Connection metrics")
+}
+
+pub fn handle_input_event(event: &mut InputEvent<'_>, keys: &Keys) -> MetricsResult {
+    if event.matches(&keys.general.abort) || event.matches(&keys.general.confirm) {
+        return MetricsResult::Close;
+    }
+
+    MetricsResult::Unhandled
+}
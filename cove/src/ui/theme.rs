@@ -0,0 +1,123 @@
+//! Styles that can be switched at runtime.
+
+use cove_config::Config;
+use crossterm::style::{Color, Stylize};
+use toss::Style;
+
+use crate::euph::NickColors;
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub name: String,
+    /// Message contents.
+    pub text: Style,
+    /// Saturation of nick colors in the range `[0, 1]`.
+    pub nick_saturation: f32,
+    /// Lightness of nick colors in the range `[0, 1]`.
+    pub nick_lightness: f32,
+    /// The message under the cursor.
+    pub cursor: Style,
+    /// Popup borders.
+    pub border: Style,
+    /// Unseen message counts in the room list.
+    pub unseen: Style,
+}
+
+/// Parse an optional theme color. Colors were already checked when loading
+/// the config.
+fn parse_color(color: &Option<String>) -> Option<Color> {
+    cove_config::parse_color(color.as_ref()?)
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            name: "dark".to_string(),
+            text: Style::new(),
+            nick_saturation: 1.0,
+            nick_lightness: 0.72,
+            cursor: Style::new().black().on_white(),
+            border: Style::new(),
+            unseen: Style::new().bold().green(),
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            name: "light".to_string(),
+            text: Style::new(),
+            nick_saturation: 1.0,
+            nick_lightness: 0.35,
+            cursor: Style::new().white().on_black(),
+            border: Style::new(),
+            unseen: Style::new().bold().dark_green(),
+        }
+    }
+
+    fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            _ => None,
+        }
+    }
+
+    fn from_config(name: &str, theme: &cove_config::Theme) -> Self {
+        let base = theme.base.as_deref().and_then(Self::builtin);
+        let mut result = base.unwrap_or_else(Self::dark);
+        result.name = name.to_string();
+
+        if let Some(color) = parse_color(&theme.text) {
+            result.text = result.text.with(color);
+        }
+        if let Some(saturation) = theme.nick_saturation {
+            result.nick_saturation = saturation.min(100) as f32 / 100.0;
+        }
+        if let Some(lightness) = theme.nick_lightness {
+            result.nick_lightness = lightness.min(100) as f32 / 100.0;
+        }
+        if let Some(color) = parse_color(&theme.cursor_fg) {
+            result.cursor = result.cursor.with(color);
+        }
+        if let Some(color) = parse_color(&theme.cursor_bg) {
+            result.cursor = result.cursor.on(color);
+        }
+        if let Some(color) = parse_color(&theme.border) {
+            result.border = result.border.with(color);
+        }
+        if let Some(color) = parse_color(&theme.unseen) {
+            result.unseen = result.unseen.with(color);
+        }
+
+        result
+    }
+
+    /// All themes in the order they are cycled through, starting with the
+    /// theme selected in the config.
+    ///
+    /// Themes defined in the config replace built-in themes of the same name.
+    pub fn load_all(config: &Config) -> Vec<Self> {
+        let mut themes = vec![Self::dark(), Self::light()];
+        let mut names = config.themes.keys().collect::<Vec<_>>();
+        names.sort_unstable();
+        for name in names {
+            let theme = Self::from_config(name, &config.themes[name]);
+            match themes.iter_mut().find(|t| t.name == *name) {
+                Some(existing) => *existing = theme,
+                None => themes.push(theme),
+            }
+        }
+
+        if let Some(selected) = &config.theme {
+            if let Some(i) = themes.iter().position(|t| t.name == *selected) {
+                themes.rotate_left(i);
+            }
+        }
+
+        themes
+    }
+
+    pub fn apply_to_nick_colors(&self, nick_colors: &mut NickColors) {
+        nick_colors.set_palette(self.nick_saturation, self.nick_lightness);
+    }
+}
@@ -1,6 +1,9 @@
+use std::time::Duration;
+
 use cove_config::Keys;
 use cove_input::InputEvent;
 use crossterm::event::{KeyCode, KeyModifiers};
+use log::warn;
 use toss::widgets::EditorState;
 
 use super::widgets::ListState;
@@ -10,6 +13,37 @@ pub fn is_room_char(c: char) -> bool {
     c.is_ascii_alphanumeric() || c == '_'
 }
 
+/// Format a byte count in human-readable binary units.
+pub fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Format a duration as hours, minutes and seconds, omitting leading zero
+/// components.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{h}h {m}m {s}s")
+    } else if m > 0 {
+        format!("{m}m {s}s")
+    } else {
+        format!("{s}s")
+    }
+}
+
 //////////
 // List //
 //////////
@@ -79,9 +113,13 @@ fn edit_externally(
     event: &mut InputEvent<'_>,
     char_filter: impl Fn(char) -> bool,
 ) {
-    let Ok(text) = event.prompt(editor.text()) else {
-        // Something went wrong during editing, let's abort the edit.
-        return;
+    let text = match event.prompt(editor.text()) {
+        Ok(text) => text,
+        Err(err) => {
+            // Something went wrong during editing, let's abort the edit.
+            warn!("Failed to edit message externally: {err}");
+            return;
+        }
     };
 
     if text.trim().is_empty() {
@@ -239,6 +239,24 @@ impl<Id: Clone + Eq> ListState<Id> {
             })
     }
 
+    /// Move the cursor to the next row after the cursor whose id matches the
+    /// predicate, wrapping around at the end of the list.
+    pub fn move_cursor_to_next_matching(&mut self, predicate: impl Fn(&Id) -> bool) {
+        let start = self.cursor.as_ref().map(|c| c.idx + 1).unwrap_or(0);
+        let len = self.last_rows.len();
+        let new_cursor =
+            (start..len)
+                .chain(0..start.min(len))
+                .find_map(|i| match &self.last_rows[i] {
+                    Some(id) if predicate(id) => Some(Cursor::new(id.clone(), i)),
+                    _ => None,
+                });
+
+        if let Some(new_cursor) = new_cursor {
+            self.move_cursor_to(new_cursor);
+        }
+    }
+
     fn fix_cursor(&mut self) {
         let new_cursor = if let Some(cursor) = &self.cursor {
             self.selectable_of_id(&cursor.id)
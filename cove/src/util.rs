@@ -1,5 +1,6 @@
 use std::convert::Infallible;
 use std::env;
+use std::time::Duration;
 
 use jiff::tz::TimeZone;
 
@@ -24,19 +25,28 @@ impl<T> InfallibleExt for Result<T, Infallible> {
 /// environment variable uses.
 ///
 /// If no `TZ` environment variable could be found and no string is provided,
-/// the system local time (or UTC on Windows) is used.
-pub fn load_time_zone(tz_string: Option<&str>) -> Result<TimeZone, jiff::Error> {
+/// the system local time is used. If the system local time can't be
+/// determined (e.g. on Windows), the fallback is used, or UTC if there is no
+/// fallback. The fallback is always loaded so mistakes in it are noticed early.
+pub fn load_time_zone(
+    tz_string: Option<&str>,
+    fallback: Option<&str>,
+) -> Result<TimeZone, jiff::Error> {
+    let fallback = match fallback {
+        Some(fallback) => parse_time_zone(fallback)?,
+        None => TimeZone::UTC,
+    };
+
     let env_string = env::var("TZ").ok();
     let tz_string = env_string.as_ref().map(|s| s as &str).or(tz_string);
 
-    let Some(tz_string) = tz_string else {
-        return Ok(TimeZone::system());
-    };
-
-    if tz_string == "localtime" {
-        return Ok(TimeZone::system());
+    match tz_string {
+        None | Some("localtime") => Ok(TimeZone::try_system().unwrap_or(fallback)),
+        Some(tz_string) => parse_time_zone(tz_string),
     }
+}
 
+fn parse_time_zone(tz_string: &str) -> Result<TimeZone, jiff::Error> {
     if let Some(tz_string) = tz_string.strip_prefix(':') {
         return TimeZone::get(tz_string);
     }
@@ -51,6 +61,37 @@ pub fn load_time_zone(tz_string: Option<&str>) -> Result<TimeZone, jiff::Error>
     TimeZone::get(tz_string)
 }
 
+/// Parse a duration like `90d`, `12h` or `2w`, consisting of a whole number
+/// followed by one of the units `s`, `m`, `h`, `d` or `w`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let unit_start = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("missing unit in {s:?}, expected one of s, m, h, d, w"))?;
+    let (amount, unit) = s.split_at(unit_start);
+
+    let amount = amount
+        .parse::<u64>()
+        .map_err(|_| format!("invalid amount in {s:?}"))?;
+    let seconds_per_unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        _ => {
+            return Err(format!(
+                "unknown unit {unit:?}, expected one of s, m, h, d, w"
+            ))
+        }
+    };
+
+    let seconds = amount
+        .checked_mul(seconds_per_unit)
+        .ok_or_else(|| format!("duration {s:?} is too long"))?;
+    Ok(Duration::from_secs(seconds))
+}
+
 pub fn caesar(text: &str, by: i8) -> String {
     let by = by.rem_euclid(26) as u8;
     text.chars()
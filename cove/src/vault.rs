@@ -1,20 +1,29 @@
 mod euph;
 mod migrate;
 mod prepare;
+mod timing;
 
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
 
-use rusqlite::Connection;
+use cove_config::Config;
+use rusqlite::{Connection, OpenFlags};
 use vault::tokio::TokioVault;
 use vault::Action;
 
-pub use self::euph::{EuphRoomVault, EuphVault, RoomIdentifier};
+pub use self::euph::{EuphRoomVault, EuphVault, RoomIdentifier, RoomStats};
+pub use self::migrate::MIGRATIONS;
+use self::timing::{Timed, Timings};
 
 #[derive(Debug, Clone)]
 pub struct Vault {
     tokio_vault: TokioVault,
     ephemeral: bool,
+    bulk_backfill: bool,
+    max_tree_size: usize,
+    /// Only present if vault action timing is enabled.
+    timings: Option<Arc<Timings>>,
 }
 
 struct GcAction;
@@ -24,7 +33,33 @@ impl Action for GcAction {
     type Error = rusqlite::Error;
 
     fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
-        conn.execute_batch("ANALYZE; VACUUM;")
+        // The search index refers to messages by their rowid, which may change
+        // when vacuuming, so it needs to be rebuilt afterwards.
+        conn.execute_batch(
+            "
+            ANALYZE;
+            VACUUM;
+            INSERT INTO euph_msgs_fts (euph_msgs_fts) VALUES ('rebuild');
+            ",
+        )
+    }
+}
+
+struct SizeAction;
+
+impl Action for SizeAction {
+    type Output = usize;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.query_row(
+            "
+            SELECT page_count * page_size
+            FROM pragma_page_count(), pragma_page_size()
+            ",
+            [],
+            |row| row.get(0),
+        )
     }
 }
 
@@ -33,37 +68,144 @@ impl Vault {
         self.ephemeral
     }
 
+    pub fn bulk_backfill(&self) -> bool {
+        self.bulk_backfill
+    }
+
+    /// Maximum number of messages per tree when displaying trees.
+    pub fn max_tree_size(&self) -> usize {
+        self.max_tree_size
+    }
+
     pub async fn close(&self) {
         self.tokio_vault.stop().await;
+        if let Some(timings) = &self.timings {
+            timings.print_summary();
+        }
+    }
+
+    /// Run an action, measuring how long it takes if timing is enabled.
+    async fn execute<A>(
+        &self,
+        name: &'static str,
+        action: A,
+    ) -> Result<A::Output, vault::tokio::Error<rusqlite::Error>>
+    where
+        A: Action<Error = rusqlite::Error> + Send + 'static,
+        A::Output: Send,
+    {
+        match &self.timings {
+            Some(timings) => {
+                let timings = timings.clone();
+                let action = Timed {
+                    name,
+                    action,
+                    timings,
+                };
+                self.tokio_vault.execute(action).await
+            }
+            None => self.tokio_vault.execute(action).await,
+        }
     }
 
     pub async fn gc(&self) -> Result<(), vault::tokio::Error<rusqlite::Error>> {
         self.tokio_vault.execute(GcAction).await
     }
 
+    /// Size of the database in bytes.
+    pub async fn size(&self) -> Result<usize, vault::tokio::Error<rusqlite::Error>> {
+        self.tokio_vault.execute(SizeAction).await
+    }
+
     pub fn euph(&self) -> EuphVault {
         EuphVault::new(self.clone())
     }
 }
 
-fn launch_from_connection(conn: Connection, ephemeral: bool) -> rusqlite::Result<Vault> {
+/// Schema version of a vault, which is the number of migrations applied to it.
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Problems found by sqlite's integrity check, or `["ok"]` if there are none.
+pub fn integrity_check(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    conn.prepare("PRAGMA integrity_check")?
+        .query_map([], |row| row.get(0))?
+        .collect()
+}
+
+/// Refuse to open vaults written by a newer version of cove. Their schema is
+/// unknown, so queries would fail in confusing ways or even damage the data.
+fn check_schema_version(conn: &Connection) -> rusqlite::Result<()> {
+    let version = schema_version(conn)?;
+    let supported = MIGRATIONS.len();
+    if version <= supported {
+        return Ok(());
+    }
+
+    Err(rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+        Some(format!(
+            "vault schema version {version} is newer than version {supported}, \
+             the newest version this cove supports (was the vault created by a newer cove?)"
+        )),
+    ))
+}
+
+/// Read-only vaults can't be migrated, so their schema must match exactly.
+fn check_schema_version_read_only(conn: &Connection) -> rusqlite::Result<()> {
+    check_schema_version(conn)?;
+    let version = schema_version(conn)?;
+    let supported = MIGRATIONS.len();
+    if version == supported {
+        return Ok(());
+    }
+
+    Err(rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+        Some(format!(
+            "vault schema version {version} is older than version {supported}, \
+             which this cove expects (start cove normally once to migrate the vault)"
+        )),
+    ))
+}
+
+fn vault_from_tokio_vault(tokio_vault: TokioVault, config: &Config, ephemeral: bool) -> Vault {
+    Vault {
+        tokio_vault,
+        ephemeral,
+        bulk_backfill: config.bulk_backfill && !ephemeral,
+        max_tree_size: config.max_tree_size(),
+        timings: config
+            .vault_timing
+            .then(|| Arc::new(Timings::new(config.vault_slow_action_threshold()))),
+    }
+}
+
+fn launch_from_connection(
+    conn: Connection,
+    config: &Config,
+    ephemeral: bool,
+) -> rusqlite::Result<Vault> {
     conn.pragma_update(None, "foreign_keys", true)?;
     conn.pragma_update(None, "trusted_schema", false)?;
+    check_schema_version(&conn)?;
 
     let tokio_vault = TokioVault::launch_and_prepare(conn, &migrate::MIGRATIONS, prepare::prepare)?;
-    Ok(Vault {
-        tokio_vault,
-        ephemeral,
-    })
+    Ok(vault_from_tokio_vault(tokio_vault, config, ephemeral))
 }
 
-pub fn launch(path: &Path) -> rusqlite::Result<Vault> {
+pub fn launch(path: &Path, config: &Config) -> rusqlite::Result<Vault> {
     // If this fails, rusqlite will complain about not being able to open the db
     // file, which saves me from adding a separate vault error type.
     let _ = fs::create_dir_all(path.parent().expect("path to file"));
 
     let conn = Connection::open(path)?;
 
+    // If another instance holds the lock, sqlite retries with backoff until the
+    // timeout expires instead of failing immediately.
+    conn.busy_timeout(config.vault_busy_timeout())?;
+
     // Setting locking mode before journal mode so no shared memory files
     // (*-shm) need to be created by sqlite. Apparently, setting the journal
     // mode is also enough to immediately acquire the exclusive lock even if the
@@ -72,10 +214,99 @@ pub fn launch(path: &Path) -> rusqlite::Result<Vault> {
     conn.pragma_update(None, "locking_mode", "exclusive")?;
     conn.pragma_update(None, "journal_mode", "wal")?;
 
-    launch_from_connection(conn, false)
+    launch_from_connection(conn, config, false)
+}
+
+/// Open a plain connection to an existing vault without modifying it. No
+/// migrations are run and the vault is not prepared.
+pub fn open_read_only(path: &Path, config: &Config) -> rusqlite::Result<Connection> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY
+        | OpenFlags::SQLITE_OPEN_URI
+        | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let conn = Connection::open_with_flags(path, flags)?;
+    conn.busy_timeout(config.vault_busy_timeout())?;
+    Ok(conn)
+}
+
+/// Open an existing vault without modifying it.
+///
+/// Since migrations would write to the vault, they are skipped, and vaults
+/// with a different schema version are refused instead. If `prepare` is false,
+/// the temporary tables created while preparing are missing, so only actions
+/// that don't rely on them may be used. Preparing only writes to temporary
+/// tables, which leaves the vault itself untouched.
+///
+/// Other instances of cove may not use the vault at the same time.
+pub fn launch_read_only(path: &Path, config: &Config, prepare: bool) -> rusqlite::Result<Vault> {
+    let mut conn = open_read_only(path, config)?;
+    conn.pragma_update(None, "trusted_schema", false)?;
+    check_schema_version_read_only(&conn)?;
+    if prepare {
+        prepare::prepare(&mut conn)?;
+    }
+
+    let tokio_vault = TokioVault::launch(conn);
+    Ok(vault_from_tokio_vault(tokio_vault, config, false))
 }
 
-pub fn launch_in_memory() -> rusqlite::Result<Vault> {
+/// Whether an error was caused by the vault being locked by another instance.
+pub fn is_busy(err: &rusqlite::Error) -> bool {
+    matches!(
+        err.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+    )
+}
+
+pub fn launch_in_memory(config: &Config) -> rusqlite::Result<Vault> {
     let conn = Connection::open_in_memory()?;
-    launch_from_connection(conn, true)
+    launch_from_connection(conn, config, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn config(busy_timeout: usize) -> Config {
+        Config {
+            vault_busy_timeout: Some(busy_timeout),
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn second_instance_is_busy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.db");
+        let config = config(100);
+
+        let first = launch(&path, &config).unwrap();
+        let err = launch(&path, &config).unwrap_err();
+        assert!(is_busy(&err), "unexpected error: {err}");
+
+        first.close().await;
+        let second = launch(&path, &config).unwrap();
+        second.close().await;
+    }
+
+    #[tokio::test]
+    async fn busy_timeout_waits_for_other_instance() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.db");
+        let first = launch(&path, &config(5000)).unwrap();
+        let closing = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            first.close().await;
+        });
+
+        // The second launch blocks in sqlite's busy handler until the first
+        // instance has released its lock.
+        let second = tokio::task::spawn_blocking(move || launch(&path, &config(5000)))
+            .await
+            .unwrap()
+            .unwrap();
+        closing.await.unwrap();
+        second.close().await;
+    }
 }
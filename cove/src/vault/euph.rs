@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::str::FromStr;
 use std::{fmt, mem};
 
@@ -8,7 +9,7 @@ use rusqlite::types::{FromSql, FromSqlError, ToSqlOutput, Value, ValueRef};
 use rusqlite::{named_params, params, Connection, OptionalExtension, Row, ToSql, Transaction};
 use vault::Action;
 
-use crate::euph::SmallMessage;
+use crate::euph::{self, Highlight, SmallMessage};
 use crate::store::{MsgStore, Path, Tree};
 
 /// Wrapper for [`Snowflake`] that implements useful rusqlite traits.
@@ -61,6 +62,16 @@ impl RoomIdentifier {
     }
 }
 
+/// Summary of the messages stored for a room.
+#[derive(Debug, Clone)]
+pub struct RoomStats {
+    pub room: RoomIdentifier,
+    pub msgs: usize,
+    pub unseen: usize,
+    /// Times of the oldest and newest message, if there are any messages.
+    pub time_range: Option<(Time, Time)>,
+}
+
 ///////////////
 // EuphVault //
 ///////////////
@@ -100,7 +111,7 @@ macro_rules! euph_vault_actions {
         impl EuphVault {
             $(
                 pub async fn $fn(&self, $( $arg: $arg_ty, )* ) -> Result<$res, vault::tokio::Error<rusqlite::Error>> {
-                    self.vault.tokio_vault.execute($struct { $( $arg, )* }).await
+                    self.vault.execute(stringify!($fn), $struct { $( $arg, )* }).await
                 }
             )*
         }
@@ -112,7 +123,9 @@ euph_vault_actions! {
     SetCookies : set_cookies(domain: String, cookies: CookieJar) -> ();
     ClearCookies : clear_cookies(domain: Option<String>) -> ();
     GetRooms : rooms() -> Vec<RoomIdentifier>;
-    GetTotalUnseenMsgsCount : total_unseen_msgs_count() -> usize;
+    GetRecentRooms : recent_rooms(amount: usize) -> Vec<RoomIdentifier>;
+    GetTotalUnseenMsgsCount : total_unseen_msgs_count(excluded: HashSet<RoomIdentifier>) -> usize;
+    GetRoomStats : room_stats() -> Vec<RoomStats>;
 }
 
 impl Action for GetCookies {
@@ -210,18 +223,94 @@ impl Action for GetRooms {
     }
 }
 
+impl Action for GetRecentRooms {
+    type Output = Vec<RoomIdentifier>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.prepare(
+            "
+            SELECT domain, room
+            FROM euph_rooms
+            ORDER BY last_joined DESC
+            LIMIT ?
+            ",
+        )?
+        .query_map([self.amount], |row| {
+            Ok(RoomIdentifier {
+                domain: row.get(0)?,
+                name: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()
+    }
+}
+
 impl Action for GetTotalUnseenMsgsCount {
     type Output = usize;
     type Error = rusqlite::Error;
 
     fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let mut query = conn.prepare(
+            "
+            SELECT domain, room, amount
+            FROM euph_unseen_counts
+            ",
+        )?;
+        let mut rows = query.query([])?;
+
+        let mut total = 0;
+        while let Some(row) = rows.next()? {
+            let room = RoomIdentifier {
+                domain: row.get(0)?,
+                name: row.get(1)?,
+            };
+            if !self.excluded.contains(&room) {
+                total += row.get::<_, usize>(2)?;
+            }
+        }
+        Ok(total)
+    }
+}
+
+impl Action for GetRoomStats {
+    type Output = Vec<RoomStats>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        // Unseen messages are counted directly instead of using the cached
+        // counts because read-only vaults are not prepared.
         conn.prepare(
             "
-                SELECT COALESCE(SUM(amount), 0)
-                FROM euph_unseen_counts
+                SELECT
+                    r.domain,
+                    r.room,
+                    COUNT(m.id),
+                    MIN(m.time),
+                    MAX(m.time),
+                    COALESCE(SUM(NOT m.seen), 0)
+                FROM euph_rooms AS r
+                LEFT JOIN euph_msgs AS m
+                    ON m.domain = r.domain
+                    AND m.room = r.room
+                GROUP BY r.domain, r.room
+                ORDER BY COUNT(m.id) DESC, r.domain, r.room
                 ",
         )?
-        .query_row([], |row| row.get(0))
+        .query_map([], |row| {
+            let oldest = row.get::<_, Option<WTime>>(3)?;
+            let newest = row.get::<_, Option<WTime>>(4)?;
+            Ok(RoomStats {
+                room: RoomIdentifier {
+                    domain: row.get(0)?,
+                    name: row.get(1)?,
+                },
+                msgs: row.get(2)?,
+                unseen: row.get(5)?,
+                time_range: oldest.zip(newest).map(|(o, n)| (o.0, n.0)),
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()
     }
 }
 
@@ -259,7 +348,7 @@ macro_rules! euph_room_vault_actions {
         impl EuphRoomVault {
             $(
                 pub async fn $fn(&self, $( $arg: $arg_ty, )* ) -> Result<$res, vault::tokio::Error<rusqlite::Error>> {
-                    self.vault.vault.tokio_vault.execute($struct {
+                    self.vault.vault.execute(stringify!($fn), $struct {
                         room: self.room.clone(),
                         $( $arg, )*
                     }).await
@@ -276,12 +365,13 @@ euph_room_vault_actions! {
 
     // Message
     AddMsg : add_msg(msg: Box<Message>, prev_msg_id: Option<MessageId>, own_user_id: Option<UserId>) -> ();
-    AddMsgs : add_msgs(msgs: Vec<Message>, next_msg_id: Option<MessageId>, own_user_id: Option<UserId>) -> ();
+    AddMsgs : add_msgs(msgs: Vec<Message>, next_msg_id: Option<MessageId>, own_user_id: Option<UserId>, bulk: bool) -> ();
     GetLastSpan : last_span() -> Option<(Option<MessageId>, Option<MessageId>)>;
     GetPath : path(id: MessageId) -> Path<MessageId>;
     GetMsg : msg(id: MessageId) -> Option<SmallMessage>;
     GetFullMsg : full_msg(id: MessageId) -> Option<Message>;
-    GetTree : tree(root_id: MessageId) -> Tree<SmallMessage>;
+    GetTree : tree(root_id: MessageId, limit: Option<usize>) -> Tree<SmallMessage>;
+    GetSubtreeFullMsgs : subtree_full_msgs(root_id: MessageId) -> Vec<Message>;
     GetFirstRootId : first_root_id() -> Option<MessageId>;
     GetLastRootId : last_root_id() -> Option<MessageId>;
     GetPrevRootId : prev_root_id(root_id: MessageId) -> Option<MessageId>;
@@ -292,12 +382,29 @@ euph_room_vault_actions! {
     GetNewerMsgId : newer_msg_id(id: MessageId) -> Option<MessageId>;
     GetOldestUnseenMsgId : oldest_unseen_msg_id() -> Option<MessageId>;
     GetNewestUnseenMsgId : newest_unseen_msg_id() -> Option<MessageId>;
+    GetNewestSeenMsgId : newest_seen_msg_id() -> Option<MessageId>;
     GetOlderUnseenMsgId : older_unseen_msg_id(id: MessageId) -> Option<MessageId>;
     GetNewerUnseenMsgId : newer_unseen_msg_id(id: MessageId) -> Option<MessageId>;
     GetUnseenMsgsCount : unseen_msgs_count() -> usize;
+    GetOlderUnseenMsgsCount : older_unseen_msgs_count(id: MessageId) -> usize;
+    GetUnseenMentionsCount : unseen_mentions_count(nick: Option<String>, highlights: Vec<Highlight>) -> usize;
+    GetMsgCount : msg_count() -> usize;
+    CountExistingMsgs : count_existing_msgs(ids: Vec<MessageId>) -> usize;
+    GetTimeRange : time_range() -> Option<(Time, Time)>;
+    GetContentSize : content_size() -> usize;
     SetSeen : set_seen(id: MessageId, seen: bool) -> ();
     SetOlderSeen : set_older_seen(id: MessageId, seen: bool) -> ();
+    MarkAllSeen : mark_all_seen() -> ();
+    GetFoldedIds : folded_ids() -> HashSet<MessageId>;
+    SetFolded : set_folded(id: MessageId, folded: bool) -> ();
+    FoldAll : fold_all() -> ();
+    UnfoldAll : unfold_all() -> ();
     GetChunkAfter : chunk_after(id: Option<MessageId>, amount: usize) -> Vec<Message>;
+    GetChunkAfterInRange : chunk_after_in_range(id: Option<MessageId>, amount: usize, since: Option<Time>, until: Option<Time>) -> Vec<Message>;
+    SearchMsgs : search_msgs(query: String, limit: usize) -> Vec<MessageId>;
+    GetReadMark : read_mark() -> Option<MessageId>;
+    SetReadMark : set_read_mark(id: Option<MessageId>) -> ();
+    DeleteOlderMsgs : delete_older_msgs(time: Time) -> usize;
 }
 
 impl Action for Join {
@@ -339,6 +446,12 @@ impl Action for Delete {
     }
 }
 
+/// Insert messages or update them if they already exist.
+///
+/// New messages are marked as seen if they were sent by the user or before the
+/// room was first joined. Existing messages keep their seen status, so
+/// downloading a message again never marks it as seen or unseen. This is why
+/// `seen` is not part of the `DO UPDATE` clause.
 fn insert_msgs(
     tx: &Transaction<'_>,
     room: &RoomIdentifier,
@@ -457,6 +570,12 @@ fn add_span(
                 // Since spans are sorted lexicographically, we know that
                 // cur_span.0 <= span.0, which means that span starts inside
                 // of cur_span.
+                //
+                // Spans are inclusive on both ends, so this also merges spans
+                // that only touch (i.e. share a boundary message) and
+                // single-message spans where start equals end. Spans whose
+                // boundaries are merely close together are kept separate
+                // since there may be unknown messages between them.
                 cur_span.1 = cur_span.1.max(span.1);
             } else {
                 // Since span doesn't overlap cur_span, we know that no
@@ -523,6 +642,25 @@ impl Action for AddMsgs {
     type Error = rusqlite::Error;
 
     fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        if !self.bulk {
+            return self.add(conn);
+        }
+
+        // In WAL mode, relaxing the synchronous setting may lose the most
+        // recent transactions on power loss, but never corrupts the database.
+        // Since messages and their span are inserted in the same transaction,
+        // the spans stay consistent with the messages even then.
+        // https://sqlite.org/pragma.html#pragma_synchronous
+        let synchronous: i64 = conn.pragma_query_value(None, "synchronous", |row| row.get(0))?;
+        conn.pragma_update(None, "synchronous", "normal")?;
+        let result = self.add(conn);
+        conn.pragma_update(None, "synchronous", synchronous)?;
+        result
+    }
+}
+
+impl AddMsgs {
+    fn add(self, conn: &mut Connection) -> rusqlite::Result<()> {
         let tx = conn.transaction()?;
 
         if self.msgs.is_empty() {
@@ -608,7 +746,7 @@ impl Action for GetMsg {
         let msg = conn
             .query_row(
                 "
-                SELECT id, parent, time, name, content, seen
+                SELECT id, parent, time, name, user_id, content, seen
                 FROM euph_msgs
                 WHERE domain = ?
                 AND room = ?
@@ -621,8 +759,9 @@ impl Action for GetMsg {
                         parent: row.get::<_, Option<WSnowflake>>(1)?.map(|s| MessageId(s.0)),
                         time: row.get::<_, WTime>(2)?.0,
                         nick: row.get(3)?,
-                        content: row.get(4)?,
-                        seen: row.get(5)?,
+                        user_id: UserId(row.get(4)?),
+                        content: row.get(5)?,
+                        seen: row.get(6)?,
                     })
                 },
             )
@@ -686,7 +825,13 @@ impl Action for GetTree {
     type Error = rusqlite::Error;
 
     fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
-        let msgs = conn
+        // The limit applies to the recursive query itself so that pathological
+        // trees are never fully loaded. The row of the root id (which may not
+        // exist as a message) counts towards the limit, and one additional
+        // message is requested to detect whether the tree was truncated. A
+        // negative limit means no limit in sqlite.
+        let limit = self.limit.map(|l| l as i64 + 2).unwrap_or(-1);
+        let mut msgs: Vec<SmallMessage> = conn
             .prepare(
                 "
                 WITH RECURSIVE
@@ -699,28 +844,87 @@ impl Action for GetTree {
                         ON tree.domain = euph_msgs.domain
                         AND tree.room = euph_msgs.room
                         AND tree.id = euph_msgs.parent
+                    LIMIT ?
                 )
-                SELECT id, parent, time, name, content, seen
+                SELECT id, parent, time, name, user_id, content, seen
                 FROM euph_msgs
                 JOIN tree USING (domain, room, id)
                 ORDER BY id ASC
                 ",
             )?
             .query_map(
-                params![self.room.domain, self.room.name, WSnowflake(self.root_id.0)],
+                params![
+                    self.room.domain,
+                    self.room.name,
+                    WSnowflake(self.root_id.0),
+                    limit
+                ],
                 |row| {
                     Ok(SmallMessage {
                         id: MessageId(row.get::<_, WSnowflake>(0)?.0),
                         parent: row.get::<_, Option<WSnowflake>>(1)?.map(|s| MessageId(s.0)),
                         time: row.get::<_, WTime>(2)?.0,
                         nick: row.get(3)?,
-                        content: row.get(4)?,
-                        seen: row.get(5)?,
+                        user_id: UserId(row.get(4)?),
+                        content: row.get(5)?,
+                        seen: row.get(6)?,
                     })
                 },
             )?
             .collect::<rusqlite::Result<_>>()?;
-        Ok(Tree::new(self.root_id, msgs))
+
+        // Replies always have larger ids than their parents, so keeping the
+        // oldest messages results in a connected tree.
+        let truncated = match self.limit {
+            Some(limit) if msgs.len() > limit => {
+                msgs.truncate(limit);
+                true
+            }
+            _ => false,
+        };
+        Ok(Tree::new(self.root_id, msgs).with_truncated(truncated))
+    }
+}
+
+impl Action for GetSubtreeFullMsgs {
+    type Output = Vec<Message>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        // Unlike in GetTree, the recursion starts at the root message itself,
+        // so nothing is returned if the root is not present.
+        let msgs = conn
+            .prepare(
+                "
+                WITH RECURSIVE
+                tree (domain, room, id) AS (
+                    SELECT domain, room, id
+                    FROM euph_msgs
+                    WHERE domain = ?
+                    AND room = ?
+                    AND id = ?
+                UNION
+                    SELECT euph_msgs.domain, euph_msgs.room, euph_msgs.id
+                    FROM euph_msgs
+                    JOIN tree
+                        ON tree.domain = euph_msgs.domain
+                        AND tree.room = euph_msgs.room
+                        AND tree.id = euph_msgs.parent
+                )
+                SELECT
+                    id, parent, previous_edit_id, time, content, encryption_key_id, edited, deleted, truncated,
+                    user_id, name, server_id, server_era, session_id, is_staff, is_manager, client_address, real_client_address
+                FROM euph_msgs
+                JOIN tree USING (domain, room, id)
+                ORDER BY id ASC
+                ",
+            )?
+            .query_map(
+                params![self.room.domain, self.room.name, WSnowflake(self.root_id.0)],
+                row2full_msg,
+            )?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(msgs)
     }
 }
 
@@ -973,6 +1177,31 @@ impl Action for GetNewestUnseenMsgId {
     }
 }
 
+impl Action for GetNewestSeenMsgId {
+    type Output = Option<MessageId>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let msg_id = conn
+            .prepare(
+                "
+                SELECT id
+                FROM euph_msgs
+                WHERE domain = ?
+                AND room = ?
+                AND seen
+                ORDER BY id DESC
+                LIMIT 1
+                ",
+            )?
+            .query_row([&self.room.domain, &self.room.name], |row| {
+                row.get::<_, WSnowflake>(0).map(|s| MessageId(s.0))
+            })
+            .optional()?;
+        Ok(msg_id)
+    }
+}
+
 impl Action for GetOlderUnseenMsgId {
     type Output = Option<MessageId>;
     type Error = rusqlite::Error;
@@ -1048,6 +1277,200 @@ impl Action for GetUnseenMsgsCount {
     }
 }
 
+impl Action for GetOlderUnseenMsgsCount {
+    type Output = usize;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let amount = conn
+            .prepare(
+                "
+                SELECT COUNT(*)
+                FROM euph_msgs
+                WHERE domain = ?
+                AND room = ?
+                AND id <= ?
+                AND NOT seen
+                ",
+            )?
+            .query_row(
+                params![self.room.domain, self.room.name, WSnowflake(self.id.0)],
+                |row| row.get(0),
+            )?;
+        Ok(amount)
+    }
+}
+
+impl Action for GetUnseenMentionsCount {
+    type Output = usize;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let mut query = conn.prepare(
+            "
+            SELECT content
+            FROM euph_msgs
+            WHERE domain = ?
+            AND room = ?
+            AND NOT seen
+            ",
+        )?;
+        let mut rows = query.query(params![self.room.domain, self.room.name])?;
+
+        // Highlight rules are regular expressions, so the messages need to be
+        // matched here instead of in sql.
+        let mut amount = 0;
+        while let Some(row) = rows.next()? {
+            let content = row.get::<_, String>(0)?;
+            let mentioned = self
+                .nick
+                .as_ref()
+                .is_some_and(|nick| euph::mentions(&content, nick));
+            if mentioned || self.highlights.iter().any(|h| h.is_match(&content)) {
+                amount += 1;
+            }
+        }
+        Ok(amount)
+    }
+}
+
+impl Action for GetMsgCount {
+    type Output = usize;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let amount = conn
+            .prepare(
+                "
+                SELECT COUNT(*)
+                FROM euph_msgs
+                WHERE domain = ?
+                AND room = ?
+                ",
+            )?
+            .query_row(params![self.room.domain, self.room.name], |row| row.get(0))?;
+        Ok(amount)
+    }
+}
+
+impl Action for CountExistingMsgs {
+    type Output = usize;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let mut query = conn.prepare(
+            "
+            SELECT EXISTS(
+                SELECT *
+                FROM euph_msgs
+                WHERE domain = ?
+                AND room = ?
+                AND id = ?
+            )
+            ",
+        )?;
+
+        let mut amount = 0;
+        for id in self.ids {
+            let exists = query.query_row(
+                params![self.room.domain, self.room.name, WSnowflake(id.0)],
+                |row| row.get::<_, bool>(0),
+            )?;
+            if exists {
+                amount += 1;
+            }
+        }
+        Ok(amount)
+    }
+}
+
+/// Turn arbitrary text into an fts5 query matching messages that contain all
+/// words of the text in order, with the last word possibly incomplete.
+fn fts_query(text: &str) -> String {
+    format!("\"{}\" *", text.replace('"', "\"\""))
+}
+
+impl Action for SearchMsgs {
+    type Output = Vec<MessageId>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        // Only the newest matches are returned, but from oldest to newest
+        let mut ids = conn
+            .prepare(
+                "
+                SELECT id
+                FROM euph_msgs_fts
+                JOIN euph_msgs ON euph_msgs.rowid = euph_msgs_fts.rowid
+                WHERE euph_msgs_fts MATCH ?
+                AND domain = ?
+                AND room = ?
+                AND deleted IS NULL
+                AND NOT truncated
+                ORDER BY id DESC
+                LIMIT ?
+                ",
+            )?
+            .query_map(
+                params![
+                    fts_query(&self.query),
+                    self.room.domain,
+                    self.room.name,
+                    self.limit
+                ],
+                |row| row.get::<_, WSnowflake>(0).map(|s| MessageId(s.0)),
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        ids.reverse();
+        Ok(ids)
+    }
+}
+
+impl Action for GetTimeRange {
+    type Output = Option<(Time, Time)>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let range = conn
+            .prepare(
+                "
+                SELECT MIN(time), MAX(time)
+                FROM euph_msgs
+                WHERE domain = ?
+                AND room = ?
+                ",
+            )?
+            .query_row(params![self.room.domain, self.room.name], |row| {
+                let oldest = row.get::<_, Option<WTime>>(0)?;
+                let newest = row.get::<_, Option<WTime>>(1)?;
+                Ok(oldest.zip(newest).map(|(o, n)| (o.0, n.0)))
+            })?;
+        Ok(range)
+    }
+}
+
+impl Action for GetContentSize {
+    type Output = usize;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        // This only estimates the size of the message contents, not of the
+        // entire rows or any indices. It's good enough to give users a rough
+        // idea of how much data a room holds.
+        let size = conn
+            .prepare(
+                "
+                SELECT COALESCE(SUM(LENGTH(CAST(content AS BLOB))), 0)
+                FROM euph_msgs
+                WHERE domain = ?
+                AND room = ?
+                ",
+            )?
+            .query_row(params![self.room.domain, self.room.name], |row| row.get(0))?;
+        Ok(size)
+    }
+}
+
 impl Action for SetSeen {
     type Output = ();
     type Error = rusqlite::Error;
@@ -1072,6 +1495,101 @@ impl Action for SetSeen {
     }
 }
 
+impl Action for GetReadMark {
+    type Output = Option<MessageId>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let msg_id = conn
+            .prepare(
+                "
+                SELECT read_mark
+                FROM euph_rooms
+                WHERE domain = ?
+                AND room = ?
+                ",
+            )?
+            .query_row([&self.room.domain, &self.room.name], |row| {
+                row.get::<_, Option<WSnowflake>>(0)
+                    .map(|s| s.map(|s| MessageId(s.0)))
+            })
+            .optional()?
+            .flatten();
+        Ok(msg_id)
+    }
+}
+
+impl Action for SetReadMark {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.execute(
+            "
+            UPDATE euph_rooms
+            SET read_mark = :read_mark
+            WHERE domain = :domain
+            AND room = :room
+            ",
+            named_params! {
+                ":domain": self.room.domain,
+                ":room": self.room.name,
+                ":read_mark": self.id.map(|id| WSnowflake(id.0)),
+            },
+        )?;
+        Ok(())
+    }
+}
+
+impl Action for DeleteOlderMsgs {
+    type Output = usize;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let tx = conn.transaction()?;
+
+        let newest = tx
+            .prepare(
+                "
+                SELECT MAX(id)
+                FROM euph_msgs
+                WHERE domain = ?
+                AND room = ?
+                AND time < ?
+                ",
+            )?
+            .query_row(
+                params![self.room.domain, self.room.name, WTime(self.time)],
+                |row| row.get::<_, Option<WSnowflake>>(0),
+            )?;
+        let Some(newest) = newest else {
+            return Ok(0);
+        };
+
+        // Deleting by id instead of by time keeps the deleted messages a
+        // contiguous range of the room's history, which the spans below rely
+        // on.
+        let deleted = tx.execute(
+            "
+            DELETE FROM euph_msgs
+            WHERE domain = ?
+            AND room = ?
+            AND id <= ?
+            ",
+            params![self.room.domain, self.room.name, newest],
+        )?;
+
+        // Splitting the spans at the deleted range would make the backfill
+        // download the deleted messages right back. Instead, the deleted range
+        // is marked as known, which merges all spans touching it into a single
+        // span reaching back to the start of the room.
+        add_span(&tx, &self.room, None, Some(MessageId(newest.0)))?;
+
+        tx.commit()?;
+        Ok(deleted)
+    }
+}
+
 impl Action for SetOlderSeen {
     type Output = ();
     type Error = rusqlite::Error;
@@ -1097,36 +1615,141 @@ impl Action for SetOlderSeen {
     }
 }
 
-impl Action for GetChunkAfter {
-    type Output = Vec<Message>;
+impl Action for MarkAllSeen {
+    type Output = ();
     type Error = rusqlite::Error;
 
     fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
-        fn row2msg(row: &Row<'_>) -> rusqlite::Result<Message> {
-            Ok(Message {
-                id: MessageId(row.get::<_, WSnowflake>(0)?.0),
-                parent: row.get::<_, Option<WSnowflake>>(1)?.map(|s| MessageId(s.0)),
-                previous_edit_id: row.get::<_, Option<WSnowflake>>(2)?.map(|s| s.0),
-                time: row.get::<_, WTime>(3)?.0,
-                content: row.get(4)?,
-                encryption_key_id: row.get(5)?,
-                edited: row.get::<_, Option<WTime>>(6)?.map(|t| t.0),
-                deleted: row.get::<_, Option<WTime>>(7)?.map(|t| t.0),
-                truncated: row.get(8)?,
-                sender: SessionView {
-                    id: UserId(row.get(9)?),
-                    name: row.get(10)?,
-                    server_id: row.get(11)?,
-                    server_era: row.get(12)?,
-                    session_id: SessionId(row.get(13)?),
-                    is_staff: row.get(14)?,
-                    is_manager: row.get(15)?,
-                    client_address: row.get(16)?,
-                    real_client_address: row.get(17)?,
-                },
-            })
+        conn.execute(
+            "
+            UPDATE euph_msgs
+            SET seen = true
+            WHERE domain = ?
+            AND room = ?
+            AND NOT seen
+            ",
+            params![self.room.domain, self.room.name],
+        )?;
+        Ok(())
+    }
+}
+
+impl Action for GetFoldedIds {
+    type Output = HashSet<MessageId>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let ids = conn
+            .prepare(
+                "
+                SELECT id
+                FROM euph_folded
+                WHERE domain = ?
+                AND room = ?
+                ",
+            )?
+            .query_map([&self.room.domain, &self.room.name], |row| {
+                row.get::<_, WSnowflake>(0).map(|s| MessageId(s.0))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(ids)
+    }
+}
+
+impl Action for SetFolded {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        if self.folded {
+            conn.execute(
+                "
+                INSERT OR IGNORE INTO euph_folded (domain, room, id)
+                VALUES (?, ?, ?)
+                ",
+                params![self.room.domain, self.room.name, WSnowflake(self.id.0)],
+            )?;
+        } else {
+            conn.execute(
+                "
+                DELETE FROM euph_folded
+                WHERE domain = ?
+                AND room = ?
+                AND id = ?
+                ",
+                params![self.room.domain, self.room.name, WSnowflake(self.id.0)],
+            )?;
         }
+        Ok(())
+    }
+}
+
+impl Action for FoldAll {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.execute(
+            "
+            INSERT OR IGNORE INTO euph_folded (domain, room, id)
+            SELECT domain, room, id
+            FROM euph_trees
+            WHERE domain = ?
+            AND room = ?
+            ",
+            [&self.room.domain, &self.room.name],
+        )?;
+        Ok(())
+    }
+}
+
+impl Action for UnfoldAll {
+    type Output = ();
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.execute(
+            "
+            DELETE FROM euph_folded
+            WHERE domain = ?
+            AND room = ?
+            ",
+            [&self.room.domain, &self.room.name],
+        )?;
+        Ok(())
+    }
+}
 
+fn row2full_msg(row: &Row<'_>) -> rusqlite::Result<Message> {
+    Ok(Message {
+        id: MessageId(row.get::<_, WSnowflake>(0)?.0),
+        parent: row.get::<_, Option<WSnowflake>>(1)?.map(|s| MessageId(s.0)),
+        previous_edit_id: row.get::<_, Option<WSnowflake>>(2)?.map(|s| s.0),
+        time: row.get::<_, WTime>(3)?.0,
+        content: row.get(4)?,
+        encryption_key_id: row.get(5)?,
+        edited: row.get::<_, Option<WTime>>(6)?.map(|t| t.0),
+        deleted: row.get::<_, Option<WTime>>(7)?.map(|t| t.0),
+        truncated: row.get(8)?,
+        sender: SessionView {
+            id: UserId(row.get(9)?),
+            name: row.get(10)?,
+            server_id: row.get(11)?,
+            server_era: row.get(12)?,
+            session_id: SessionId(row.get(13)?),
+            is_staff: row.get(14)?,
+            is_manager: row.get(15)?,
+            client_address: row.get(16)?,
+            real_client_address: row.get(17)?,
+        },
+    })
+}
+
+impl Action for GetChunkAfter {
+    type Output = Vec<Message>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
         let messages = if let Some(id) = self.id {
             conn.prepare("
                 SELECT
@@ -1139,7 +1762,7 @@ impl Action for GetChunkAfter {
                 ORDER BY id ASC
                 LIMIT ?
             ")?
-            .query_map(params![self.room.domain, self.room.name, WSnowflake(id.0), self.amount], row2msg)?
+            .query_map(params![self.room.domain, self.room.name, WSnowflake(id.0), self.amount], row2full_msg)?
             .collect::<rusqlite::Result<_>>()?
         } else {
             conn.prepare("
@@ -1152,7 +1775,7 @@ impl Action for GetChunkAfter {
                 ORDER BY id ASC
                 LIMIT ?
             ")?
-            .query_map(params![self.room.domain, self.room.name, self.amount], row2msg)?
+            .query_map(params![self.room.domain, self.room.name, self.amount], row2full_msg)?
             .collect::<rusqlite::Result<_>>()?
         };
 
@@ -1160,6 +1783,39 @@ impl Action for GetChunkAfter {
     }
 }
 
+impl Action for GetChunkAfterInRange {
+    type Output = Vec<Message>;
+    type Error = rusqlite::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        conn.prepare("
+            SELECT
+                id, parent, previous_edit_id, time, content, encryption_key_id, edited, deleted, truncated,
+                user_id, name, server_id, server_era, session_id, is_staff, is_manager, client_address, real_client_address
+            FROM euph_msgs
+            WHERE domain = :domain
+            AND room = :room
+            AND (:id IS NULL OR id > :id)
+            AND (:since IS NULL OR time >= :since)
+            AND (:until IS NULL OR time < :until)
+            ORDER BY id ASC
+            LIMIT :amount
+        ")?
+        .query_map(
+            named_params! {
+                ":domain": self.room.domain,
+                ":room": self.room.name,
+                ":id": self.id.map(|id| WSnowflake(id.0)),
+                ":since": self.since.map(WTime),
+                ":until": self.until.map(WTime),
+                ":amount": self.amount,
+            },
+            row2full_msg,
+        )?
+        .collect::<rusqlite::Result<_>>()
+    }
+}
+
 #[async_trait]
 impl MsgStore<SmallMessage> for EuphRoomVault {
     type Error = vault::tokio::Error<rusqlite::Error>;
@@ -1173,7 +1829,8 @@ impl MsgStore<SmallMessage> for EuphRoomVault {
     }
 
     async fn tree(&self, root_id: &MessageId) -> Result<Tree<SmallMessage>, Self::Error> {
-        self.tree(*root_id).await
+        self.tree(*root_id, Some(self.vault.vault.max_tree_size()))
+            .await
     }
 
     async fn first_root_id(&self) -> Result<Option<MessageId>, Self::Error> {
@@ -1235,4 +1892,238 @@ impl MsgStore<SmallMessage> for EuphRoomVault {
     async fn set_older_seen(&self, id: &MessageId, seen: bool) -> Result<(), Self::Error> {
         self.set_older_seen(*id, seen).await
     }
+
+    async fn folded_ids(&self) -> Result<HashSet<MessageId>, Self::Error> {
+        self.folded_ids().await
+    }
+
+    async fn set_folded(&self, id: &MessageId, folded: bool) -> Result<(), Self::Error> {
+        self.set_folded(*id, folded).await
+    }
+
+    async fn fold_all(&self) -> Result<(), Self::Error> {
+        self.fold_all().await
+    }
+
+    async fn unfold_all(&self) -> Result<(), Self::Error> {
+        self.unfold_all().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cove_config::Config;
+
+    use super::*;
+
+    fn msg(id: u64, time: i64) -> Message {
+        Message {
+            id: MessageId(Snowflake(id)),
+            parent: None,
+            previous_edit_id: None,
+            time: Time(time),
+            content: format!("Message {id}"),
+            encryption_key_id: None,
+            edited: None,
+            deleted: None,
+            truncated: false,
+            sender: SessionView {
+                id: UserId("agent:test".to_string()),
+                name: "test".to_string(),
+                server_id: "test".to_string(),
+                server_era: "test".to_string(),
+                session_id: SessionId("test".to_string()),
+                is_staff: false,
+                is_manager: false,
+                client_address: None,
+                real_client_address: None,
+            },
+        }
+    }
+
+    async fn room_vault() -> EuphRoomVault {
+        let vault = crate::vault::launch_in_memory(&Config::default()).unwrap();
+        let room = RoomIdentifier::new("example.com".to_string(), "test".to_string());
+        let room = vault.euph().room(room);
+        room.join(Time(0)).await.unwrap();
+        room
+    }
+
+    async fn seen(room: &EuphRoomVault, id: u64) -> bool {
+        room.msg(MessageId(Snowflake(id)))
+            .await
+            .unwrap()
+            .expect("message exists")
+            .seen
+    }
+
+    #[tokio::test]
+    async fn reinserting_msgs_keeps_seen() {
+        let room = room_vault().await;
+        room.add_msgs(vec![msg(1, 10), msg(2, 20)], None, None, false)
+            .await
+            .unwrap();
+        assert!(!seen(&room, 1).await);
+        assert!(!seen(&room, 2).await);
+
+        room.set_seen(MessageId(Snowflake(1)), true).await.unwrap();
+
+        let mut edited = msg(1, 10);
+        edited.content = "Edited".to_string();
+        room.add_msgs(vec![edited, msg(2, 20)], None, None, false)
+            .await
+            .unwrap();
+        assert!(seen(&room, 1).await);
+        assert!(!seen(&room, 2).await);
+
+        let updated = room.msg(MessageId(Snowflake(1))).await.unwrap().unwrap();
+        assert_eq!(updated.content, "Edited");
+    }
+
+    #[tokio::test]
+    async fn reinserting_own_msg_keeps_unseen() {
+        let room = room_vault().await;
+        let own_user_id = Some(UserId("agent:test".to_string()));
+        room.add_msgs(vec![msg(1, 10)], None, own_user_id.clone(), false)
+            .await
+            .unwrap();
+        assert!(seen(&room, 1).await);
+
+        room.set_seen(MessageId(Snowflake(1)), false).await.unwrap();
+        room.add_msgs(vec![msg(1, 10)], None, own_user_id, false)
+            .await
+            .unwrap();
+        assert!(!seen(&room, 1).await);
+    }
+
+    #[tokio::test]
+    async fn fold_all_folds_roots_and_unfold_all_clears() {
+        let room = room_vault().await;
+        let mut reply = msg(3, 30);
+        reply.parent = Some(MessageId(Snowflake(1)));
+        room.add_msgs(vec![msg(1, 10), msg(2, 20), reply], None, None, false)
+            .await
+            .unwrap();
+
+        room.fold_all().await.unwrap();
+        let folded = room.folded_ids().await.unwrap();
+        let roots = HashSet::from([MessageId(Snowflake(1)), MessageId(Snowflake(2))]);
+        assert_eq!(folded, roots);
+
+        room.unfold_all().await.unwrap();
+        assert!(room.folded_ids().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn total_unseen_msgs_count_skips_excluded_rooms() {
+        let room = room_vault().await;
+        room.add_msgs(vec![msg(1, 10), msg(2, 20)], None, None, false)
+            .await
+            .unwrap();
+        let euph = room.vault();
+
+        let total = euph.total_unseen_msgs_count(HashSet::new()).await.unwrap();
+        assert_eq!(total, 2);
+
+        let excluded = HashSet::from([room.room().clone()]);
+        let total = euph.total_unseen_msgs_count(excluded).await.unwrap();
+        assert_eq!(total, 0);
+    }
+
+    type Span = (Option<u64>, Option<u64>);
+
+    /// Adds spans one after another and returns the room's resulting spans.
+    struct AddSpans {
+        room: RoomIdentifier,
+        spans: Vec<Span>,
+    }
+
+    impl Action for AddSpans {
+        type Output = Vec<Span>;
+        type Error = rusqlite::Error;
+
+        fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+            let id = |id: Option<u64>| id.map(|id| MessageId(Snowflake(id)));
+            for (start, end) in self.spans {
+                let tx = conn.transaction()?;
+                add_span(&tx, &self.room, id(start), id(end))?;
+                tx.commit()?;
+            }
+
+            let mut result = conn
+                .prepare(
+                    "
+                    SELECT start, end
+                    FROM euph_spans
+                    WHERE domain = ?
+                    AND room = ?
+                    ",
+                )?
+                .query_map([&self.room.domain, &self.room.name], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<Span>>>()?;
+            result.sort_unstable();
+            Ok(result)
+        }
+    }
+
+    /// Add spans one after another to an otherwise empty room and return the
+    /// resulting spans.
+    async fn spans_after_adding(spans: &[Span]) -> Vec<Span> {
+        let room = room_vault().await;
+        let action = AddSpans {
+            room: room.room().clone(),
+            spans: spans.to_vec(),
+        };
+        room.vault()
+            .vault()
+            .tokio_vault
+            .execute(action)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn spans_sharing_a_boundary_are_merged() {
+        let spans = spans_after_adding(&[(Some(1), Some(3)), (Some(3), Some(5))]).await;
+        assert_eq!(spans, [(Some(1), Some(5))]);
+    }
+
+    #[tokio::test]
+    async fn adjacent_spans_are_kept_separate() {
+        let spans = spans_after_adding(&[(Some(1), Some(3)), (Some(4), Some(5))]).await;
+        assert_eq!(spans, [(Some(1), Some(3)), (Some(4), Some(5))]);
+    }
+
+    #[tokio::test]
+    async fn single_message_span_inside_span_is_absorbed() {
+        let spans = spans_after_adding(&[(Some(1), Some(5)), (Some(3), Some(3))]).await;
+        assert_eq!(spans, [(Some(1), Some(5))]);
+    }
+
+    #[tokio::test]
+    async fn single_message_span_on_boundary_is_absorbed() {
+        let spans = spans_after_adding(&[(Some(1), Some(5)), (Some(5), Some(5))]).await;
+        assert_eq!(spans, [(Some(1), Some(5))]);
+    }
+
+    #[tokio::test]
+    async fn span_from_beginning_merges_with_overlapping_spans() {
+        let spans =
+            spans_after_adding(&[(Some(2), Some(4)), (Some(6), Some(8)), (None, Some(6))]).await;
+        assert_eq!(spans, [(None, Some(8))]);
+    }
+
+    #[tokio::test]
+    async fn span_covering_multiple_spans_merges_them() {
+        let spans = spans_after_adding(&[
+            (Some(1), Some(2)),
+            (Some(4), Some(5)),
+            (Some(7), Some(8)),
+            (Some(2), Some(7)),
+        ])
+        .await;
+        assert_eq!(spans, [(Some(1), Some(8))]);
+    }
 }
@@ -1,7 +1,7 @@
 use rusqlite::Transaction;
 use vault::Migration;
 
-pub const MIGRATIONS: [Migration; 3] = [m1, m2, m3];
+pub const MIGRATIONS: [Migration; 6] = [m1, m2, m3, m4, m5, m6];
 
 fn eprint_status(nr: usize, total: usize) {
     eprintln!("Migrating vault from {} to {} (out of {total})", nr, nr + 1);
@@ -222,3 +222,68 @@ fn m3(tx: &mut Transaction<'_>, nr: usize, total: usize) -> rusqlite::Result<()>
 
     Ok(())
 }
+
+fn m4(tx: &mut Transaction<'_>, nr: usize, total: usize) -> rusqlite::Result<()> {
+    eprint_status(nr, total);
+    tx.execute_batch(
+        "
+        ALTER TABLE euph_rooms
+        ADD COLUMN read_mark INT;
+        ",
+    )
+}
+
+fn m5(tx: &mut Transaction<'_>, nr: usize, total: usize) -> rusqlite::Result<()> {
+    eprint_status(nr, total);
+    tx.execute_batch(
+        "
+        CREATE VIRTUAL TABLE euph_msgs_fts USING fts5 (
+            content,
+            content = 'euph_msgs',
+            content_rowid = 'rowid'
+        );
+
+        CREATE TRIGGER euph_msgs_fts_insert
+        AFTER INSERT ON euph_msgs
+        BEGIN
+            INSERT INTO euph_msgs_fts (rowid, content)
+            VALUES (new.rowid, new.content);
+        END;
+
+        CREATE TRIGGER euph_msgs_fts_delete
+        AFTER DELETE ON euph_msgs
+        BEGIN
+            INSERT INTO euph_msgs_fts (euph_msgs_fts, rowid, content)
+            VALUES ('delete', old.rowid, old.content);
+        END;
+
+        CREATE TRIGGER euph_msgs_fts_update
+        AFTER UPDATE OF content ON euph_msgs
+        BEGIN
+            INSERT INTO euph_msgs_fts (euph_msgs_fts, rowid, content)
+            VALUES ('delete', old.rowid, old.content);
+            INSERT INTO euph_msgs_fts (rowid, content)
+            VALUES (new.rowid, new.content);
+        END;
+
+        INSERT INTO euph_msgs_fts (euph_msgs_fts) VALUES ('rebuild');
+        ",
+    )
+}
+
+fn m6(tx: &mut Transaction<'_>, nr: usize, total: usize) -> rusqlite::Result<()> {
+    eprint_status(nr, total);
+    tx.execute_batch(
+        "
+        CREATE TABLE euph_folded (
+            domain TEXT NOT NULL,
+            room   TEXT NOT NULL,
+            id     INT  NOT NULL,
+
+            PRIMARY KEY (domain, room, id),
+            FOREIGN KEY (domain, room) REFERENCES euph_rooms (domain, room)
+                ON DELETE CASCADE
+        ) STRICT;
+        ",
+    )
+}
@@ -0,0 +1,100 @@
+//! Optional measurements of how long vault actions take.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::info;
+use parking_lot::Mutex;
+use rusqlite::Connection;
+use vault::Action;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ActionStats {
+    pub count: usize,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+fn format_ms(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[derive(Debug)]
+pub struct Timings {
+    /// Actions taking at least this long are logged.
+    threshold: Duration,
+    stats: Mutex<HashMap<&'static str, ActionStats>>,
+}
+
+impl Timings {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            stats: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn record(&self, name: &'static str, duration: Duration) {
+        if duration >= self.threshold {
+            info!("Slow vault action {name} took {}", format_ms(duration));
+        }
+
+        let mut stats = self.stats.lock();
+        let stats = stats.entry(name).or_default();
+        stats.count += 1;
+        stats.total += duration;
+        stats.max = stats.max.max(duration);
+    }
+
+    /// Stats of all actions run so far, sorted by total duration.
+    pub fn stats(&self) -> Vec<(&'static str, ActionStats)> {
+        let mut stats = self
+            .stats
+            .lock()
+            .iter()
+            .map(|(name, stats)| (*name, *stats))
+            .collect::<Vec<_>>();
+        stats.sort_unstable_by_key(|(_, stats)| stats.total);
+        stats.reverse();
+        stats
+    }
+
+    pub fn print_summary(&self) {
+        let stats = self.stats();
+        if stats.is_empty() {
+            return;
+        }
+
+        eprintln!("Vault action timings:");
+        for (name, stats) in stats {
+            let avg = stats.total / stats.count as u32;
+            eprintln!(
+                "  {name:<30} {:>8}x  total {:>10}  avg {:>8}  max {:>8}",
+                stats.count,
+                format_ms(stats.total),
+                format_ms(avg),
+                format_ms(stats.max),
+            );
+        }
+    }
+}
+
+/// Wraps an action, recording how long it takes to run.
+pub struct Timed<A> {
+    pub name: &'static str,
+    pub action: A,
+    pub timings: Arc<Timings>,
+}
+
+impl<A: Action> Action for Timed<A> {
+    type Output = A::Output;
+    type Error = A::Error;
+
+    fn run(self, conn: &mut Connection) -> Result<Self::Output, Self::Error> {
+        let start = Instant::now();
+        let result = self.action.run(conn);
+        self.timings.record(self.name, start.elapsed());
+        result
+    }
+}